@@ -584,10 +584,9 @@ impl<'a> Sequence<'a> {
             .map_err(ProtocolEncodingError::with_source)?;
 
         let mut arg_buf = BytesMut::with_capacity(8);
-        arguments.encode(&mut Encoder::new(
-            &inp_desc.as_query_arg_context(),
-            &mut arg_buf,
-        ))?;
+        let ctx = inp_desc.as_query_arg_context();
+        let mut encoder = Encoder::new(&ctx, &mut arg_buf);
+        arguments.encode(&mut encoder)?;
 
         self.send_messages(&[
             ClientMessage::Execute0(Execute0 {
@@ -643,10 +642,9 @@ impl<'a> Sequence<'a> {
             .map_err(ProtocolEncodingError::with_source)?;
 
         let mut arg_buf = BytesMut::with_capacity(8);
-        arguments.encode(&mut Encoder::new(
-            &inp_desc.as_query_arg_context(),
-            &mut arg_buf,
-        ))?;
+        let ctx = inp_desc.as_query_arg_context();
+        let mut encoder = Encoder::new(&ctx, &mut arg_buf);
+        arguments.encode(&mut encoder)?;
 
         self.send_messages(&[
             ClientMessage::Execute1(Execute1 {
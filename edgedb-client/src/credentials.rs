@@ -166,3 +166,109 @@ impl<'de> Deserialize<'de> for Credentials {
         }
     }
 }
+
+#[cfg(feature="edgedb-tokio")]
+impl From<TlsSecurity> for edgedb_tokio::TlsSecurity {
+    fn from(value: TlsSecurity) -> edgedb_tokio::TlsSecurity {
+        use edgedb_tokio::TlsSecurity as T;
+        match value {
+            TlsSecurity::Insecure => T::Insecure,
+            TlsSecurity::NoHostVerification => T::NoHostVerification,
+            TlsSecurity::Strict => T::Strict,
+            TlsSecurity::Default => T::Default,
+        }
+    }
+}
+
+#[cfg(feature="edgedb-tokio")]
+impl From<edgedb_tokio::TlsSecurity> for TlsSecurity {
+    fn from(value: edgedb_tokio::TlsSecurity) -> TlsSecurity {
+        use edgedb_tokio::TlsSecurity as T;
+        match value {
+            T::Insecure => TlsSecurity::Insecure,
+            T::NoHostVerification => TlsSecurity::NoHostVerification,
+            T::Strict => TlsSecurity::Strict,
+            T::Default => TlsSecurity::Default,
+        }
+    }
+}
+
+/// Note: cloud instance fields are not supported by `edgedb-tokio` and are
+/// dropped by this conversion.
+#[cfg(feature="edgedb-tokio")]
+impl From<&Credentials> for edgedb_tokio::Credentials {
+    fn from(creds: &Credentials) -> edgedb_tokio::Credentials {
+        let mut result = edgedb_tokio::Credentials::default();
+        result.host = creds.host.clone();
+        result.port = creds.port;
+        result.user = creds.user.clone();
+        result.password = creds.password.clone();
+        result.database = creds.database.clone();
+        result.tls_ca = creds.tls_ca.clone();
+        result.tls_security = creds.tls_security.into();
+        result
+    }
+}
+
+#[cfg(feature="edgedb-tokio")]
+impl From<&edgedb_tokio::Credentials> for Credentials {
+    fn from(creds: &edgedb_tokio::Credentials) -> Credentials {
+        Credentials {
+            host: creds.host.clone(),
+            port: creds.port,
+            user: creds.user.clone(),
+            password: creds.password.clone(),
+            database: creds.database.clone(),
+            tls_ca: creds.tls_ca.clone(),
+            tls_security: creds.tls_security.into(),
+            ..Default::default()
+        }
+    }
+}
+
+#[test]
+#[cfg(feature="edgedb-tokio")]
+fn tokio_conversions() {
+    use edgedb_tokio::TlsSecurity as T;
+    let modes = [
+        (TlsSecurity::Insecure, T::Insecure),
+        (TlsSecurity::NoHostVerification, T::NoHostVerification),
+        (TlsSecurity::Strict, T::Strict),
+        (TlsSecurity::Default, T::Default),
+    ];
+    for &(security, tokio_security) in &modes {
+        assert_eq!(T::from(security), tokio_security);
+        assert_eq!(TlsSecurity::from(tokio_security), security);
+
+        let creds = Credentials {
+            host: Some("example.org".into()),
+            port: 10702,
+            user: "test3n".into(),
+            password: Some("secret".into()),
+            database: Some("db".into()),
+            tls_ca: Some("-----BEGIN CERTIFICATE-----".into()),
+            tls_security: security,
+            cloud_instance_id: Some("org/inst".into()),
+            ..Default::default()
+        };
+        let tokio = edgedb_tokio::Credentials::from(&creds);
+        assert_eq!(tokio.host.as_deref(), Some("example.org"));
+        assert_eq!(tokio.port, 10702);
+        assert_eq!(tokio.user, "test3n");
+        assert_eq!(tokio.password.as_deref(), Some("secret"));
+        assert_eq!(tokio.database.as_deref(), Some("db"));
+        assert_eq!(tokio.tls_ca, creds.tls_ca);
+        assert_eq!(tokio.tls_security, tokio_security);
+
+        let back = Credentials::from(&tokio);
+        assert_eq!(back.host, creds.host);
+        assert_eq!(back.port, creds.port);
+        assert_eq!(back.user, creds.user);
+        assert_eq!(back.password, creds.password);
+        assert_eq!(back.database, creds.database);
+        assert_eq!(back.tls_ca, creds.tls_ca);
+        assert_eq!(back.tls_security, security);
+        // not supported by edgedb-tokio
+        assert_eq!(back.cloud_instance_id, None);
+    }
+}
@@ -1,3 +1,11 @@
+//! EdgeDB client based on async-std
+//!
+//! # Features
+//!
+//! * `derive` (default) -- reexports `Queryable` and `ScalarArg` derives
+//! * `edgedb-tokio` -- conversions of [`Credentials`] and [`TlsSecurity`]
+//!   from and to their `edgedb-tokio` counterparts
+//! * `unstable` -- exposes internal modules (used by the CLI)
 #[warn(missing_docs)]
 
 mod builder;
@@ -33,6 +41,7 @@ mod server_params;
 pub mod errors;
 
 pub use builder::{Builder, Config};
+pub use credentials::{Credentials, TlsSecurity};
 pub use pool::Client;
 pub use errors::{Error};
 pub use traits::{Executor, ExecuteResult};
@@ -45,7 +54,7 @@ pub use edgedb_protocol::query_arg::{QueryArg, QueryArgs};
 pub use edgedb_protocol::{QueryResult};
 
 #[cfg(feature="derive")]
-pub use edgedb_derive::Queryable;
+pub use edgedb_derive::{Queryable, ScalarArg};
 
 mod pool;
 
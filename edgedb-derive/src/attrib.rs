@@ -1,25 +1,25 @@
+use proc_macro2::Span;
 use syn::punctuated::Punctuated;
 use syn::parse::{Parse, ParseStream};
 
-#[derive(Debug)]
 enum FieldAttr {
-    Json,
+    Json(kw::json),
 }
 
-#[derive(Debug)]
 enum ContainerAttr {
-    Json,
+    Json(kw::json),
 }
 
 struct FieldAttrList(pub Punctuated<FieldAttr, syn::Token![,]>);
 struct ContainerAttrList(pub Punctuated<ContainerAttr, syn::Token![,]>);
 
+/// Field attributes, each one is a span of the attribute if it's set
 pub struct FieldAttrs {
-    pub json: bool,
+    pub json: Option<Span>,
 }
 
 pub struct ContainerAttrs {
-    pub json: bool,
+    pub json: Option<Span>,
 }
 
 mod kw {
@@ -30,8 +30,7 @@ impl Parse for FieldAttr {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let lookahead = input.lookahead1();
         if lookahead.peek(kw::json) {
-            let _ident: syn::Ident = input.parse()?;
-            Ok(FieldAttr::Json)
+            Ok(FieldAttr::Json(input.parse()?))
         } else {
             Err(lookahead.error())
         }
@@ -42,8 +41,7 @@ impl Parse for ContainerAttr {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let lookahead = input.lookahead1();
         if lookahead.peek(kw::json) {
-            let _ident: syn::Ident = input.parse()?;
-            Ok(ContainerAttr::Json)
+            Ok(ContainerAttr::Json(input.parse()?))
         } else {
             Err(lookahead.error())
         }
@@ -65,7 +63,7 @@ impl Parse for FieldAttrList {
 impl FieldAttrs {
     fn default() -> FieldAttrs{
         FieldAttrs {
-            json: false,
+            json: None,
         }
     }
     pub fn from_syn(attrs: &[syn::Attribute]) -> syn::Result<FieldAttrs> {
@@ -77,7 +75,9 @@ impl FieldAttrs {
                 let chunk: FieldAttrList = attr.parse_args()?;
                 for item in chunk.0 {
                     match item {
-                        FieldAttr::Json => res.json = true,
+                        FieldAttr::Json(kw) => {
+                            set_flag(&mut res.json, kw.span, "json")?;
+                        }
                     }
                 }
             }
@@ -89,7 +89,7 @@ impl FieldAttrs {
 impl ContainerAttrs {
     fn default() -> ContainerAttrs{
         ContainerAttrs {
-            json: false,
+            json: None,
         }
     }
     pub fn from_syn(attrs: &[syn::Attribute]) -> syn::Result<ContainerAttrs> {
@@ -101,7 +101,9 @@ impl ContainerAttrs {
                 let chunk: ContainerAttrList = attr.parse_args()?;
                 for item in chunk.0 {
                     match item {
-                        ContainerAttr::Json => res.json = true,
+                        ContainerAttr::Json(kw) => {
+                            set_flag(&mut res.json, kw.span, "json")?;
+                        }
                     }
                 }
             }
@@ -109,3 +111,14 @@ impl ContainerAttrs {
         Ok(res)
     }
 }
+
+fn set_flag(flag: &mut Option<Span>, span: Span, name: &str)
+    -> syn::Result<()>
+{
+    if flag.is_some() {
+        return Err(syn::Error::new(span,
+            format!("duplicate `{}` attribute", name)));
+    }
+    *flag = Some(span);
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+
+pub fn derive_scalar_arg(item: &syn::Item) -> syn::Result<TokenStream> {
+    let e = match item {
+        syn::Item::Enum(e) => e,
+        _ => {
+            return Err(syn::Error::new_spanned(item,
+                "can only derive ScalarArg for enums"));
+        }
+    };
+    let name = &e.ident;
+    let (impl_generics, ty_generics, where_clause) =
+        e.generics.split_for_impl();
+    let mut variants = Vec::with_capacity(e.variants.len());
+    let mut members = Vec::with_capacity(e.variants.len());
+    for variant in &e.variants {
+        if !matches!(variant.fields, syn::Fields::Unit) {
+            return Err(syn::Error::new_spanned(&variant.fields,
+                "only unit variants can be converted into EdgeDB enum"));
+        }
+        let ident = &variant.ident;
+        members.push(syn::LitStr::new(&ident.to_string(), ident.span()));
+        variants.push(ident);
+    }
+    let expanded = quote! {
+        impl #impl_generics ::edgedb_protocol::query_arg::ScalarArg
+            for #name #ty_generics #where_clause {
+            fn encode(&self, encoder: &mut ::edgedb_protocol::query_arg::Encoder)
+                -> Result<(), ::edgedb_protocol::query_arg::Error>
+            {
+                let member = match self {
+                    #(
+                        #name::#variants => #members,
+                    )*
+                };
+                ::edgedb_protocol::query_arg::ScalarArg::encode(
+                    &::edgedb_protocol::codec::EnumValue::from(member),
+                    encoder)
+            }
+            fn check_descriptor(
+                ctx: &::edgedb_protocol::query_arg::DescriptorContext,
+                type_pos: ::edgedb_protocol::descriptors::TypePos)
+                -> Result<(), ::edgedb_protocol::query_arg::Error>
+            {
+                <::edgedb_protocol::codec::EnumValue as
+                    ::edgedb_protocol::query_arg::ScalarArg>
+                    ::check_descriptor(ctx, type_pos)
+            }
+        }
+    };
+    Ok(expanded)
+}
@@ -1,10 +1,19 @@
 use proc_macro2::TokenStream;
 use quote::quote;
 
+use crate::attrib::FieldAttrs;
+
 
 pub fn derive(item: &syn::Item) -> syn::Result<TokenStream> {
     let (name, impl_generics, ty_generics) = match item {
         syn::Item::Struct(s) => {
+            for field in &s.fields {
+                if let Some(span) = FieldAttrs::from_syn(&field.attrs)?.json {
+                    return Err(syn::Error::new(span,
+                        "field attributes can't be used when the whole \
+                         struct is decoded from JSON"));
+                }
+            }
             let (impl_generics, ty_generics, _) = s.generics.split_for_impl();
             (&s.ident, impl_generics, ty_generics)
         }
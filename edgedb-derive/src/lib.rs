@@ -1,9 +1,11 @@
 extern crate proc_macro;
 
 use proc_macro::TokenStream;
+use quote::quote;
 use syn::{self, parse_macro_input};
 
 mod attrib;
+mod enums;
 mod json;
 mod shape;
 
@@ -22,6 +24,9 @@ mod shape;
 /// }
 /// ```
 ///
+/// Free objects (e.g. `SELECT { first_name := "John", age := 42 }`) can
+/// be decoded into such structures too, as they lack only the implicit `id`.
+///
 /// # Field attributes
 ///
 /// ## JSON
@@ -57,6 +62,33 @@ mod shape;
 ///     field2: u32,
 /// }
 /// ```
+///
+/// Field attributes can't be used on such structure, as the whole value is
+/// decoded by `serde_json`. Enums are supported only in this mode.
+///
+/// # Compile errors
+///
+/// Errors point to the offending part of the definition:
+///
+/// * unknown attributes and attributes that are specified twice point to
+///   the attribute;
+/// * field types that can never be decoded, such as references (`&str`),
+///   raw pointers, trait objects and functions, point to the type, even if
+///   nested (e.g. `Vec<&str>`);
+/// * field types that don't implement `Queryable` produce a trait error
+///   pointing to the type of the field (the wording of the error is up to
+///   the compiler);
+/// * tuple structs, unit structs, and enums without `#[edgedb(json)]`
+///   point to the name of the type.
+///
+/// ```rust,compile_fail
+/// struct Unknown;
+///
+/// #[derive(edgedb_client::Queryable)]
+/// struct User {
+///     data: Unknown,
+/// }
+/// ```
 #[proc_macro_derive(Queryable, attributes(edgedb))]
 pub fn edgedb_queryable(input: TokenStream) -> TokenStream {
     let s = parse_macro_input!(input as syn::Item);
@@ -66,27 +98,54 @@ pub fn edgedb_queryable(input: TokenStream) -> TokenStream {
     }
 }
 
+/// Derive macro that allows enums to be used as query arguments
+///
+/// Each variant is passed as the member of EdgeDB enum with the same name,
+/// so only enums with unit variants are supported.
+///
+/// ```rust
+/// #[derive(edgedb_client::ScalarArg)]
+/// enum Color {
+///     Red,
+///     Green,
+/// }
+/// ```
+///
+/// Membership of the value is checked by the server.
+#[proc_macro_derive(ScalarArg)]
+pub fn edgedb_scalar_arg(input: TokenStream) -> TokenStream {
+    let s = parse_macro_input!(input as syn::Item);
+    match enums::derive_scalar_arg(&s) {
+        Ok(stream) => stream.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
 fn derive(item: &syn::Item) -> syn::Result<proc_macro2::TokenStream> {
     let attrs = match item {
         syn::Item::Struct(s) => &s.attrs,
         syn::Item::Enum(e) => &e.attrs,
-        _ => {
-            return Err(syn::Error::new_spanned(item,
-                "can only derive Queryable for structs and enums"
-            ));
-        }
+        _ => return Err(unsupported_item(item)),
     };
     let attrs = attrib::ContainerAttrs::from_syn(&attrs)?;
-    if attrs.json {
+    if attrs.json.is_some() {
         json::derive(item)
     } else {
         match item {
             syn::Item::Struct(s) => shape::derive_struct(s),
-            _ => {
-                return Err(syn::Error::new_spanned(item,
-                    "can only derive Queryable for a struct in non-JSON mode"
-                ));
+            syn::Item::Enum(e) => {
+                let (token, name) = (&e.enum_token, &e.ident);
+                Err(syn::Error::new_spanned(quote!(#token #name),
+                    "can only derive Queryable for a struct in non-JSON \
+                     mode, use `#[edgedb(json)]` to decode the enum from JSON"
+                ))
             }
+            _ => Err(unsupported_item(item)),
         }
     }
 }
+
+fn unsupported_item(item: &syn::Item) -> syn::Error {
+    syn::Error::new_spanned(item,
+        "can only derive Queryable for structs and enums")
+}
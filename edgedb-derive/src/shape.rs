@@ -1,5 +1,6 @@
 use proc_macro2::TokenStream;
-use quote::quote;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
 
 use crate::attrib::FieldAttrs;
 
@@ -18,6 +19,7 @@ pub fn derive_struct(s: &syn::ItemStruct) -> syn::Result<TokenStream> {
             let mut fields = Vec::with_capacity(named.named.len());
             for field in &named.named {
                 let attrs = FieldAttrs::from_syn(&field.attrs)?;
+                check_type(&field.ty)?;
                 let name = field.ident.clone().unwrap();
                 fields.push(Field {
                     str_name: syn::LitStr::new(&name.to_string(), name.span()),
@@ -28,17 +30,21 @@ pub fn derive_struct(s: &syn::ItemStruct) -> syn::Result<TokenStream> {
             }
             fields
         }
-        _ => {
+        syn::Fields::Unnamed(_) => {
             return Err(syn::Error::new_spanned(
                 &s.fields, "only named fields are supported"));
         }
+        syn::Fields::Unit => {
+            return Err(syn::Error::new_spanned(
+                &s.ident, "unit structs are not supported, \
+                           use a struct with named fields"));
+        }
     };
     let fieldname = fields.iter()
         .map(|f| f.name.clone()).collect::<Vec<_>>();
     let has_id = fieldname.iter()
         .find(|x| x.to_string() == "id").is_some();
-    let base_fields = fields.len()
-        + if has_id { 0 } else { 1 };
+    let base_fields = fields.len();
     let type_id_block = Some(quote! {
         if decoder.has_implicit_tid {
             elements.skip_element()?;
@@ -49,11 +55,27 @@ pub fn derive_struct(s: &syn::ItemStruct) -> syn::Result<TokenStream> {
             elements.skip_element()?;
         }
     });
+    // free objects have no implicit id, so it's skipped only if present
+    let new_object = if has_id {
+        quote! {
+            let mut elements =
+                ::edgedb_protocol::serialization::decode::DecodeTupleLike
+                ::new_object(buf, nfields)?;
+        }
+    } else {
+        quote! {
+            let (mut elements, has_implicit_id) =
+                ::edgedb_protocol::serialization::decode::DecodeTupleLike
+                ::new_object_implicit_id(buf, nfields)?;
+        }
+    };
     let id_block = if has_id {
         None
     } else {
         Some(quote! {
-            elements.skip_element()?;
+            if has_implicit_id {
+                elements.skip_element()?;
+            }
         })
     };
     let type_id_check = Some(quote! {
@@ -76,27 +98,31 @@ pub fn derive_struct(s: &syn::ItemStruct) -> syn::Result<TokenStream> {
         None
     } else {
         Some(quote! {
-            if(!shape.elements[idx].flag_implicit) {
-                return Err(ctx.expected("implicit id"));
+            if shape.elements.get(idx).map_or(false, |el| el.flag_implicit) {
+                idx += 1;
             }
-            idx += 1;
         })
     };
     let field_decoders = fields.iter().map(|field| {
         let ref fieldname = field.name;
-        if field.attrs.json {
+        let ref fieldtype = field.ty;
+        if field.attrs.json.is_some() {
+            let decode = quote_spanned!{fieldtype.span()=>
+                let #fieldname: #fieldtype =
+                    ::serde_json::from_str(#fieldname.as_ref())
+                    .map_err(::edgedb_protocol::errors::decode_error)?;
+            };
             quote!{
                 let #fieldname: ::edgedb_protocol::model::Json =
                     <::edgedb_protocol::model::Json as
                         ::edgedb_protocol::queryable::Queryable>
                     ::decode_optional(decoder, elements.read()?)?;
-                let #fieldname = ::serde_json::from_str(#fieldname.as_ref())
-                    .map_err(::edgedb_protocol::errors::decode_error)?;
+                #decode
             }
         } else {
-            quote!{
+            quote_spanned!{fieldtype.span()=>
                 let #fieldname =
-                    ::edgedb_protocol::queryable::Queryable
+                    <#fieldtype as ::edgedb_protocol::queryable::Queryable>
                     ::decode_optional(decoder, elements.read()?)?;
             }
         }
@@ -111,14 +137,15 @@ pub fn derive_struct(s: &syn::ItemStruct) -> syn::Result<TokenStream> {
             idx += 1;
         };
         let ref fieldtype = field.ty;
-        if field.attrs.json {
+        if field.attrs.json.is_some() {
             result.extend(quote!{
                 <::edgedb_protocol::model::Json as
                     ::edgedb_protocol::queryable::Queryable>
                     ::check_descriptor(ctx, el.type_pos)?;
             });
         } else {
-            result.extend(quote!{
+            // trait errors point to the type of the field
+            result.extend(quote_spanned!{fieldtype.span()=>
                 <#fieldtype as ::edgedb_protocol::queryable::Queryable>
                     ::check_descriptor(ctx, el.type_pos)?;
             });
@@ -135,9 +162,7 @@ pub fn derive_struct(s: &syn::ItemStruct) -> syn::Result<TokenStream> {
                 let nfields = #base_fields
                     + if decoder.has_implicit_tid { 1 } else { 0 }
                     + if decoder.has_implicit_tname { 1 } else { 0 };
-                let mut elements =
-                    ::edgedb_protocol::serialization::decode::DecodeTupleLike
-                    ::new_object(buf, nfields)?;
+                #new_object
 
                 #type_id_block
                 #type_name_block
@@ -181,3 +206,38 @@ pub fn derive_struct(s: &syn::ItemStruct) -> syn::Result<TokenStream> {
     };
     Ok(expanded)
 }
+
+/// Rejects types that can never be decoded, so the error points to the
+/// field instead of the generated code
+fn check_type(ty: &syn::Type) -> syn::Result<()> {
+    use syn::Type::*;
+
+    match ty {
+        Reference(_) => Err(syn::Error::new_spanned(ty,
+            "references are not supported, use an owned type instead \
+             (e.g. `String` instead of `&str`)")),
+        Ptr(_) | BareFn(_) | TraitObject(_) | ImplTrait(_) | Infer(_)
+        | Never(_) => Err(syn::Error::new_spanned(ty,
+            "unsupported field type")),
+        Paren(p) => check_type(&p.elem),
+        Group(g) => check_type(&g.elem),
+        Array(a) => check_type(&a.elem),
+        Slice(s) => check_type(&s.elem),
+        Tuple(t) => t.elems.iter().try_for_each(check_type),
+        Path(p) => {
+            use syn::{PathArguments, GenericArgument};
+
+            for seg in &p.path.segments {
+                if let PathArguments::AngleBracketed(args) = &seg.arguments {
+                    for arg in &args.args {
+                        if let GenericArgument::Type(ty) = arg {
+                            check_type(ty)?;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,10 @@
+use edgedb_derive::Queryable;
+
+#[derive(Queryable)]
+struct User {
+    #[edgedb(json, json)]
+    data: String,
+}
+
+fn main() {
+}
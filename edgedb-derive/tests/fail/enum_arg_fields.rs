@@ -0,0 +1,10 @@
+use edgedb_derive::ScalarArg;
+
+#[derive(ScalarArg)]
+enum Shape {
+    Circle,
+    Square(u32),
+}
+
+fn main() {
+}
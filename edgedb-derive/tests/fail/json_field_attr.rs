@@ -0,0 +1,12 @@
+use edgedb_derive::Queryable;
+
+#[derive(Queryable, serde::Deserialize)]
+#[edgedb(json)]
+struct User {
+    name: String,
+    #[edgedb(json)]
+    data: String,
+}
+
+fn main() {
+}
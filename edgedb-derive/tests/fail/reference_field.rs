@@ -0,0 +1,10 @@
+use edgedb_derive::Queryable;
+
+#[derive(Queryable)]
+struct User {
+    id: i64,
+    names: Vec<&'static str>,
+}
+
+fn main() {
+}
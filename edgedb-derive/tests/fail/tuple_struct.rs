@@ -0,0 +1,7 @@
+use edgedb_derive::Queryable;
+
+#[derive(Queryable)]
+struct Point(i64, i64);
+
+fn main() {
+}
@@ -0,0 +1,10 @@
+use edgedb_derive::Queryable;
+
+#[derive(Queryable)]
+struct User {
+    #[edgedb(jsn)]
+    data: String,
+}
+
+fn main() {
+}
@@ -43,3 +43,17 @@ fn decode_old() {
     });
 }
 
+
+#[test]
+fn decode_free_object() {
+    // SELECT { name := ..., extending := ..., kind := ... }
+    let data = b"\0\0\0\x03\
+        \0\0\0\x19\0\0\0\x0fcal::local_date\
+        \0\0\0\x19\0\0\0\x0estd::anyscalar\0\0\0\x19\0\0\0\x06normal";
+    let res = ScalarType::decode(&Decoder::default(), data);
+    assert_eq!(res.unwrap(), ScalarType {
+        name: "cal::local_date".into(),
+        extending: "std::anyscalar".into(),
+        kind: "normal".into(),
+    });
+}
@@ -0,0 +1,58 @@
+use bytes::BytesMut;
+
+use edgedb_derive::ScalarArg;
+use edgedb_protocol::common::Cardinality;
+use edgedb_protocol::descriptors::{Descriptor, TypePos, InputTypedesc};
+use edgedb_protocol::descriptors::BaseScalarTypeDescriptor;
+use edgedb_protocol::descriptors::{ObjectShapeDescriptor, ShapeElement};
+use edgedb_protocol::descriptors::EnumerationTypeDescriptor;
+use edgedb_protocol::features::ProtocolVersion;
+use edgedb_protocol::query_arg::{QueryArgs, Encoder, Error};
+
+
+#[derive(ScalarArg)]
+enum Status {
+    Waiting,
+    Done,
+}
+
+fn encode(element_type: Descriptor, args: &impl QueryArgs)
+    -> Result<BytesMut, Error>
+{
+    let descriptors = vec![
+        element_type,
+        Descriptor::ObjectShape(ObjectShapeDescriptor {
+            id: "00000000-0000-0000-0000-0000000000AA".parse().unwrap(),
+            elements: vec![ShapeElement {
+                flag_implicit: false,
+                flag_link_property: false,
+                flag_link: false,
+                cardinality: Some(Cardinality::One),
+                name: "0".into(),
+                type_pos: TypePos(0),
+            }],
+        }),
+    ];
+    let desc = InputTypedesc::new(ProtocolVersion::current(), descriptors,
+        "00000000-0000-0000-0000-0000000000AA".parse().unwrap()).unwrap();
+    let mut buf = BytesMut::new();
+    args.encode(&mut Encoder::new(&desc.as_query_arg_context(), &mut buf))?;
+    Ok(buf)
+}
+
+#[test]
+fn enum_arg() {
+    let status = Descriptor::Enumeration(EnumerationTypeDescriptor {
+        id: "ac5dc6a4-2656-11ec-ae6d-73b11a2ab7d4".parse().unwrap(),
+        members: vec!["Waiting".into(), "Done".into()],
+    });
+    assert_eq!(&encode(status.clone(), &(Status::Done,)).unwrap()[..],
+        b"\0\0\0\x01\0\0\0\0\0\0\0\x04Done");
+    assert_eq!(&encode(status, &(Some(Status::Waiting),)).unwrap()[..],
+        b"\0\0\0\x01\0\0\0\0\0\0\0\x07Waiting");
+
+    let str_type = Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+        id: "00000000-0000-0000-0000-000000000101".parse().unwrap(),
+    });
+    assert!(encode(str_type, &(Status::Done,)).is_err());
+}
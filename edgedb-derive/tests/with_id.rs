@@ -0,0 +1,98 @@
+use std::error::Error;
+
+use bytes::Bytes;
+use edgedb_derive::Queryable;
+use edgedb_protocol::common::Cardinality;
+use edgedb_protocol::descriptors::{Descriptor, TypePos, OutputTypedesc};
+use edgedb_protocol::descriptors::{ObjectShapeDescriptor, ShapeElement};
+use edgedb_protocol::descriptors::BaseScalarTypeDescriptor;
+use edgedb_protocol::features::ProtocolVersion;
+use edgedb_protocol::model::Uuid;
+use edgedb_protocol::{QueryResult, WithId};
+
+#[derive(Queryable, Debug, PartialEq)]
+struct User {
+    id: Uuid,
+    name: String,
+}
+
+#[derive(Queryable, Debug, PartialEq)]
+struct Name {
+    name: String,
+}
+
+fn element(name: &str, pos: u16, implicit: bool) -> ShapeElement {
+    ShapeElement {
+        flag_implicit: implicit,
+        flag_link_property: false,
+        flag_link: false,
+        cardinality: Some(Cardinality::One),
+        name: name.into(),
+        type_pos: TypePos(pos),
+    }
+}
+
+fn typedesc(elements: Vec<ShapeElement>)
+    -> Result<OutputTypedesc, Box<dyn Error>>
+{
+    Ok(OutputTypedesc::new(ProtocolVersion::current(), vec![
+        // std::uuid
+        Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+            id: "00000000-0000-0000-0000-000000000100".parse()?,
+        }),
+        // std::str
+        Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+            id: "00000000-0000-0000-0000-000000000101".parse()?,
+        }),
+        Descriptor::ObjectShape(ObjectShapeDescriptor {
+            id: "00000000-0000-0000-0000-0000000000AA".parse()?,
+            elements,
+        }),
+    ], "00000000-0000-0000-0000-0000000000AA".parse()?)?)
+}
+
+fn data() -> Bytes {
+    Bytes::from_static(b"\0\0\0\x02\
+        \0\0\0\0\0\0\0\x10\
+        \x01\x02\x03\x04\x05\x06\x07\x08\
+        \x09\x0a\x0b\x0c\x0d\x0e\x0f\x10\
+        \0\0\0\0\0\0\0\x04john")
+}
+
+#[test]
+fn explicit_id() -> Result<(), Box<dyn Error>> {
+    // SELECT User { id, name }
+    let out = typedesc(vec![
+        element("id", 0, false),
+        element("name", 1, false),
+    ])?;
+    let ctx = out.as_queryable_context();
+    let mut state = WithId::<User>::prepare(&ctx, TypePos(2))?;
+    let res = WithId::<User>::decode(&mut state, &data())?;
+    let id = "01020304-0506-0708-090a-0b0c0d0e0f10".parse::<Uuid>()?;
+    assert_eq!(res, WithId {
+        id,
+        value: User { id, name: "john".into() },
+    });
+
+    // explicitly selected `id` must be present in the structure
+    assert!(WithId::<Name>::prepare(&ctx, TypePos(2)).is_err());
+    Ok(())
+}
+
+#[test]
+fn implicit_id() -> Result<(), Box<dyn Error>> {
+    // SELECT User { name } (with implicit id)
+    let out = typedesc(vec![
+        element("id", 0, true),
+        element("name", 1, false),
+    ])?;
+    let ctx = out.as_queryable_context();
+    let mut state = WithId::<Name>::prepare(&ctx, TypePos(2))?;
+    let res = WithId::<Name>::decode(&mut state, &data())?;
+    assert_eq!(res, WithId {
+        id: "01020304-0506-0708-090a-0b0c0d0e0f10".parse()?,
+        value: Name { name: "john".into() },
+    });
+    Ok(())
+}
@@ -140,6 +140,14 @@ pub struct Json;
 #[derive(Debug)]
 pub struct Nothing;
 
+/// Codec for base scalars that are not known to this library
+///
+/// Data is passed as is, see [`build_codec_with_fallback`].
+#[derive(Debug)]
+pub struct Unknown {
+    type_id: UuidVal,
+}
+
 #[derive(Debug)]
 pub struct Object {
     shape: ObjectShape,
@@ -193,6 +201,7 @@ pub struct Enum {
 
 struct CodecBuilder<'a> {
     descriptors: &'a [Descriptor],
+    unknown_scalars: bool,
 }
 
 impl ObjectShape {
@@ -220,6 +229,14 @@ impl<'a> CodecBuilder<'a> {
         use Descriptor as D;
         if let Some(item) = self.descriptors.get(pos.0 as usize) {
             match item {
+                D::BaseScalar(base) if self.unknown_scalars => {
+                    scalar_codec(&base.id).or_else(|e| match e {
+                        CodecError::UndefinedBaseScalar { .. } => {
+                            Ok(Arc::new(Unknown { type_id: base.id }))
+                        }
+                        e => Err(e),
+                    })
+                }
                 D::BaseScalar(base) => scalar_codec(&base.id),
                 D::Set(d) => Ok(Arc::new(Set::build(d, self)?)),
                 D::ObjectShape(d) => Ok(Arc::new(Object::build(d, self)?)),
@@ -256,39 +273,96 @@ pub fn build_codec(root_pos: Option<TypePos>,
     descriptors: &[Descriptor])
     -> Result<Arc<dyn Codec>, CodecError>
 {
-    let dec = CodecBuilder { descriptors };
+    let dec = CodecBuilder { descriptors, unknown_scalars: false };
     match root_pos {
         Some(pos) => dec.build(pos),
         None => Ok(Arc::new(Nothing {})),
     }
 }
 
-pub fn scalar_codec(uuid: &UuidVal) -> Result<Arc<dyn Codec>, CodecError> {
-    match *uuid {
-        STD_UUID => Ok(Arc::new(Uuid {})),
-        STD_STR => Ok(Arc::new(Str {})),
-        STD_BYTES => Ok(Arc::new(Bytes {})),
-        STD_INT16 => Ok(Arc::new(Int16 {})),
-        STD_INT32 => Ok(Arc::new(Int32 {})),
-        STD_INT64 => Ok(Arc::new(Int64 {})),
-        STD_FLOAT32 => Ok(Arc::new(Float32 {})),
-        STD_FLOAT64 => Ok(Arc::new(Float64 {})),
-        STD_DECIMAL => Ok(Arc::new(Decimal {})),
-        STD_BOOL => Ok(Arc::new(Bool {})),
-        STD_DATETIME => Ok(Arc::new(Datetime {})),
-        CAL_LOCAL_DATETIME => Ok(Arc::new(LocalDatetime {})),
-        CAL_LOCAL_DATE => Ok(Arc::new(LocalDate {})),
-        CAL_LOCAL_TIME => Ok(Arc::new(LocalTime {})),
-        STD_DURATION => Ok(Arc::new(Duration {})),
-        CAL_RELATIVE_DURATION => Ok(Arc::new(RelativeDuration {})),
-        CAL_DATE_DURATION => Ok(Arc::new(DateDuration {})),
-        STD_JSON => Ok(Arc::new(Json {})),
-        STD_BIGINT => Ok(Arc::new(BigInt {})),
-        CFG_MEMORY => Ok(Arc::new(ConfigMemory {})),
-        _ => return errors::UndefinedBaseScalar { uuid: uuid.clone() }.fail()?,
+/// Same as [`build_codec`] but tolerates unknown base scalar types
+///
+/// Values of scalar types unknown to this library (for example, types
+/// added by newer server versions or extensions) are decoded as
+/// [`Value::Unknown`] containing the type id and the raw data, instead of
+/// failing to build a codec. Such values can be encoded back verbatim.
+pub fn build_codec_with_fallback(root_pos: Option<TypePos>,
+    descriptors: &[Descriptor])
+    -> Result<Arc<dyn Codec>, CodecError>
+{
+    let dec = CodecBuilder { descriptors, unknown_scalars: true };
+    match root_pos {
+        Some(pos) => dec.build(pos),
+        None => Ok(Arc::new(Nothing {})),
+    }
+}
+
+macro_rules! scalar_types {
+    ($($id:ident => $name:expr, $rust_type:expr, $codec:ident;)*) => {
+        /// Returns EdgeQL name of the base scalar type with specified uuid
+        pub fn scalar_name(uuid: &UuidVal) -> Option<&'static str> {
+            match *uuid {
+                $($id => Some($name),)*
+                _ => None,
+            }
+        }
+
+        /// Returns Rust type the base scalar type with specified uuid is
+        /// decoded into
+        ///
+        /// Types defined in this crate are returned with full path, e.g.
+        /// `edgedb_protocol::model::Datetime`.
+        pub fn scalar_rust_type(uuid: &UuidVal) -> Option<&'static str> {
+            match *uuid {
+                $($id => Some($rust_type),)*
+                _ => None,
+            }
+        }
+
+        pub fn scalar_codec(uuid: &UuidVal)
+            -> Result<Arc<dyn Codec>, CodecError>
+        {
+            match *uuid {
+                $($id => Ok(Arc::new($codec {})),)*
+                _ => errors::UndefinedBaseScalar { uuid: uuid.clone() }
+                    .fail()?,
+            }
+        }
     }
 }
 
+scalar_types! {
+    STD_UUID => "std::uuid", "edgedb_protocol::model::Uuid", Uuid;
+    STD_STR => "std::str", "String", Str;
+    STD_BYTES => "std::bytes", "Vec<u8>", Bytes;
+    STD_INT16 => "std::int16", "i16", Int16;
+    STD_INT32 => "std::int32", "i32", Int32;
+    STD_INT64 => "std::int64", "i64", Int64;
+    STD_FLOAT32 => "std::float32", "f32", Float32;
+    STD_FLOAT64 => "std::float64", "f64", Float64;
+    STD_DECIMAL => "std::decimal", "edgedb_protocol::model::Decimal",
+        Decimal;
+    STD_BOOL => "std::bool", "bool", Bool;
+    STD_DATETIME => "std::datetime", "edgedb_protocol::model::Datetime",
+        Datetime;
+    CAL_LOCAL_DATETIME => "cal::local_datetime",
+        "edgedb_protocol::model::LocalDatetime", LocalDatetime;
+    CAL_LOCAL_DATE => "cal::local_date",
+        "edgedb_protocol::model::LocalDate", LocalDate;
+    CAL_LOCAL_TIME => "cal::local_time",
+        "edgedb_protocol::model::LocalTime", LocalTime;
+    STD_DURATION => "std::duration", "edgedb_protocol::model::Duration",
+        Duration;
+    CAL_RELATIVE_DURATION => "cal::relative_duration",
+        "edgedb_protocol::model::RelativeDuration", RelativeDuration;
+    CAL_DATE_DURATION => "cal::date_duration",
+        "edgedb_protocol::model::DateDuration", DateDuration;
+    STD_JSON => "std::json", "edgedb_protocol::model::Json", Json;
+    STD_BIGINT => "std::bigint", "edgedb_protocol::model::BigInt", BigInt;
+    CFG_MEMORY => "cfg::memory", "edgedb_protocol::model::ConfigMemory",
+        ConfigMemory;
+}
+
 impl Codec for Int32 {
     fn decode(&self, buf: &[u8]) -> Result<Value, DecodeError> {
         RawCodec::decode(buf).map(Value::Int32)
@@ -423,6 +497,24 @@ impl Codec for Bytes {
     }
 }
 
+impl Codec for Unknown {
+    fn decode(&self, buf: &[u8]) -> Result<Value, DecodeError> {
+        Ok(Value::Unknown { type_id: self.type_id, data: buf.to_vec() })
+    }
+    fn encode(&self, buf: &mut BytesMut, val: &Value)
+        -> Result<(), EncodeError>
+    {
+        let data = match val {
+            Value::Unknown { type_id, data } if *type_id == self.type_id => {
+                data
+            }
+            _ => Err(errors::invalid_value(type_name::<Self>(), val))?,
+        };
+        buf.extend(data);
+        Ok(())
+    }
+}
+
 impl Codec for Duration {
     fn decode(&self, buf: &[u8]) -> Result<Value, DecodeError> {
         RawCodec::decode(buf).map(Value::Duration)
@@ -1119,8 +1211,84 @@ impl Codec for NamedTuple {
     }
 }
 
+impl Array {
+    fn decode_multi(&self, mut buf: &[u8]) -> Result<Value, DecodeError> {
+        ensure!(buf.remaining() >= 12, errors::Underflow);
+        let ndims = buf.get_u32() as usize;
+        let _reserved0 = buf.get_u32();
+        let _reserved1 = buf.get_u32();
+        ensure!(buf.remaining() / 8 >= ndims, errors::Underflow);
+        let mut dimensions = Vec::with_capacity(ndims);
+        let mut total = 1usize;
+        for _ in 0..ndims {
+            let size = buf.get_u32() as usize;
+            let lower = buf.get_u32();
+            ensure!(lower == 1, errors::InvalidArrayShape);
+            total = total.checked_mul(size)
+                .context(errors::InvalidArrayShape)?;
+            dimensions.push(size);
+        }
+        // every element takes at least 4 bytes (the length)
+        ensure!(buf.remaining() / 4 >= total, errors::Underflow);
+        let mut elements = Vec::with_capacity(total);
+        for _ in 0..total {
+            ensure!(buf.remaining() >= 4, errors::Underflow);
+            let len = buf.get_i32() as usize;
+            ensure!(buf.remaining() >= len, errors::Underflow);
+            elements.push(self.element.decode(&buf[..len])?);
+            buf.advance(len);
+        }
+        ensure!(buf.remaining() == 0, errors::ExtraData);
+        Ok(Value::MultiArray { dimensions, elements })
+    }
+    fn encode_elements(&self, buf: &mut BytesMut, items: &[Value])
+        -> Result<(), EncodeError>
+    {
+        for item in items {
+            buf.reserve(4);
+            let pos = buf.len();
+            buf.put_u32(0);  // replaced after serializing a value
+            self.element.encode(buf, item)?;
+            let len = buf.len()-pos-4;
+            buf[pos..pos+4].copy_from_slice(&u32::try_from(len)
+                    .ok().context(errors::ElementTooLong)?
+                    .to_be_bytes());
+        }
+        Ok(())
+    }
+    fn encode_multi(&self, buf: &mut BytesMut,
+                    dimensions: &[usize], elements: &[Value])
+        -> Result<(), EncodeError>
+    {
+        ensure!(!dimensions.is_empty(), errors::ArrayShapeMismatch);
+        let total = dimensions.iter()
+            .try_fold(1usize, |total, &size| total.checked_mul(size));
+        ensure!(total == Some(elements.len()), errors::ArrayShapeMismatch);
+        if elements.is_empty() {
+            // empty arrays have no dimensions
+            return self.encode(buf, &Value::Array(Vec::new()));
+        }
+        buf.reserve(12 + 8*dimensions.len());
+        buf.put_u32(dimensions.len().try_into().ok()
+            .context(errors::ArrayTooLong)?);  // ndims
+        buf.put_u32(0);  // reserved0
+        buf.put_u32(0);  // reserved1
+        for &size in dimensions {
+            buf.put_u32(size.try_into().ok()
+                .context(errors::ArrayTooLong)?);
+            buf.put_u32(1);  // lower
+        }
+        self.encode_elements(buf, elements)
+    }
+}
+
 impl Codec for Array {
     fn decode(&self, buf: &[u8]) -> Result<Value, DecodeError> {
+        ensure!(buf.remaining() >= 4, errors::Underflow);
+        let ndims = (&buf[..4]).get_u32();
+        if ndims > 1 {
+            return self.decode_multi(buf);
+        }
         let elements = DecodeArrayLike::new_array(buf)?;
         let items = decode_array_like(elements, &*self.element)?;
         Ok(Value::Array(items))
@@ -1130,6 +1298,9 @@ impl Codec for Array {
     {
         let items = match val {
             Value::Array(items) => items,
+            Value::MultiArray { dimensions, elements } => {
+                return self.encode_multi(buf, dimensions, elements);
+            }
             _ => Err(errors::invalid_value(type_name::<Self>(), val))?,
         };
         if items.is_empty() {
@@ -1146,25 +1317,15 @@ impl Codec for Array {
         buf.put_u32(items.len().try_into().ok()
             .context(errors::ArrayTooLong)?);
         buf.put_u32(1);  // lower
-        for item in items {
-            buf.reserve(4);
-            let pos = buf.len();
-            buf.put_u32(0);  // replaced after serializing a value
-            self.element.encode(buf, item)?;
-            let len = buf.len()-pos-4;
-            buf[pos..pos+4].copy_from_slice(&u32::try_from(len)
-                    .ok().context(errors::ElementTooLong)?
-                    .to_be_bytes());
-        }
-        Ok(())
+        self.encode_elements(buf, items)
     }
 }
 
-const RANGE_EMPTY: usize = 0x01;
-const RANGE_LB_INC: usize = 0x02;
-const RANGE_UB_INC: usize = 0x04;
-const RANGE_LB_INF: usize = 0x08;
-const RANGE_UB_INF: usize = 0x10;
+pub(crate) const RANGE_EMPTY: usize = 0x01;
+pub(crate) const RANGE_LB_INC: usize = 0x02;
+pub(crate) const RANGE_UB_INC: usize = 0x04;
+pub(crate) const RANGE_LB_INF: usize = 0x08;
+pub(crate) const RANGE_UB_INF: usize = 0x10;
 
 impl Codec for Range {
     fn decode(&self, mut buf: &[u8]) -> Result<Value, DecodeError> {
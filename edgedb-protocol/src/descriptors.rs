@@ -5,7 +5,7 @@ use bytes::Buf;
 use uuid::Uuid;
 use snafu::{ensure, OptionExt};
 
-use crate::codec::{Codec, build_codec};
+use crate::codec::{Codec, build_codec, scalar_rust_type};
 use crate::common::Cardinality;
 use crate::encoding::{Decode, Input};
 use crate::errors::{InvalidTypeDescriptor, UnexpectedTypePos};
@@ -132,7 +132,38 @@ pub struct TypeAnnotationDescriptor {
     pub annotation: String,
 }
 
+fn find_root_pos(descriptors: &[Descriptor], root_id: Uuid)
+    -> Result<Option<TypePos>, DecodeError>
+{
+    if root_id == Uuid::from_u128(0) {
+        return Ok(None);
+    }
+    let idx = descriptors.iter().position(|x| *x.id() == root_id)
+        .context(errors::UuidNotFound { uuid: root_id })?;
+    let pos = idx.try_into().ok()
+        .context(errors::TooManyDescriptors { index: idx })?;
+    Ok(Some(TypePos(pos)))
+}
+
 impl OutputTypedesc {
+    /// Assemble output type descriptor from already decoded descriptors
+    ///
+    /// `root_id` must be either an id of one of the `descriptors` or zero
+    /// (for queries that return no data). This is mostly useful for testing
+    /// [`Queryable`](crate::queryable::Queryable) and codec implementations
+    /// without a server.
+    pub fn new(proto: ProtocolVersion, descriptors: Vec<Descriptor>,
+               root_id: Uuid)
+        -> Result<OutputTypedesc, DecodeError>
+    {
+        let root_pos = find_root_pos(&descriptors, root_id)?;
+        Ok(OutputTypedesc {
+            proto,
+            array: descriptors,
+            root_id,
+            root_pos,
+        })
+    }
     pub fn as_queryable_context(&self) -> queryable::DescriptorContext {
         let mut ctx = queryable::DescriptorContext::new(self.descriptors());
         ctx.has_implicit_tid = self.proto.has_implicit_tid();
@@ -144,6 +175,15 @@ impl OutputTypedesc {
     pub fn build_codec(&self) -> Result<Arc<dyn Codec>, CodecError> {
         build_codec(self.root_pos(), self.descriptors())
     }
+    /// Rust type the query result is decoded into, see [`rust_type`]
+    ///
+    /// Returns `()` for queries that return no data.
+    pub fn rust_type(&self) -> String {
+        match self.root_pos {
+            Some(pos) => rust_type(self.descriptors(), pos),
+            None => "()".into(),
+        }
+    }
     pub fn root_pos(&self) -> Option<TypePos> {
         self.root_pos
     }
@@ -155,26 +195,31 @@ impl OutputTypedesc {
                 item => descriptors.push(item),
             }
         }
-        let root_pos = if root_id == Uuid::from_u128(0) {
-            None
-        } else {
-            let idx = descriptors.iter().position(|x| *x.id() == root_id)
-                .context(errors::UuidNotFound { uuid: root_id })?;
-            let pos = idx.try_into().ok()
-                .context(errors::TooManyDescriptors { index: idx })?;
-            Some(TypePos(pos))
-        };
-        Ok(OutputTypedesc {
-            proto: buf.proto().clone(),
-            array: descriptors,
-            root_id,
-            root_pos,
-        })
+        OutputTypedesc::new(buf.proto().clone(), descriptors, root_id)
     }
 }
 
 
 impl InputTypedesc {
+    /// Assemble input type descriptor from already decoded descriptors
+    ///
+    /// `root_id` must be either an id of one of the `descriptors` or zero.
+    /// Queries without arguments use an empty tuple (with id
+    /// `00000000-0000-0000-0000-0000000000FF`) as the root. This is mostly
+    /// useful for testing [`QueryArgs`](crate::query_arg::QueryArgs)
+    /// implementations without a server.
+    pub fn new(proto: ProtocolVersion, descriptors: Vec<Descriptor>,
+               root_id: Uuid)
+        -> Result<InputTypedesc, DecodeError>
+    {
+        let root_pos = find_root_pos(&descriptors, root_id)?;
+        Ok(InputTypedesc {
+            proto,
+            array: descriptors,
+            root_id,
+            root_pos,
+        })
+    }
     pub fn as_query_arg_context(&self) -> query_arg::DescriptorContext {
         query_arg::DescriptorContext {
             proto: &self.proto,
@@ -210,6 +255,110 @@ impl InputTypedesc {
     }
 }
 
+/// Returns Rust type the value of the type at `type_pos` is decoded into
+///
+/// This is a canonical type, i.e. the one code generators should use for
+/// the type, other types may be accepted by
+/// [`Queryable`](crate::queryable::Queryable) too (e.g. `bytes::Bytes` for
+/// `std::bytes` or `std::time::SystemTime` for `std::datetime`):
+///
+/// * scalars are mapped to primitive types, `String` or types from
+///   [`model`](crate::model); enums are decoded as `String`, and custom
+///   scalars as their base type;
+/// * sets and arrays are `Vec<T>`, tuples (including named ones) are Rust
+///   tuples;
+/// * objects are described by a struct sketch, e.g.
+///   `struct { name: String, friends: Vec<struct { name: String }> }`,
+///   where optional properties and links are `Option<T>` and multi ones
+///   are `Vec<T>`. Implicit fields (e.g. `__tid__`) are omitted.
+///
+/// Scalars unknown to this crate are described as
+/// `edgedb_protocol::value::Value`.
+pub fn rust_type(descriptors: &[Descriptor], type_pos: TypePos) -> String {
+    rust_type_at(descriptors, type_pos, 0)
+}
+
+pub(crate) fn rust_type_of(descriptors: &[Descriptor], desc: &Descriptor)
+    -> String
+{
+    describe_rust_type(descriptors, desc, 0)
+}
+
+fn rust_type_at(descriptors: &[Descriptor], type_pos: TypePos, depth: usize)
+    -> String
+{
+    // descriptors only refer to the ones before them, so nesting deeper
+    // than the number of descriptors means there is a cycle
+    if depth > descriptors.len() {
+        return "<invalid>".into();
+    }
+    match descriptors.get(type_pos.0 as usize) {
+        Some(desc) => describe_rust_type(descriptors, desc, depth),
+        None => "<invalid>".into(),
+    }
+}
+
+fn describe_rust_type(descriptors: &[Descriptor], desc: &Descriptor,
+                      depth: usize)
+    -> String
+{
+    use Descriptor::*;
+    let element = |pos| rust_type_at(descriptors, pos, depth + 1);
+    match desc {
+        BaseScalar(d) => scalar_rust_type(&d.id)
+            .unwrap_or("edgedb_protocol::value::Value").into(),
+        Scalar(d) => element(d.base_type_pos),
+        Enumeration(_) => "String".into(),
+        Set(d) => format!("Vec<{}>", element(d.type_pos)),
+        Array(d) => format!("Vec<{}>", element(d.type_pos)),
+        Range(d) => format!("edgedb_protocol::model::Range<{}>",
+                            element(d.type_pos)),
+        Tuple(d) => rust_tuple(d.element_types.iter().map(|&p| element(p))),
+        NamedTuple(d) => {
+            rust_tuple(d.elements.iter().map(|el| element(el.type_pos)))
+        }
+        ObjectShape(ObjectShapeDescriptor { elements, .. })
+        | InputShape(InputShapeTypeDescriptor { elements, .. }) => {
+            let fields = elements.iter()
+                .filter(|el| !el.flag_implicit)
+                .map(|el| format!("{}: {}", el.name,
+                                  rust_field(descriptors, el, depth + 1)))
+                .collect::<Vec<_>>();
+            if fields.is_empty() {
+                "struct {}".into()
+            } else {
+                format!("struct {{ {} }}", fields.join(", "))
+            }
+        }
+        TypeAnnotation(_) => "<annotation>".into(),
+    }
+}
+
+fn rust_tuple(elements: impl Iterator<Item=String>) -> String {
+    let elements = elements.collect::<Vec<_>>();
+    match elements.len() {
+        1 => format!("({},)", elements[0]),
+        _ => format!("({})", elements.join(", ")),
+    }
+}
+
+fn rust_field(descriptors: &[Descriptor], el: &ShapeElement, depth: usize)
+    -> String
+{
+    use Cardinality::*;
+    let ty = rust_type_at(descriptors, el.type_pos, depth);
+    let is_set = matches!(descriptors.get(el.type_pos.0 as usize),
+                          Some(Descriptor::Set(_)));
+    match el.cardinality {
+        Some(AtMostOne) => format!("Option<{}>", ty),
+        // cardinality of the element is the cardinality of the set, so
+        // don't wrap it twice
+        Some(Many) | Some(AtLeastOne) if is_set => ty,
+        Some(Many) | Some(AtLeastOne) => format!("Vec<{}>", ty),
+        Some(One) | Some(NoResult) | None => ty,
+    }
+}
+
 impl Descriptor {
     pub fn id(&self) -> &Uuid {
         use Descriptor::*;
@@ -258,7 +407,8 @@ impl Decode for Descriptor {
 impl Decode for SetDescriptor {
     fn decode(buf: &mut Input) -> Result<Self, DecodeError> {
         ensure!(buf.remaining() >= 19, errors::Underflow);
-        assert!(buf.get_u8() == 0);
+        let descriptor = buf.get_u8();
+        ensure!(descriptor == 0, InvalidTypeDescriptor { descriptor });
         let id = Uuid::decode(buf)?;
         let type_pos = TypePos(buf.get_u16());
         Ok(SetDescriptor { id, type_pos })
@@ -268,7 +418,8 @@ impl Decode for SetDescriptor {
 impl Decode for ObjectShapeDescriptor {
     fn decode(buf: &mut Input) -> Result<Self, DecodeError> {
         ensure!(buf.remaining() >= 19, errors::Underflow);
-        assert!(buf.get_u8() == 1);
+        let descriptor = buf.get_u8();
+        ensure!(descriptor == 1, InvalidTypeDescriptor { descriptor });
         let id = Uuid::decode(buf)?;
         let element_count = buf.get_u16();
         let mut elements = Vec::with_capacity(element_count as usize);
@@ -282,7 +433,8 @@ impl Decode for ObjectShapeDescriptor {
 impl Decode for InputShapeTypeDescriptor {
     fn decode(buf: &mut Input) -> Result<Self, DecodeError> {
         ensure!(buf.remaining() >= 19, errors::Underflow);
-        assert!(buf.get_u8() == 8);
+        let descriptor = buf.get_u8();
+        ensure!(descriptor == 8, InvalidTypeDescriptor { descriptor });
         let id = Uuid::decode(buf)?;
         let element_count = buf.get_u16();
         let mut elements = Vec::with_capacity(element_count as usize);
@@ -319,7 +471,9 @@ impl Decode for ShapeElement {
 
 impl Decode for BaseScalarTypeDescriptor {
     fn decode(buf: &mut Input) -> Result<Self, DecodeError> {
-        assert!(buf.get_u8() == 2);
+        ensure!(buf.remaining() >= 17, errors::Underflow);
+        let descriptor = buf.get_u8();
+        ensure!(descriptor == 2, InvalidTypeDescriptor { descriptor });
         let id = Uuid::decode(buf)?;
         Ok(BaseScalarTypeDescriptor { id })
     }
@@ -329,7 +483,8 @@ impl Decode for BaseScalarTypeDescriptor {
 impl Decode for ScalarTypeDescriptor {
     fn decode(buf: &mut Input) -> Result<Self, DecodeError> {
         ensure!(buf.remaining() >= 19, errors::Underflow);
-        assert!(buf.get_u8() == 3);
+        let descriptor = buf.get_u8();
+        ensure!(descriptor == 3, InvalidTypeDescriptor { descriptor });
         let id = Uuid::decode(buf)?;
         let base_type_pos = TypePos(buf.get_u16());
         Ok(ScalarTypeDescriptor { id, base_type_pos })
@@ -339,7 +494,8 @@ impl Decode for ScalarTypeDescriptor {
 impl Decode for TupleTypeDescriptor {
     fn decode(buf: &mut Input) -> Result<Self, DecodeError> {
         ensure!(buf.remaining() >= 19, errors::Underflow);
-        assert!(buf.get_u8() == 4);
+        let descriptor = buf.get_u8();
+        ensure!(descriptor == 4, InvalidTypeDescriptor { descriptor });
         let id = Uuid::decode(buf)?;
         let el_count = buf.get_u16();
         ensure!(buf.remaining() >= 2*el_count as usize, errors::Underflow);
@@ -354,7 +510,8 @@ impl Decode for TupleTypeDescriptor {
 impl Decode for NamedTupleTypeDescriptor {
     fn decode(buf: &mut Input) -> Result<Self, DecodeError> {
         ensure!(buf.remaining() >= 19, errors::Underflow);
-        assert!(buf.get_u8() == 5);
+        let descriptor = buf.get_u8();
+        ensure!(descriptor == 5, InvalidTypeDescriptor { descriptor });
         let id = Uuid::decode(buf)?;
         let element_count = buf.get_u16();
         let mut elements = Vec::with_capacity(element_count as usize);
@@ -380,7 +537,8 @@ impl Decode for TupleElement {
 impl Decode for ArrayTypeDescriptor {
     fn decode(buf: &mut Input) -> Result<Self, DecodeError> {
         ensure!(buf.remaining() >= 21, errors::Underflow);
-        assert!(buf.get_u8() == 6);
+        let descriptor = buf.get_u8();
+        ensure!(descriptor == 6, InvalidTypeDescriptor { descriptor });
         let id = Uuid::decode(buf)?;
         let type_pos = TypePos(buf.get_u16());
         let dim_count = buf.get_u16();
@@ -400,7 +558,8 @@ impl Decode for ArrayTypeDescriptor {
 impl Decode for RangeTypeDescriptor {
     fn decode(buf: &mut Input) -> Result<Self, DecodeError> {
         ensure!(buf.remaining() >= 19, errors::Underflow);
-        assert!(buf.get_u8() == 9);
+        let descriptor = buf.get_u8();
+        ensure!(descriptor == 9, InvalidTypeDescriptor { descriptor });
         let id = Uuid::decode(buf)?;
         let type_pos = TypePos(buf.get_u16());
         Ok(RangeTypeDescriptor { id, type_pos })
@@ -410,7 +569,8 @@ impl Decode for RangeTypeDescriptor {
 impl Decode for EnumerationTypeDescriptor {
     fn decode(buf: &mut Input) -> Result<Self, DecodeError> {
         ensure!(buf.remaining() >= 19, errors::Underflow);
-        assert!(buf.get_u8() == 7);
+        let descriptor = buf.get_u8();
+        ensure!(descriptor == 7, InvalidTypeDescriptor { descriptor });
         let id = Uuid::decode(buf)?;
         let member_count = buf.get_u16();
         let mut members = Vec::with_capacity(member_count as usize);
@@ -425,7 +585,8 @@ impl Decode for TypeAnnotationDescriptor {
     fn decode(buf: &mut Input) -> Result<Self, DecodeError> {
         ensure!(buf.remaining() >= 21, errors::Underflow);
         let annotated_type = buf.get_u8();
-        assert!(annotated_type >= 0x7F);
+        ensure!(annotated_type >= 0x7F,
+                InvalidTypeDescriptor { descriptor: annotated_type });
         let id = Uuid::decode(buf)?;
         let annotation = String::decode(buf)?;
         Ok(TypeAnnotationDescriptor { annotated_type, id, annotation })
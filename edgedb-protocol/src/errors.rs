@@ -113,6 +113,8 @@ pub enum EncodeError {
     TupleShapeMismatch { backtrace: Backtrace },
     #[snafu(display("enum value is not in type descriptor"))]
     MissingEnumValue { backtrace: Backtrace },
+    #[snafu(display("array dimensions don't match number of elements"))]
+    ArrayShapeMismatch { backtrace: Backtrace },
 }
 
 #[derive(Snafu, Debug)]
@@ -123,6 +125,8 @@ pub enum CodecError {
     UnexpectedTypePos { backtrace: Backtrace, position: u16 },
     #[snafu(display("base scalar with uuid {} not found", uuid))]
     UndefinedBaseScalar { backtrace: Backtrace, uuid: uuid::Uuid },
+    #[snafu(display("enumeration type has no members"))]
+    EmptyEnum { backtrace: Backtrace },
 }
 
 pub fn invalid_value(codec: &'static str, value: &Value) -> EncodeError
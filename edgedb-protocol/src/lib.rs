@@ -14,6 +14,10 @@ pub mod codec;
 pub mod queryable;
 pub mod query_arg;
 pub mod model;
+#[cfg(feature="test-vectors")]
+pub mod test_vectors;
+#[cfg(feature="random-values")]
+pub mod random_values;
 
 
-pub use query_result::QueryResult;
+pub use query_result::{QueryResult, WithId, WithIdState};
@@ -10,6 +10,8 @@ pub use memory::ConfigMemory;
 pub use range::Range;
 pub use self::bignum:: {BigInt, Decimal};
 pub use self::json::Json;
+#[cfg(feature = "with-serde")]
+pub use self::json::AsJson;
 pub use self::time::{LocalDatetime, LocalDate, LocalTime, Duration, Datetime};
 pub use self::time::{RelativeDuration,DateDuration};
 pub use uuid::Uuid;
@@ -33,6 +33,17 @@ impl BigInt {
         return self
     }
 
+    /// Same number with no leading and trailing zero digits and zero
+    /// always being positive
+    pub(crate) fn canonical(&self) -> BigInt {
+        let mut val = self.clone().normalize();
+        if val.digits.is_empty() {
+            val.negative = false;
+            val.weight = 0;
+        }
+        val
+    }
+
     fn trailing_zero_groups(&self) -> i16 {
         self.weight - self.digits.len() as i16 + 1
     }
@@ -131,7 +142,6 @@ impl From<i32> for BigInt {
 }
 
 impl Decimal {
-    #[allow(dead_code)]  // isn't used when BigDecimal is disabled
     fn normalize(mut self) -> Decimal {
         while let Some(0) = self.digits.last() {
             self.digits.pop();
@@ -142,6 +152,34 @@ impl Decimal {
         }
         return self
     }
+    /// Same number with insignificant zeros trimmed, i.e. `1.50` becomes
+    /// `1.5` and `-0.0` becomes `0`
+    pub(crate) fn canonical(&self) -> Decimal {
+        let mut val = self.clone().normalize();
+        let last = match val.digits.last() {
+            Some(&last) => last,
+            None => return Decimal {
+                negative: false,
+                weight: 0,
+                decimal_digits: 0,
+                digits: Vec::new(),
+            },
+        };
+        // number of base 10000 digits after the decimal point
+        let fraction = val.digits.len() as i32 - val.weight as i32 - 1;
+        val.decimal_digits = if fraction > 0 {
+            let mut zeros = 0;
+            let mut last = last;
+            while last % 10 == 0 {
+                last /= 10;
+                zeros += 1;
+            }
+            (fraction * 4 - zeros) as u16
+        } else {
+            0
+        };
+        val
+    }
 }
 
 #[cfg(test)]
@@ -261,4 +299,55 @@ mod test {
             assert_eq!(BigInt::from(i).to_string(), i.to_string());
         }
     }
+
+    #[test]
+    fn canonical() {
+        let dec = Decimal {
+            negative: true,
+            weight: 1,
+            decimal_digits: 8,
+            digits: vec![0, 1, 2, 5000, 0],
+        };
+        assert_eq!(dec.canonical(), Decimal {
+            negative: true,
+            weight: 0,
+            decimal_digits: 5,
+            digits: vec![1, 2, 5000],
+        });
+        let dec = Decimal {
+            negative: false,
+            weight: 1,
+            decimal_digits: 2,
+            digits: vec![1, 0],
+        };
+        assert_eq!(dec.canonical(), Decimal {
+            negative: false,
+            weight: 1,
+            decimal_digits: 0,
+            digits: vec![1],
+        });
+        let zero = Decimal {
+            negative: true,
+            weight: -1,
+            decimal_digits: 3,
+            digits: vec![0],
+        };
+        assert_eq!(zero.canonical(), Decimal {
+            negative: false,
+            weight: 0,
+            decimal_digits: 0,
+            digits: vec![],
+        });
+        let zero = BigInt {
+            negative: true,
+            weight: 2,
+            digits: vec![0, 0],
+        };
+        assert_eq!(zero.canonical(), BigInt {
+            negative: false,
+            weight: 0,
+            digits: vec![],
+        });
+        assert_eq!(BigInt::from(0).canonical(), zero.canonical());
+    }
 }
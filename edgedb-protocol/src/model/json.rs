@@ -15,6 +15,30 @@ impl Json {
 	}
 }
 
+#[cfg(feature = "with-serde")]
+impl Json {
+    /// Serialize a value into JSON
+    ///
+    /// Useful for passing structured data to `<json>$arg` parameters.
+    pub fn from_serialize<T>(value: &T) -> Result<Json, serde_json::Error>
+        where T: serde::Serialize + ?Sized,
+    {
+        serde_json::to_string(value).map(Json)
+    }
+}
+
+/// A wrapper that passes any serializable value as a JSON query argument
+///
+/// Unlike [`Json::from_serialize`] value is serialized directly into the
+/// query arguments buffer, without intermediate string.
+///
+/// ```rust,ignore
+/// client.query::<Value, _>("SELECT <json>$0", &(AsJson(&data),)).await?;
+/// ```
+#[cfg(feature = "with-serde")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AsJson<T>(pub T);
+
 impl AsRef<str> for Json {
     fn as_ref(&self) -> &str {
         &self.0
@@ -1,3 +1,4 @@
+use std::any::Any;
 use std::convert::TryFrom;
 use std::sync::Arc;
 
@@ -5,20 +6,38 @@ use bytes::{BytesMut, BufMut};
 use snafu::OptionExt;
 use uuid::Uuid;
 
-use edgedb_errors::{Error, ErrorKind};
+use edgedb_errors::{ErrorKind};
 use edgedb_errors::{ClientEncodingError, ProtocolError, DescriptorMismatch};
 
-use crate::codec::{Codec, build_codec};
-use crate::descriptors::Descriptor;
+use crate::codec::{self, Codec, build_codec, scalar_name, EnumValue};
+use crate::descriptors::{Descriptor, ShapeElement};
 use crate::descriptors::TypePos;
 use crate::errors;
 use crate::features::ProtocolVersion;
+use crate::model::Range;
 use crate::value::Value;
 
+pub use edgedb_errors::Error;
+
 
 pub struct Encoder<'a> {
     pub(crate) ctx: &'a DescriptorContext<'a>,
     pub(crate) buf: &'a mut BytesMut,
+    external: Option<Vec<ExternalData>>,
+}
+
+/// Argument data that is not stored in the encoded buffer
+///
+/// Created by [`Encoder::external_slot`] of an encoder made with
+/// [`Encoder::with_external`]. When sending arguments, exactly
+/// `len` bytes read from the `source` must be inserted at `offset` of the
+/// encoded buffer. The `source` is opaque for this crate, it's interpreted
+/// by the client that supports streaming arguments.
+#[derive(Debug)]
+pub struct ExternalData {
+    pub offset: usize,
+    pub len: usize,
+    pub source: Box<dyn Any + Send>,
 }
 
 /// A single argument for a query.
@@ -57,7 +76,45 @@ impl<'a> Encoder<'a> {
     pub fn new(ctx: &'a DescriptorContext<'a>, buf: &'a mut BytesMut)
         -> Encoder<'a>
     {
-        Encoder { ctx, buf }
+        Encoder { ctx, buf, external: None }
+    }
+    /// Create an encoder that allows [`external_slot`](Encoder::external_slot)
+    ///
+    /// Should only be used by clients that send data of the slots taken with
+    /// [`take_external`](Encoder::take_external) to the server.
+    pub fn with_external(ctx: &'a DescriptorContext<'a>, buf: &'a mut BytesMut)
+        -> Encoder<'a>
+    {
+        Encoder { ctx, buf, external: Some(Vec::new()) }
+    }
+    /// Write a slot of `len` bytes which data is sent separately
+    ///
+    /// Only the length of the slot is written to the buffer. This allows
+    /// large arguments to be streamed directly to the connection without
+    /// keeping them in memory. Returns an error if the encoder isn't created
+    /// using [`with_external`](Encoder::with_external).
+    pub fn external_slot(&mut self, len: usize, source: Box<dyn Any + Send>)
+        -> Result<(), Error>
+    {
+        let external = self.external.as_mut()
+            .ok_or_else(|| ClientEncodingError::with_message(
+                "streaming arguments are not supported here"))?;
+        let slot_len = i32::try_from(len)
+            .ok().context(errors::ElementTooLong)
+            .map_err(ClientEncodingError::with_source)?;
+        self.buf.reserve(4);
+        self.buf.put_i32(slot_len);
+        external.push(ExternalData {
+            offset: self.buf.len(),
+            len,
+            source,
+        });
+        Ok(())
+    }
+    /// Take data of the slots written by
+    /// [`external_slot`](Encoder::external_slot), ordered by offset
+    pub fn take_external(&mut self) -> Vec<ExternalData> {
+        self.external.as_mut().map(std::mem::take).unwrap_or_default()
     }
 }
 
@@ -77,8 +134,8 @@ impl DescriptorContext<'_> {
     pub fn wrong_type(&self, descriptor: &Descriptor, expected: &str) -> Error
     {
         DescriptorMismatch::with_message(format!(
-            "unexpected type {:?}, expected {}",
-            descriptor, expected))
+            "unexpected type {}, expected {}",
+            self.describe(descriptor), expected))
     }
     pub fn field_number(&self, expected: usize, unexpected: usize)
         -> Error
@@ -87,6 +144,82 @@ impl DescriptorContext<'_> {
             "expected {} fields, got {}",
             expected, unexpected))
     }
+    /// Returns EdgeQL name of the type at the specified position
+    pub fn type_name(&self, type_pos: TypePos) -> String {
+        match self.descriptors.get(type_pos.0 as usize) {
+            Some(desc) => self.describe(desc),
+            None => "<invalid>".into(),
+        }
+    }
+    fn describe(&self, desc: &Descriptor) -> String {
+        use Descriptor::*;
+        let list = |items: &mut dyn Iterator<Item=String>| {
+            items.collect::<Vec<_>>().join(", ")
+        };
+        match desc {
+            BaseScalar(d) => scalar_name(&d.id).map(String::from)
+                .unwrap_or_else(|| d.id.to_string()),
+            Scalar(d) => self.type_name(d.base_type_pos),
+            Set(d) => format!("set<{}>", self.type_name(d.type_pos)),
+            Array(d) => format!("array<{}>", self.type_name(d.type_pos)),
+            Range(d) => format!("range<{}>", self.type_name(d.type_pos)),
+            Tuple(d) => format!("tuple<{}>", list(
+                &mut d.element_types.iter().map(|&p| self.type_name(p)))),
+            NamedTuple(d) => format!("tuple<{}>", list(
+                &mut d.elements.iter()
+                .map(|el| format!("{}: {}",
+                                  el.name, self.type_name(el.type_pos))))),
+            Enumeration(_) => "enum".into(),
+            ObjectShape(_) | InputShape(_) => "object".into(),
+            TypeAnnotation(_) => "annotation".into(),
+        }
+    }
+    /// Describe query parameters, e.g. `$0: std::str, $1: std::int64`
+    pub fn parameters(&self) -> String {
+        let root = self.root_pos.and_then(|p| self.get(p).ok());
+        let params = match root {
+            Some(Descriptor::ObjectShape(desc)) => {
+                desc.elements.iter()
+                    .map(|el| format!("${}: {}",
+                                      el.name, self.type_name(el.type_pos)))
+                    .collect::<Vec<_>>()
+            }
+            Some(Descriptor::Tuple(desc)) => {
+                desc.element_types.iter().enumerate()
+                    .map(|(i, &p)| format!("${}: {}", i, self.type_name(p)))
+                    .collect::<Vec<_>>()
+            }
+            _ => Vec::new(),
+        };
+        params.join(", ")
+    }
+    fn positional_mismatch(&self, elements: &[ShapeElement], provided: usize)
+        -> Error
+    {
+        let expected = self.parameters();
+        if elements.iter().any(|el| el.name.parse::<usize>().is_err()) {
+            return DescriptorMismatch::with_message(format!(
+                "query expects named arguments ({}), \
+                 but {} positional arguments were provided",
+                expected, provided));
+        }
+        let problem = if provided < elements.len() {
+            format!("missing {}", elements[provided..].iter()
+                .map(|el| format!("${}: {}",
+                                  el.name, self.type_name(el.type_pos)))
+                .collect::<Vec<_>>()
+                .join(", "))
+        } else {
+            let extra = (elements.len()..provided)
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>();
+            format!("unexpected positional arg{} {}",
+                if extra.len() > 1 { "s" } else { "" },
+                extra.join(", "))
+        };
+        DescriptorMismatch::with_message(format!(
+            "{}; query expects ({})", problem, expected))
+    }
 }
 
 impl<T: ScalarArg> ScalarArg for &T {
@@ -116,11 +249,13 @@ impl QueryArgs for () {
                     && t.element_types.is_empty()
                     => {}
                     _ => return Err(DescriptorMismatch::with_message(
-                            "query arguments expected")),
+                        format!("query arguments expected: missing {}",
+                                enc.ctx.parameters()))),
                 };
             } else {
                 return Err(DescriptorMismatch::with_message(
-                    "query arguments expected"));
+                    format!("query arguments expected: missing {}",
+                            enc.ctx.parameters())));
             }
         }
         if enc.ctx.proto.is_at_most(0, 11) {
@@ -178,6 +313,57 @@ impl<T: ScalarArg> QueryArg for Option<T> {
     }
 }
 
+impl<T: ScalarArg> ScalarArg for Range<T> {
+    fn encode(&self, enc: &mut Encoder) -> Result<(), Error> {
+        let flags = if self.empty {
+            codec::RANGE_EMPTY
+        } else {
+            (if self.inc_lower { codec::RANGE_LB_INC } else { 0 }) |
+            (if self.inc_upper { codec::RANGE_UB_INC } else { 0 }) |
+            (if self.lower.is_none() { codec::RANGE_LB_INF } else { 0 }) |
+            (if self.upper.is_none() { codec::RANGE_UB_INF } else { 0 })
+        };
+        enc.buf.reserve(1);
+        enc.buf.put_u8(flags as u8);
+        if let Some(lower) = &self.lower {
+            QueryArg::encode_slot(lower, enc)?;
+        }
+        if let Some(upper) = &self.upper {
+            QueryArg::encode_slot(upper, enc)?;
+        }
+        Ok(())
+    }
+    fn check_descriptor(ctx: &DescriptorContext, pos: TypePos)
+        -> Result<(), Error>
+    {
+        let desc = ctx.get(pos)?;
+        match desc {
+            Descriptor::Range(rng) => T::check_descriptor(ctx, rng.type_pos),
+            _ => Err(ctx.wrong_type(desc, "range")),
+        }
+    }
+}
+
+/// Enum values are passed as their string representation
+///
+/// Membership of the value is checked by the server. To pass a Rust enum
+/// as an argument, derive `ScalarArg` for it using `edgedb-derive` crate.
+impl ScalarArg for EnumValue {
+    fn encode(&self, enc: &mut Encoder) -> Result<(), Error> {
+        enc.buf.extend(self.as_bytes());
+        Ok(())
+    }
+    fn check_descriptor(ctx: &DescriptorContext, pos: TypePos)
+        -> Result<(), Error>
+    {
+        let desc = ctx.get(pos)?;
+        match desc {
+            Descriptor::Enumeration(_) => Ok(()),
+            _ => Err(ctx.wrong_type(desc, "enum")),
+        }
+    }
+}
+
 macro_rules! implement_tuple {
     ( $count:expr, $($name:ident,)+ ) => {
         impl<$($name:QueryArg),+> QueryArgs for ($($name,)+) {
@@ -186,28 +372,28 @@ macro_rules! implement_tuple {
             {
                 #![allow(non_snake_case)]
                 let root_pos = enc.ctx.root_pos
-                    .ok_or_else(|| DescriptorMismatch::with_message(
+                    .ok_or_else(|| DescriptorMismatch::with_message(format!(
                         "provided {} positional arguments, \
-                         but no arguments expected by the server"))?;
+                         but no arguments expected by the server", $count)))?;
                 let desc = enc.ctx.get(root_pos)?;
                 match desc {
                     Descriptor::ObjectShape(desc)
                     if enc.ctx.proto.is_at_least(0, 12)
                     => {
                         if desc.elements.len() != $count {
-                            return Err(enc.ctx.field_number(
-                                $count, desc.elements.len()));
+                            return Err(enc.ctx.positional_mismatch(
+                                &desc.elements, $count));
                         }
                         let mut els = desc.elements.iter().enumerate();
                         $(
                             let (idx, el) = els.next().unwrap();
                             if el.name.parse() != Ok(idx) {
-                                return Err(DescriptorMismatch::with_message(
-                                    format!("expected positional arguments, \
-                                             got {} instead of {}",
-                                             el.name, idx)));
+                                return Err(enc.ctx.positional_mismatch(
+                                    &desc.elements, $count));
                             }
-                            $name::check_descriptor(enc.ctx, el.type_pos)?;
+                            $name::check_descriptor(enc.ctx, el.type_pos)
+                                .map_err(|e| e.context(
+                                    format!("argument ${}", el.name)))?;
                         )+
                     }
                     Descriptor::Tuple(desc) if enc.ctx.proto.is_at_most(0, 11)
@@ -216,10 +402,12 @@ macro_rules! implement_tuple {
                             return Err(enc.ctx.field_number(
                                 $count, desc.element_types.len()));
                         }
-                        let mut els = desc.element_types.iter();
+                        let mut els = desc.element_types.iter().enumerate();
                         $(
-                            let type_pos = els.next().unwrap();
-                            $name::check_descriptor(enc.ctx, *type_pos)?;
+                            let (idx, type_pos) = els.next().unwrap();
+                            $name::check_descriptor(enc.ctx, *type_pos)
+                                .map_err(|e| e.context(
+                                    format!("argument ${}", idx)))?;
                         )+
                     }
                     _ => return Err(enc.ctx.wrong_type(desc,
@@ -2,13 +2,17 @@ use std::sync::Arc;
 
 use bytes::Bytes;
 
+use std::convert::TryFrom;
+
 use edgedb_errors::{Error, ErrorKind};
 use edgedb_errors::{ProtocolEncodingError, DescriptorMismatch};
 
 use crate::codec::Codec;
 use crate::queryable::{Queryable, Decoder, DescriptorContext};
-use crate::descriptors::{TypePos};
-use crate::value::Value;
+use crate::descriptors::{Descriptor, TypePos};
+use crate::model::Uuid;
+use crate::serialization::decode::DecodeTupleLike;
+use crate::value::{Value, FreeObject};
 
 
 pub trait Sealed: Sized {}
@@ -32,6 +36,33 @@ impl<T: Queryable> Sealed for T {
 impl Sealed for Value {
 }
 
+impl Sealed for FreeObject {
+}
+
+impl<T: QueryResult> Sealed for WithId<T> {
+}
+
+/// Object decoded together with its `id`
+///
+/// This is useful to build lookup tables keyed by object id. The `id`
+/// property must be selected in the shape (or be added implicitly by the
+/// server), while the rest of the object is decoded into `T`. The object
+/// itself is passed to `T` as is, so if `id` is selected explicitly, a
+/// derived `T` must have an `id` field too. Only the implicit `id` added by
+/// the server can be omitted from `T`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WithId<T> {
+    pub id: Uuid,
+    pub value: T,
+}
+
+/// Decoder state of [`WithId`]
+pub struct WithIdState<S> {
+    id_index: usize,
+    elements: usize,
+    value: S,
+}
+
 impl<T: Queryable> QueryResult for T {
     type State = Decoder;
     fn prepare(ctx: &DescriptorContext, root_pos: TypePos)
@@ -42,13 +73,17 @@ impl<T: Queryable> QueryResult for T {
         Ok(Decoder {
             has_implicit_tid: ctx.has_implicit_tid,
             has_implicit_tname: ctx.has_implicit_tname,
+            source: None,
         })
     }
     fn decode(decoder: &mut Decoder, msg: &Bytes)
         -> Result<Self, Error>
     {
-        Queryable::decode(&decoder, msg)
-            .map_err(ProtocolEncodingError::with_source)
+        decoder.source = Some(msg.clone());
+        let result = Queryable::decode(&decoder, msg)
+            .map_err(ProtocolEncodingError::with_source);
+        decoder.source = None;
+        result
     }
 }
 
@@ -67,3 +102,76 @@ impl QueryResult for Value {
     }
 }
 
+
+impl QueryResult for FreeObject {
+    type State = Arc<dyn Codec>;
+    fn prepare(ctx: &DescriptorContext, root_pos: TypePos)
+        -> Result<Arc<dyn Codec>, Error>
+    {
+        let desc = ctx.get(root_pos)
+            .map_err(DescriptorMismatch::with_source)?;
+        match desc {
+            Descriptor::ObjectShape(_) => {}
+            _ => {
+                return Err(DescriptorMismatch::with_source(
+                    ctx.wrong_type(desc, "object")));
+            }
+        }
+        ctx.build_codec(root_pos)
+    }
+    fn decode(codec: &mut Arc<dyn Codec>, msg: &Bytes)
+        -> Result<Self, Error>
+    {
+        let value = codec.decode(msg)
+            .map_err(ProtocolEncodingError::with_source)?;
+        FreeObject::try_from(value)
+            .map_err(|v| ProtocolEncodingError::with_message(format!(
+                "expected object, got {}", v.kind())))
+    }
+}
+
+impl<T: QueryResult> QueryResult for WithId<T> {
+    type State = WithIdState<T::State>;
+    fn prepare(ctx: &DescriptorContext, root_pos: TypePos)
+        -> Result<Self::State, Error>
+    {
+        let desc = ctx.get(root_pos)
+            .map_err(DescriptorMismatch::with_source)?;
+        let shape = match desc {
+            Descriptor::ObjectShape(shape) => shape,
+            _ => {
+                return Err(DescriptorMismatch::with_source(
+                    ctx.wrong_type(desc, "object")));
+            }
+        };
+        let id_index = shape.elements.iter()
+            .position(|el| el.name == "id")
+            .ok_or_else(|| DescriptorMismatch::with_source(
+                ctx.expected("object with `id` property")))?;
+        Uuid::check_descriptor(ctx, shape.elements[id_index].type_pos)
+            .map_err(DescriptorMismatch::with_source)?;
+        Ok(WithIdState {
+            id_index,
+            elements: shape.elements.len(),
+            value: T::prepare(ctx, root_pos)?,
+        })
+    }
+    fn decode(state: &mut Self::State, msg: &Bytes)
+        -> Result<Self, Error>
+    {
+        let mut elements = DecodeTupleLike::new_object(msg, state.elements)
+            .map_err(ProtocolEncodingError::with_source)?;
+        for _ in 0..state.id_index {
+            elements.skip_element()
+                .map_err(ProtocolEncodingError::with_source)?;
+        }
+        let decoder = Decoder::default();
+        let id = elements.read()
+            .and_then(|buf| Uuid::decode_optional(&decoder, buf))
+            .map_err(ProtocolEncodingError::with_source)?;
+        Ok(WithId {
+            id,
+            value: T::decode(&mut state.value, msg)?,
+        })
+    }
+}
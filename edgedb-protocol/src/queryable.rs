@@ -1,17 +1,21 @@
 use std::sync::Arc;
 use std::default::Default;
+use bytes::Bytes;
 use snafu::{Snafu, ensure};
 
 use edgedb_errors::{Error, ErrorKind, ProtocolEncodingError};
-use crate::codec::{Codec, build_codec};
+use crate::codec::{Codec, build_codec, build_codec_with_fallback};
+use crate::codec::scalar_name;
 use crate::errors::{self, DecodeError};
-use crate::descriptors::{Descriptor, TypePos};
+use crate::descriptors::{self, Descriptor, TypePos};
 
 
 #[non_exhaustive]
 pub struct Decoder {
     pub has_implicit_tid: bool,
     pub has_implicit_tname: bool,
+    /// Message being decoded, so that `Bytes` can be sliced out of it
+    pub(crate) source: Option<Bytes>,
 }
 
 impl Default for Decoder {
@@ -19,10 +23,30 @@ impl Default for Decoder {
         Decoder {
             has_implicit_tid: false,
             has_implicit_tname: false,
+            source: None,
         }
     }
 }
 
+impl Decoder {
+    /// Returns `buf` as `Bytes` sharing memory with the decoded message
+    ///
+    /// Falls back to a copy if `buf` isn't a part of the message (e.g. if
+    /// `Queryable::decode` is called directly on a slice).
+    pub(crate) fn bytes(&self, buf: &[u8]) -> Bytes {
+        match &self.source {
+            Some(src) if contains(src, buf) => src.slice_ref(buf),
+            _ => Bytes::copy_from_slice(buf),
+        }
+    }
+}
+
+fn contains(src: &[u8], buf: &[u8]) -> bool {
+    let start = src.as_ptr() as usize;
+    let ptr = buf.as_ptr() as usize;
+    ptr >= start && ptr + buf.len() <= start + src.len()
+}
+
 pub trait Queryable: Sized {
     fn decode(decoder: &Decoder, buf: &[u8])
         -> Result<Self, DecodeError>;
@@ -54,6 +78,11 @@ pub enum DescriptorMismatch {
 pub struct DescriptorContext<'a> {
     pub has_implicit_tid: bool,
     pub has_implicit_tname: bool,
+    /// Decode scalars of unknown types as [`Value::Unknown`] in dynamic
+    /// results instead of failing
+    ///
+    /// [`Value::Unknown`]: crate::value::Value::Unknown
+    pub unknown_scalars: bool,
     descriptors: &'a [Descriptor],
 }
 
@@ -63,13 +92,17 @@ impl DescriptorContext<'_> {
             descriptors,
             has_implicit_tid: false,
             has_implicit_tname: false,
+            unknown_scalars: false,
         }
     }
     pub fn build_codec(&self, root_pos: TypePos)
         -> Result<Arc<dyn Codec>, Error>
     {
-        build_codec(Some(root_pos), self.descriptors)
-            .map_err(ProtocolEncodingError::with_source)
+        if self.unknown_scalars {
+            build_codec_with_fallback(Some(root_pos), self.descriptors)
+        } else {
+            build_codec(Some(root_pos), self.descriptors)
+        }.map_err(ProtocolEncodingError::with_source)
     }
     pub fn get(&self, type_pos: TypePos)
         -> Result<&Descriptor, DescriptorMismatch>
@@ -77,12 +110,25 @@ impl DescriptorContext<'_> {
         self.descriptors.get(type_pos.0 as usize)
             .ok_or(DescriptorMismatch::InvalidDescriptor)
     }
+    /// Rust type the value at `type_pos` is decoded into
+    ///
+    /// See [`descriptors::rust_type`] for details.
+    pub fn rust_type(&self, type_pos: TypePos) -> String {
+        descriptors::rust_type(self.descriptors, type_pos)
+    }
     pub fn wrong_type(&self, descriptor: &Descriptor, expected: &str)
         -> DescriptorMismatch
     {
+        let rust_type = descriptors::rust_type_of(self.descriptors, descriptor);
+        let unexpected = match descriptor {
+            Descriptor::BaseScalar(d) => match scalar_name(&d.id) {
+                Some(name) => format!("{} (`{}`)", name, rust_type),
+                None => format!("`{}`", rust_type),
+            },
+            _ => format!("`{}`", rust_type),
+        };
         DescriptorMismatch::WrongType {
-            // TODO(tailhook) human-readable type description
-            unexpected: format!("{:?}", descriptor),
+            unexpected,
             expected: expected.into(),
         }
     }
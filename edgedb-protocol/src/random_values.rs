@@ -0,0 +1,415 @@
+/*!
+Random values conforming to type descriptors
+
+This module is enabled by the `random-values` feature. It's useful for
+property testing of codecs and of [`Queryable`](crate::queryable::Queryable)
+implementations: values generated for the output descriptor of a query can
+be encoded and then decoded into the user type.
+
+Generator accepts any [`rand::Rng`], so it can be driven by the test runner
+of [proptest](https://docs.rs/proptest) too:
+
+```rust,ignore
+proptest! {
+    #[test]
+    fn decode(seed: u64) {
+        let gen = ValueGenerator::for_output(&desc);
+        let value = gen.generate(&mut StdRng::seed_from_u64(seed))?;
+        // encode and decode the value
+    }
+}
+```
+
+Floating point values are never `NaN`, so generated values can be compared
+after a round trip.
+*/
+
+use std::cmp::Ordering;
+
+use rand::Rng;
+use rand::seq::SliceRandom;
+use snafu::OptionExt;
+
+use crate::codec::{self, EnumValue, NamedTupleShape, ObjectShape};
+use crate::common::Cardinality;
+use crate::descriptors::{Descriptor, TypePos, ShapeElement};
+use crate::descriptors::{InputTypedesc, OutputTypedesc};
+use crate::errors::{self, CodecError};
+use crate::model::{BigInt, Decimal, ConfigMemory, Range};
+use crate::model::{Datetime, LocalDatetime, LocalDate, LocalTime};
+use crate::model::{Duration, RelativeDuration, DateDuration, Uuid};
+use crate::value::{Value, SparseObject};
+
+const MICROS_PER_DAY: u64 = 86_400_000_000;
+
+/// Generator of random values of the type described by the descriptors
+///
+/// Generated values are well-formed, i.e. can be encoded by the codec built
+/// from the same descriptors, and decode into an equal value.
+#[derive(Debug, Clone)]
+pub struct ValueGenerator<'a> {
+    descriptors: &'a [Descriptor],
+    root_pos: Option<TypePos>,
+    max_len: usize,
+}
+
+impl<'a> ValueGenerator<'a> {
+    /// Generator for values described by an output descriptor
+    pub fn for_output(desc: &'a OutputTypedesc) -> ValueGenerator<'a> {
+        ValueGenerator::new(desc.descriptors(), desc.root_pos())
+    }
+    /// Generator for query arguments described by an input descriptor
+    pub fn for_input(desc: &'a InputTypedesc) -> ValueGenerator<'a> {
+        ValueGenerator::new(desc.descriptors(), desc.root_pos())
+    }
+    /// Generator for the type at `root_pos` of the descriptor array
+    pub fn new(descriptors: &'a [Descriptor], root_pos: Option<TypePos>)
+        -> ValueGenerator<'a>
+    {
+        ValueGenerator {
+            descriptors,
+            root_pos,
+            max_len: 4,
+        }
+    }
+    /// Maximum number of elements in sets and arrays (default is `4`)
+    ///
+    /// Strings and bytes are limited to four times this length.
+    pub fn max_len(mut self, max_len: usize) -> ValueGenerator<'a> {
+        self.max_len = max_len;
+        self
+    }
+    /// Generate a random value of the root type
+    ///
+    /// Returns [`Value::Nothing`] if there is no root type.
+    pub fn generate<R: Rng + ?Sized>(&self, rng: &mut R)
+        -> Result<Value, CodecError>
+    {
+        match self.root_pos {
+            Some(pos) => self.generate_at(pos, rng),
+            None => Ok(Value::Nothing),
+        }
+    }
+    /// Generate a random value of the type at the specified position
+    pub fn generate_at<R: Rng + ?Sized>(&self, pos: TypePos, rng: &mut R)
+        -> Result<Value, CodecError>
+    {
+        let desc = self.descriptors.get(pos.0 as usize)
+            .context(errors::UnexpectedTypePos { position: pos.0 })?;
+        match desc {
+            Descriptor::BaseScalar(d) => scalar(&d.id, self.max_len, rng),
+            Descriptor::Scalar(d) => self.generate_at(d.base_type_pos, rng),
+            Descriptor::Enumeration(d) => {
+                let member = d.members.choose(rng)
+                    .context(errors::EmptyEnum)?;
+                Ok(Value::Enum(EnumValue::from(&member[..])))
+            }
+            Descriptor::Set(d) => {
+                let len = rng.gen_range(0..=self.max_len);
+                let items = (0..len)
+                    .map(|_| self.generate_at(d.type_pos, rng))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::Set(items))
+            }
+            Descriptor::Array(d) if d.dimensions.len() > 1 => {
+                // empty multi-dimensional arrays have no dimensions on
+                // the wire, so are decoded as empty one-dimensional ones
+                let dimensions = d.dimensions.iter()
+                    .map(|dim| match dim {
+                        Some(size) => *size as usize,
+                        None => rng.gen_range(1..=self.max_len.max(1)),
+                    })
+                    .collect::<Vec<_>>();
+                let elements = (0..dimensions.iter().product())
+                    .map(|_| self.generate_at(d.type_pos, rng))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::MultiArray { dimensions, elements })
+            }
+            Descriptor::Array(d) => {
+                let len = match d.dimensions.first() {
+                    Some(Some(size)) => *size as usize,
+                    _ => rng.gen_range(0..=self.max_len),
+                };
+                let items = (0..len)
+                    .map(|_| self.generate_at(d.type_pos, rng))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::Array(items))
+            }
+            Descriptor::Tuple(d) => {
+                let fields = d.element_types.iter()
+                    .map(|pos| self.generate_at(*pos, rng))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::Tuple(fields))
+            }
+            Descriptor::NamedTuple(d) => {
+                let fields = d.elements.iter()
+                    .map(|el| self.generate_at(el.type_pos, rng))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::NamedTuple {
+                    shape: NamedTupleShape::from(&d.elements[..]),
+                    fields,
+                })
+            }
+            Descriptor::ObjectShape(d) => {
+                let fields = d.elements.iter()
+                    .map(|el| self.object_field(el, rng))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::Object {
+                    shape: ObjectShape::from(&d.elements[..]),
+                    fields,
+                })
+            }
+            Descriptor::InputShape(d) => {
+                let fields = d.elements.iter()
+                    .map(|el| if rng.gen_bool(0.5) {
+                        self.object_field(el, rng).map(Some)
+                    } else {
+                        Ok(None)
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::SparseObject(SparseObject {
+                    shape: ObjectShape::from(&d.elements[..]),
+                    fields,
+                }))
+            }
+            Descriptor::Range(d) => self.range(d.type_pos, rng),
+            Descriptor::TypeAnnotation(_) => {
+                errors::UnexpectedTypePos { position: pos.0 }.fail()
+            }
+        }
+    }
+    fn object_field<R: Rng + ?Sized>(&self, el: &ShapeElement, rng: &mut R)
+        -> Result<Option<Value>, CodecError>
+    {
+        let optional = matches!(el.cardinality,
+            None | Some(Cardinality::AtMostOne));
+        if optional && !el.flag_implicit && rng.gen_bool(0.2) {
+            return Ok(None);
+        }
+        self.generate_at(el.type_pos, rng).map(Some)
+    }
+    fn range<R: Rng + ?Sized>(&self, element: TypePos, rng: &mut R)
+        -> Result<Value, CodecError>
+    {
+        if rng.gen_bool(0.1) {
+            // canonical empty range as sent by the server
+            return Ok(Value::Range(Range {
+                lower: None,
+                upper: None,
+                inc_lower: false,
+                inc_upper: false,
+                empty: true,
+            }));
+        }
+        let mut lower = if rng.gen_bool(0.8) {
+            Some(self.generate_at(element, rng)?)
+        } else {
+            None
+        };
+        let mut upper = if rng.gen_bool(0.8) {
+            Some(self.generate_at(element, rng)?)
+        } else {
+            None
+        };
+        if let (Some(l), Some(u)) = (&lower, &upper) {
+            match compare(l, u) {
+                Some(Ordering::Greater) => {
+                    std::mem::swap(&mut lower, &mut upper);
+                }
+                Some(_) => {}
+                // can't order bounds, so make range unbounded instead
+                None => upper = None,
+            }
+        }
+        Ok(Value::Range(Range {
+            inc_lower: lower.is_some() && rng.gen(),
+            inc_upper: upper.is_some() && rng.gen(),
+            lower: lower.map(Box::new),
+            upper: upper.map(Box::new),
+            empty: false,
+        }))
+    }
+}
+
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    use Value::*;
+    match (a, b) {
+        (Int16(a), Int16(b)) => a.partial_cmp(b),
+        (Int32(a), Int32(b)) => a.partial_cmp(b),
+        (Int64(a), Int64(b)) => a.partial_cmp(b),
+        (Float32(a), Float32(b)) => a.partial_cmp(b),
+        (Float64(a), Float64(b)) => a.partial_cmp(b),
+        (Datetime(a), Datetime(b)) => a.partial_cmp(b),
+        (LocalDatetime(a), LocalDatetime(b)) => a.partial_cmp(b),
+        (LocalDate(a), LocalDate(b)) => a.partial_cmp(b),
+        _ => None,
+    }
+}
+
+fn scalar<R: Rng + ?Sized>(id: &Uuid, max_len: usize, rng: &mut R)
+    -> Result<Value, CodecError>
+{
+    let value = match *id {
+        codec::STD_UUID => Value::Uuid(Uuid::from_u128(rng.gen())),
+        codec::STD_STR => {
+            let len = rng.gen_range(0..=max_len*4);
+            Value::Str((0..len).map(|_| rng.gen::<char>()).collect())
+        }
+        codec::STD_BYTES => {
+            let len = rng.gen_range(0..=max_len*4);
+            Value::Bytes((0..len).map(|_| rng.gen()).collect())
+        }
+        codec::STD_INT16 => Value::Int16(rng.gen()),
+        codec::STD_INT32 => Value::Int32(rng.gen()),
+        codec::STD_INT64 => Value::Int64(rng.gen()),
+        codec::STD_FLOAT32 => Value::Float32(float(rng) as f32),
+        codec::STD_FLOAT64 => Value::Float64(float(rng)),
+        codec::STD_DECIMAL => Value::Decimal(decimal(rng)),
+        codec::STD_BOOL => Value::Bool(rng.gen()),
+        codec::STD_DATETIME => Value::Datetime(Datetime {
+            micros: rng.gen_range(Datetime::MIN.micros..=Datetime::MAX.micros),
+        }),
+        codec::CAL_LOCAL_DATETIME => Value::LocalDatetime(LocalDatetime {
+            micros: rng.gen_range(
+                LocalDatetime::MIN.micros..=LocalDatetime::MAX.micros),
+        }),
+        codec::CAL_LOCAL_DATE => Value::LocalDate(LocalDate {
+            days: rng.gen_range(LocalDate::MIN.days..=LocalDate::MAX.days),
+        }),
+        codec::CAL_LOCAL_TIME => Value::LocalTime(LocalTime {
+            micros: rng.gen_range(0..MICROS_PER_DAY),
+        }),
+        codec::STD_DURATION => Value::Duration(Duration {
+            micros: rng.gen(),
+        }),
+        codec::CAL_RELATIVE_DURATION => {
+            Value::RelativeDuration(RelativeDuration {
+                micros: rng.gen(),
+                days: rng.gen(),
+                months: rng.gen(),
+            })
+        }
+        codec::CAL_DATE_DURATION => Value::DateDuration(DateDuration {
+            days: rng.gen(),
+            months: rng.gen(),
+        }),
+        codec::STD_JSON => {
+            let mut json = String::new();
+            write_json(&mut json, 3, rng);
+            Value::Json(json)
+        }
+        codec::STD_BIGINT => Value::BigInt(bigint(rng)),
+        codec::CFG_MEMORY => Value::ConfigMemory(
+            ConfigMemory(rng.gen_range(0..=i64::MAX))),
+        _ => return errors::UndefinedBaseScalar { uuid: *id }.fail(),
+    };
+    Ok(value)
+}
+
+fn float<R: Rng + ?Sized>(rng: &mut R) -> f64 {
+    match rng.gen_range(0..10) {
+        0 => f64::INFINITY,
+        1 => f64::NEG_INFINITY,
+        2 => 0.0,
+        3 => rng.gen_range(-1e6..1e6),
+        _ => loop {
+            // random bit pattern is distributed over the whole range
+            let value = f64::from_bits(rng.gen());
+            if value.is_finite() {
+                break value;
+            }
+        },
+    }
+}
+
+/// Base 10000 digits without leading and trailing zeros
+fn digits<R: Rng + ?Sized>(rng: &mut R) -> Vec<u16> {
+    let len = rng.gen_range(1..=6);
+    (0..len)
+        .map(|idx| if idx == 0 || idx == len-1 {
+            rng.gen_range(1..10000)
+        } else {
+            rng.gen_range(0..10000)
+        })
+        .collect()
+}
+
+fn bigint<R: Rng + ?Sized>(rng: &mut R) -> BigInt {
+    if rng.gen_bool(0.1) {
+        return BigInt::from(0);
+    }
+    let digits = digits(rng);
+    BigInt {
+        negative: rng.gen(),
+        weight: digits.len() as i16 - 1 + rng.gen_range(0..3),
+        digits,
+    }
+}
+
+fn decimal<R: Rng + ?Sized>(rng: &mut R) -> Decimal {
+    if rng.gen_bool(0.1) {
+        return Decimal {
+            negative: false,
+            weight: 0,
+            decimal_digits: 0,
+            digits: Vec::new(),
+        };
+    }
+    let digits = digits(rng);
+    let weight = rng.gen_range(-3..digits.len() as i16 + 2);
+    // all the digits after the decimal point are significant
+    let fraction_digits = (digits.len() as i16 - 1 - weight).max(0);
+    Decimal {
+        negative: rng.gen(),
+        weight,
+        decimal_digits: fraction_digits as u16 * 4,
+        digits,
+    }
+}
+
+fn write_json<R: Rng + ?Sized>(buf: &mut String, depth: u32, rng: &mut R) {
+    let kinds = if depth == 0 { 4 } else { 6 };
+    match rng.gen_range(0..kinds) {
+        0 => buf.push_str("null"),
+        1 => buf.push_str(if rng.gen() { "true" } else { "false" }),
+        2 => buf.push_str(&rng.gen_range(-1000000i64..1000000).to_string()),
+        3 => write_json_string(buf, rng),
+        4 => {
+            buf.push('[');
+            for idx in 0..rng.gen_range(0..4) {
+                if idx > 0 {
+                    buf.push_str(", ");
+                }
+                write_json(buf, depth - 1, rng);
+            }
+            buf.push(']');
+        }
+        _ => {
+            buf.push('{');
+            for idx in 0..rng.gen_range(0..4) {
+                if idx > 0 {
+                    buf.push_str(", ");
+                }
+                write_json_string(buf, rng);
+                buf.push_str(": ");
+                write_json(buf, depth - 1, rng);
+            }
+            buf.push('}');
+        }
+    }
+}
+
+fn write_json_string<R: Rng + ?Sized>(buf: &mut String, rng: &mut R) {
+    buf.push('"');
+    for _ in 0..rng.gen_range(0..8) {
+        match rng.gen::<char>() {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                buf.push_str(&format!("\\u{:04x}", c as u32));
+            }
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+}
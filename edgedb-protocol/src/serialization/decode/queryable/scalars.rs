@@ -51,12 +51,23 @@ impl DecodeScalar for Json {
     fn typename() -> &'static str { "std::json" }
 }
 
-/*
 impl DecodeScalar for Vec<u8> {
     fn uuid() -> Uuid { codec::STD_BYTES }
     fn typename() -> &'static str { "std::bytes" }
 }
-*/
+
+/// Shares memory with the message instead of copying, when decoded as a
+/// query result
+impl Queryable for bytes::Bytes {
+    fn decode(decoder: &Decoder, buf: &[u8]) -> Result<Self, DecodeError> {
+        Ok(decoder.bytes(buf))
+    }
+    fn check_descriptor(ctx: &DescriptorContext, type_pos: TypePos)
+        -> Result<(), DescriptorMismatch>
+    {
+        check_scalar(ctx, type_pos, codec::STD_BYTES, "std::bytes")
+    }
+}
 
 impl DecodeScalar for i16 {
     fn uuid() -> Uuid { codec::STD_INT16 }
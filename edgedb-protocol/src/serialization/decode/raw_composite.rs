@@ -19,6 +19,24 @@ impl<'t> DecodeTupleLike<'t> {
         Ok(elements)
     }
 
+    /// Decode an object that may lack the implicit `id` element
+    ///
+    /// Free objects (`SELECT { a := 1 }`) have no `id`, while objects of
+    /// schema types have it. `expected_count` is the number of elements
+    /// without `id`. Returns whether the `id` element is present, in which
+    /// case it's the first element after the type id and type name.
+    pub fn new_object_implicit_id(buf:&'t [u8], expected_count:usize)
+        -> Result<(Self, bool), DecodeError>
+    {
+        let elements = Self::new(buf)?;
+        let count = elements.inner.count();
+        if count == expected_count + 1 {
+            return Ok((elements, true));
+        }
+        ensure!(count == expected_count, errors::ObjectSizeMismatch);
+        Ok((elements, false))
+    }
+
     pub fn new_tuple(buf:&'t [u8], expected_count:usize) -> Result<Self, DecodeError> {
         let elements = Self::new(buf)?;
         ensure!(elements.inner.count() == expected_count, errors::TupleSizeMismatch);
@@ -109,6 +109,25 @@ impl ScalarArg for Json {
     }
 }
 
+#[cfg(feature = "with-serde")]
+impl<T> ScalarArg for crate::model::AsJson<T>
+    where T: serde::Serialize + Send + Sync,
+{
+    fn encode(&self, encoder: &mut Encoder)
+        -> Result<(), Error>
+    {
+        encoder.buf.reserve(1);
+        encoder.buf.put_u8(1);
+        serde_json::to_writer((&mut *encoder.buf).writer(), &self.0)
+            .map_err(ClientEncodingError::with_source)
+    }
+    fn check_descriptor(ctx: &DescriptorContext, pos: TypePos)
+        -> Result<(), Error>
+    {
+        check_scalar(ctx, pos, Json::uuid(), Json::typename())
+    }
+}
+
 impl<'t> RawCodec<'t> for Json {
     fn decode(mut buf: &[u8]) -> Result<Self, DecodeError> {
         ensure!(buf.remaining() >= 1, errors::Underflow);
@@ -331,6 +350,28 @@ impl ScalarArg for Vec<u8> {
     }
 }
 
+impl<'t> RawCodec<'t> for bytes::Bytes {
+    fn decode(buf: &[u8]) -> Result<Self, DecodeError> {
+        // only a slice is available here, `Queryable` implementation
+        // slices the message instead
+        Ok(bytes::Bytes::copy_from_slice(buf))
+    }
+}
+
+impl ScalarArg for bytes::Bytes {
+    fn encode(&self, encoder: &mut Encoder)
+        -> Result<(), Error>
+    {
+        encoder.buf.extend(&self[..]);
+        Ok(())
+    }
+    fn check_descriptor(ctx: &DescriptorContext, pos: TypePos)
+        -> Result<(), Error>
+    {
+        check_scalar(ctx, pos, codec::STD_BYTES, "std::bytes")
+    }
+}
+
 impl<'t> RawCodec<'t> for Decimal {
     fn decode(mut buf: &[u8]) -> Result<Self, DecodeError> {
         ensure!(buf.remaining() >= 8, errors::Underflow);
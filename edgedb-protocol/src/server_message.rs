@@ -10,7 +10,7 @@ use snafu::{OptionExt, ensure};
 use crate::features::ProtocolVersion;
 use crate::errors::{self, EncodeError, DecodeError};
 use crate::encoding::{Input, Output, KeyValues, Annotations, Decode, Encode};
-use crate::descriptors::{OutputTypedesc, InputTypedesc, Descriptor, TypePos};
+use crate::descriptors::{OutputTypedesc, InputTypedesc, Descriptor};
 pub use crate::common::Cardinality;
 use crate::common::Capabilities;
 
@@ -231,22 +231,8 @@ impl CommandDataDescription0 {
                 item => descriptors.push(item),
             }
         }
-        let root_id = self.input_typedesc_id.clone();
-        let root_pos = if root_id == Uuid::from_u128(0) {
-            None
-        } else {
-            let idx = descriptors.iter().position(|x| *x.id() == root_id)
-                .context(errors::UuidNotFound { uuid: root_id })?;
-            let pos = idx.try_into().ok()
-                .context(errors::TooManyDescriptors { index: idx })?;
-            Some(TypePos(pos))
-        };
-        Ok(InputTypedesc {
-            array: descriptors,
-            proto: self.proto.clone(),
-            root_id,
-            root_pos,
-        })
+        InputTypedesc::new(self.proto.clone(), descriptors,
+                           self.input_typedesc_id.clone())
     }
 }
 
@@ -270,22 +256,8 @@ impl CommandDataDescription1 {
                 item => descriptors.push(item),
             }
         }
-        let root_id = self.input_typedesc_id.clone();
-        let root_pos = if root_id == Uuid::from_u128(0) {
-            None
-        } else {
-            let idx = descriptors.iter().position(|x| *x.id() == root_id)
-                .context(errors::UuidNotFound { uuid: root_id })?;
-            let pos = idx.try_into().ok()
-                .context(errors::TooManyDescriptors { index: idx })?;
-            Some(TypePos(pos))
-        };
-        Ok(InputTypedesc {
-            array: descriptors,
-            proto: self.proto.clone(),
-            root_id,
-            root_pos,
-        })
+        InputTypedesc::new(self.proto.clone(), descriptors,
+                           self.input_typedesc_id.clone())
     }
 }
 
@@ -358,6 +330,7 @@ impl ServerMessage {
     /// in the buffer or if extra data is present.
     pub fn decode(buf: &mut Input) -> Result<ServerMessage, DecodeError> {
         use self::ServerMessage as M;
+        ensure!(buf.remaining() >= 5, errors::Underflow);
         let ref mut data = buf.slice(5..);
         let result = match buf[0] {
             0x76 => ServerHandshake::decode(data).map(M::ServerHandshake)?,
@@ -437,6 +410,7 @@ impl Decode for ServerHandshake {
             let num_headers = buf.get_u16();
             let mut headers = HashMap::new();
             for _ in 0..num_headers {
+                ensure!(buf.remaining() >= 4, errors::Underflow);
                 headers.insert(buf.get_u16(), Bytes::decode(buf)?);
             }
             extensions.insert(name, headers);
@@ -561,6 +535,9 @@ impl Decode for Authentication {
             0x0A => {
                 ensure!(buf.remaining() >= 4, errors::Underflow);
                 let num_methods = buf.get_u32() as usize;
+                // each method is at least 4 bytes, so don't preallocate
+                // more than the buffer can possibly contain
+                ensure!(buf.remaining() / 4 >= num_methods, errors::Underflow);
                 let mut methods = Vec::with_capacity(num_methods);
                 for _ in 0..num_methods {
                     methods.push(String::decode(buf)?);
@@ -758,6 +735,7 @@ impl Decode for CommandComplete1 {
         for _ in 0..num_annotations {
             annotations.insert(String::decode(buf)?, String::decode(buf)?);
         }
+        ensure!(buf.remaining() >= 8, errors::Underflow);
         let capabilities = unsafe {
             // extra flags sent from server are okay
             Capabilities::from_bits_unchecked(buf.get_u64())
@@ -966,6 +944,7 @@ impl Decode for Data {
     fn decode(buf: &mut Input) -> Result<Self, DecodeError> {
         ensure!(buf.remaining() >= 2, errors::Underflow);
         let num_chunks = buf.get_u16() as usize;
+        ensure!(buf.remaining() >= 4*num_chunks, errors::Underflow);
         let mut data = Vec::with_capacity(num_chunks);
         for _ in 0..num_chunks {
             data.push(Bytes::decode(buf)?);
@@ -0,0 +1,371 @@
+/*!
+Canonical wire-format test vectors
+
+This module is enabled by the `test-vectors` feature. It contains messages,
+type descriptors and values along with their exact encoding, so that
+crates implementing their own codecs or transports can check that they
+produce and accept the same bytes as this crate does.
+
+Vectors are only ever added, names are never reused for different data.
+
+```rust,ignore
+use edgedb_protocol::test_vectors::{server_messages, assert_wire_eq};
+
+for vector in server_messages() {
+    let encoded = my_encoder(&vector.message);
+    assert_wire_eq(vector.name, &encoded, &vector.bytes);
+}
+```
+*/
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use bytes::{Bytes, BytesMut};
+
+use crate::client_message::{ClientMessage, ClientHandshake, ExecuteScript};
+use crate::client_message::{Parse, Execute1};
+use crate::codec::build_codec;
+use crate::common::{Capabilities, Cardinality, CompilationFlags, IoFormat};
+use crate::descriptors::{Descriptor, TypePos};
+use crate::descriptors::{BaseScalarTypeDescriptor, TupleTypeDescriptor};
+use crate::descriptors::{ArrayTypeDescriptor};
+use crate::encoding::{Input, Output};
+use crate::features::ProtocolVersion;
+use crate::model::Uuid;
+use crate::server_message::{ServerMessage, ServerHandshake};
+use crate::server_message::{ReadyForCommand, TransactionState};
+use crate::server_message::{CommandComplete1, ServerKeyData, Data};
+use crate::server_message::{Authentication};
+use crate::value::Value;
+
+
+/// Server message with its encoding
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ServerMessageVector {
+    /// Unique name of the vector
+    pub name: &'static str,
+    /// Protocol version used to encode the message
+    pub proto: ProtocolVersion,
+    pub message: ServerMessage,
+    pub bytes: Bytes,
+}
+
+/// Client message with its encoding
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ClientMessageVector {
+    /// Unique name of the vector
+    pub name: &'static str,
+    /// Protocol version used to encode the message
+    pub proto: ProtocolVersion,
+    pub message: ClientMessage,
+    pub bytes: Bytes,
+}
+
+/// Type descriptors with their encoding
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct DescriptorVector {
+    /// Unique name of the vector
+    pub name: &'static str,
+    /// Protocol version used to encode the descriptors
+    pub proto: ProtocolVersion,
+    pub descriptors: Vec<Descriptor>,
+    pub bytes: Bytes,
+}
+
+/// Value with its encoding according to the type descriptors
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ValueVector {
+    /// Unique name of the vector
+    pub name: &'static str,
+    pub descriptors: Vec<Descriptor>,
+    /// Position of the type of the value in `descriptors`
+    pub root_pos: TypePos,
+    pub value: Value,
+    pub bytes: Bytes,
+}
+
+fn base_scalar(id: u128) -> Descriptor {
+    Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+        id: Uuid::from_u128(id),
+    })
+}
+
+/// Vectors for messages sent by the server
+pub fn server_messages() -> Vec<ServerMessageVector> {
+    let v1 = ProtocolVersion::new(1, 0);
+    let vector = |name, proto: &ProtocolVersion, message, bytes| {
+        ServerMessageVector {
+            name,
+            proto: proto.clone(),
+            message,
+            bytes: Bytes::from_static(bytes),
+        }
+    };
+    vec![
+        vector("server_handshake", &v1,
+            ServerMessage::ServerHandshake(ServerHandshake {
+                major_ver: 1,
+                minor_ver: 0,
+                extensions: HashMap::new(),
+            }),
+            b"v\0\0\0\n\0\x01\0\0\0\0"),
+        vector("authentication_ok", &v1,
+            ServerMessage::Authentication(Authentication::Ok),
+            b"R\0\0\0\x08\0\0\0\0"),
+        vector("server_key_data", &v1,
+            ServerMessage::ServerKeyData(ServerKeyData {
+                data: [0u8; 32],
+            }),
+            b"K\0\0\0$\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\
+              \0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0"),
+        vector("ready_for_command", &v1,
+            ServerMessage::ReadyForCommand(ReadyForCommand {
+                transaction_state: TransactionState::NotInTransaction,
+                headers: HashMap::new(),
+            }),
+            b"Z\0\0\0\x07\0\0I"),
+        vector("command_complete1", &v1,
+            ServerMessage::CommandComplete1(CommandComplete1 {
+                annotations: HashMap::new(),
+                capabilities: Capabilities::MODIFICATIONS,
+                status_data: Bytes::from_static(b"okay"),
+                state_typedesc_id: Uuid::from_u128(0),
+                state_data: Bytes::new(),
+            }),
+            b"C\0\0\0*\0\0\0\0\0\0\0\0\0\x01\0\0\0\x04okay\
+              \0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0"),
+        vector("data", &v1,
+            ServerMessage::Data(Data {
+                data: vec![Bytes::from_static(b"\0\0\0\0\0\0\0\x01")],
+            }),
+            b"D\0\0\0\x12\0\x01\0\0\0\x08\0\0\0\0\0\0\0\x01"),
+    ]
+}
+
+/// Vectors for messages sent by the client
+pub fn client_messages() -> Vec<ClientMessageVector> {
+    let v1 = ProtocolVersion::new(1, 0);
+    let vector = |name, proto: &ProtocolVersion, message, bytes| {
+        ClientMessageVector {
+            name,
+            proto: proto.clone(),
+            message,
+            bytes: Bytes::from_static(bytes),
+        }
+    };
+    vec![
+        vector("client_handshake", &v1,
+            ClientMessage::ClientHandshake(ClientHandshake {
+                major_ver: 1,
+                minor_ver: 2,
+                params: HashMap::new(),
+                extensions: HashMap::new(),
+            }),
+            b"V\0\0\0\x0c\0\x01\0\x02\0\0\0\0"),
+        vector("execute_script", &v1,
+            ClientMessage::ExecuteScript(ExecuteScript {
+                headers: HashMap::new(),
+                script_text: String::from("START TRANSACTION"),
+            }),
+            b"Q\0\0\0\x1b\0\0\0\0\0\x11START TRANSACTION"),
+        vector("parse", &v1,
+            ClientMessage::Parse(Parse {
+                annotations: HashMap::new(),
+                allowed_capabilities: Capabilities::MODIFICATIONS,
+                compilation_flags: CompilationFlags::INJECT_OUTPUT_TYPE_NAMES,
+                implicit_limit: Some(77),
+                output_format: IoFormat::Binary,
+                expected_cardinality: Cardinality::AtMostOne,
+                command_text: String::from("SELECT 1;"),
+                state_typedesc_id: Uuid::from_u128(0),
+                state_data: Bytes::new(),
+            }),
+            b"P\0\0\0A\0\0\0\0\0\0\0\0\0\x01\0\0\0\0\0\0\0\x02\0\0\0\0\0\0\0Mbo\
+              \0\0\0\tSELECT 1;\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0"),
+        vector("execute1", &v1,
+            ClientMessage::Execute1(Execute1 {
+                annotations: HashMap::new(),
+                allowed_capabilities: Capabilities::MODIFICATIONS,
+                compilation_flags: CompilationFlags::INJECT_OUTPUT_TYPE_NAMES,
+                implicit_limit: Some(77),
+                output_format: IoFormat::Binary,
+                expected_cardinality: Cardinality::AtMostOne,
+                command_text: String::from("SELECT 1;"),
+                state_typedesc_id: Uuid::from_u128(0),
+                state_data: Bytes::new(),
+                input_typedesc_id: Uuid::from_u128(123),
+                output_typedesc_id: Uuid::from_u128(456),
+                arguments: Bytes::new(),
+            }),
+            b"O\0\0\0e\0\0\0\0\0\0\0\0\0\x01\0\0\0\0\0\0\0\x02\0\0\0\0\0\0\0Mbo\
+              \0\0\0\tSELECT 1;\
+              \0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\
+              \0\0\0{\0\0\0\0\0\0\0\0\0\0\0\0\0\0\x01\xc8\0\0\0\0"),
+        vector("sync", &v1, ClientMessage::Sync, b"S\0\0\0\x04"),
+        vector("terminate", &v1, ClientMessage::Terminate, b"X\0\0\0\x04"),
+    ]
+}
+
+/// Vectors for type descriptors
+pub fn descriptors() -> Vec<DescriptorVector> {
+    let v1 = ProtocolVersion::new(1, 0);
+    let vector = |name, proto: &ProtocolVersion, descriptors, bytes| {
+        DescriptorVector {
+            name,
+            proto: proto.clone(),
+            descriptors,
+            bytes: Bytes::from_static(bytes),
+        }
+    };
+    vec![
+        vector("empty_tuple", &v1,
+            vec![
+                Descriptor::Tuple(TupleTypeDescriptor {
+                    id: Uuid::from_u128(0xFF),
+                    element_types: Vec::new(),
+                }),
+            ],
+            b"\x04\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\xff\0\0"),
+        vector("one_tuple", &v1,
+            vec![
+                base_scalar(0x105),
+                Descriptor::Tuple(TupleTypeDescriptor {
+                    id: Uuid::from_u128(
+                        0x1c794765_7325_8953_6103_e7877645ad39),
+                    element_types: vec![TypePos(0)],
+                }),
+            ],
+            b"\x02\0\0\0\0\0\0\0\0\0\0\0\0\0\0\x01\x05\
+              \x04\x1cyGes%\x89Sa\x03\xe7\x87vE\xad9\0\x01\0\0"),
+    ]
+}
+
+/// Vectors for values
+pub fn values() -> Vec<ValueVector> {
+    let vector = |name, descriptors, root_pos, value, bytes| {
+        ValueVector {
+            name,
+            descriptors,
+            root_pos: TypePos(root_pos),
+            value,
+            bytes: Bytes::from_static(bytes),
+        }
+    };
+    let int64_array = vec![
+        base_scalar(0x105),
+        Descriptor::Array(ArrayTypeDescriptor {
+            id: Uuid::from_u128(0xb0105467_a177_635f_e207_0a21867f9be0),
+            type_pos: TypePos(0),
+            dimensions: vec![None],
+        }),
+    ];
+    vec![
+        vector("int16", vec![base_scalar(0x103)], 0,
+            Value::Int16(-1), b"\xff\xff"),
+        vector("int32", vec![base_scalar(0x104)], 0,
+            Value::Int32(0x105), b"\0\0\x01\x05"),
+        vector("int64", vec![base_scalar(0x105)], 0,
+            Value::Int64(i64::MIN), b"\x80\0\0\0\0\0\0\0"),
+        vector("str", vec![base_scalar(0x101)], 0,
+            Value::Str(String::from("привет")),
+            b"\xd0\xbf\xd1\x80\xd0\xb8\xd0\xb2\xd0\xb5\xd1\x82"),
+        vector("bool", vec![base_scalar(0x109)], 0,
+            Value::Bool(true), b"\x01"),
+        vector("uuid", vec![base_scalar(0x100)], 0,
+            Value::Uuid(Uuid::from_u128(
+                0x4928cc1e_2065_11ea_8848_7b53a6adb383)),
+            b"I(\xcc\x1e e\x11\xea\x88H{S\xa6\xad\xb3\x83"),
+        vector("array", int64_array.clone(), 1,
+            Value::Array(vec![Value::Int64(1), Value::Int64(2)]),
+            b"\0\0\0\x01\0\0\0\0\0\0\0\0\0\0\0\x02\0\0\0\x01\
+              \0\0\0\x08\0\0\0\0\0\0\0\x01\0\0\0\x08\0\0\0\0\0\0\0\x02"),
+        vector("empty_array", int64_array, 1,
+            Value::Array(Vec::new()),
+            b"\0\0\0\0\0\0\0\0\0\0\0\0"),
+    ]
+}
+
+/// Compare encoded data, panicking with a readable dump on mismatch
+///
+/// The panic message contains the name of the vector, the offset of the
+/// first differing byte, and hex dumps of both buffers.
+pub fn assert_wire_eq(name: &str, actual: &[u8], expected: &[u8]) {
+    if actual == expected {
+        return;
+    }
+    let offset = actual.iter().zip(expected)
+        .position(|(a, b)| a != b)
+        .unwrap_or_else(|| actual.len().min(expected.len()));
+    panic!("wire data mismatch in {:?} at byte {} \
+            (actual {} bytes, expected {} bytes)\n\
+            actual:   {}\n\
+            expected: {}",
+           name, offset, actual.len(), expected.len(),
+           hex(actual), hex(expected));
+}
+
+fn hex(data: &[u8]) -> String {
+    let mut buf = String::with_capacity(data.len()*3);
+    for (idx, byte) in data.iter().enumerate() {
+        if idx > 0 {
+            buf.push(' ');
+        }
+        write!(&mut buf, "{:02x}", byte).unwrap();
+    }
+    buf
+}
+
+/// Check that this crate encodes and decodes the message as in the vector
+pub fn assert_server_message(vector: &ServerMessageVector) {
+    let mut buf = BytesMut::new();
+    vector.message.encode(&mut Output::new(&vector.proto, &mut buf))
+        .unwrap_or_else(|e| panic!("{}: cannot encode: {}", vector.name, e));
+    assert_wire_eq(vector.name, &buf, &vector.bytes);
+    let message = ServerMessage::decode(
+        &mut Input::new(vector.proto.clone(), vector.bytes.clone()))
+        .unwrap_or_else(|e| panic!("{}: cannot decode: {}", vector.name, e));
+    assert_eq!(message, vector.message, "{}", vector.name);
+}
+
+/// Check that this crate encodes and decodes the message as in the vector
+pub fn assert_client_message(vector: &ClientMessageVector) {
+    let mut buf = BytesMut::new();
+    vector.message.encode(&mut Output::new(&vector.proto, &mut buf))
+        .unwrap_or_else(|e| panic!("{}: cannot encode: {}", vector.name, e));
+    assert_wire_eq(vector.name, &buf, &vector.bytes);
+    let message = ClientMessage::decode(
+        &mut Input::new(vector.proto.clone(), vector.bytes.clone()))
+        .unwrap_or_else(|e| panic!("{}: cannot decode: {}", vector.name, e));
+    assert_eq!(message, vector.message, "{}", vector.name);
+}
+
+/// Check that this crate decodes descriptors as in the vector
+pub fn assert_descriptors(vector: &DescriptorVector) {
+    let mut input = Input::new(vector.proto.clone(), vector.bytes.clone());
+    let mut descriptors = Vec::new();
+    while !input.is_empty() {
+        descriptors.push(Descriptor::decode(&mut input)
+            .unwrap_or_else(|e| {
+                panic!("{}: cannot decode: {}", vector.name, e)
+            }));
+    }
+    assert_eq!(descriptors, vector.descriptors, "{}", vector.name);
+}
+
+/// Check that this crate encodes and decodes the value as in the vector
+pub fn assert_value(vector: &ValueVector) {
+    let codec = build_codec(Some(vector.root_pos), &vector.descriptors)
+        .unwrap_or_else(|e| panic!("{}: cannot build codec: {}",
+                                   vector.name, e));
+    let mut buf = BytesMut::new();
+    codec.encode(&mut buf, &vector.value)
+        .unwrap_or_else(|e| panic!("{}: cannot encode: {}", vector.name, e));
+    assert_wire_eq(vector.name, &buf, &vector.bytes);
+    let value = codec.decode(&vector.bytes)
+        .unwrap_or_else(|e| panic!("{}: cannot decode: {}", vector.name, e));
+    assert_eq!(value, vector.value, "{}", vector.name);
+}
@@ -1,3 +1,4 @@
+use std::cmp::Ordering;
 use std::iter::IntoIterator;
 
 use crate::codec::{NamedTupleShape, ObjectShape, EnumValue, ShapeElement};
@@ -6,7 +7,13 @@ use crate::model::{BigInt, Decimal, Uuid, ConfigMemory, Range};
 use crate::model::{LocalDatetime, LocalDate, LocalTime, Duration, Datetime};
 use crate::model::{RelativeDuration, DateDuration};
 
+/// Dynamically typed value of any EdgeDB type
+///
+/// New variants are added when support for new types is added (like
+/// [`MultiArray`](Value::MultiArray) or [`Unknown`](Value::Unknown)), so
+/// matching on this enum requires a wildcard arm.
 #[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum Value {
     Nothing,
     Uuid(Uuid),
@@ -35,8 +42,21 @@ pub enum Value {
     Tuple(Vec<Value>),
     NamedTuple { shape: NamedTupleShape, fields: Vec<Value> },
     Array(Vec<Value>),
+    /// Array with more than one dimension
+    ///
+    /// Elements are stored in row-major order, so the number of elements
+    /// must be equal to the product of all dimensions.
+    MultiArray { dimensions: Vec<usize>, elements: Vec<Value> },
     Enum(EnumValue),
     Range(Range<Box<Value>>),
+    /// Value of a scalar type that this library doesn't know
+    ///
+    /// Only produced by codecs built with
+    /// [`build_codec_with_fallback`](crate::codec::build_codec_with_fallback)
+    /// and contains the type id of the base scalar and its raw data. Types
+    /// that get supported in future versions are decoded into new variants
+    /// instead, so don't rely on a particular type being `Unknown`.
+    Unknown { type_id: Uuid, data: Vec<u8> },
 }
 
 #[derive(Clone, Debug)]
@@ -45,6 +65,16 @@ pub struct SparseObject {
     pub(crate) fields: Vec<Option<Option<Value>>>,
 }
 
+/// Object without a type, e.g. result of `SELECT { a := 1, b := "x" }`
+///
+/// Fields are accessed by name. Implicit fields (such as `id` or
+/// `__tid__`), if any, are not exposed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FreeObject {
+    shape: ObjectShape,
+    fields: Vec<Option<Value>>,
+}
+
 impl Value {
     pub fn kind(&self) -> &'static str {
         use Value::*;
@@ -76,13 +106,249 @@ impl Value {
             Tuple(..) => "tuple",
             NamedTuple { .. } => "named_tuple",
             Array(..) => "array",
+            MultiArray { .. } => "array",
             Enum(..) => "enum",
             Range{..} => "range",
+            Unknown { .. } => "unknown",
         }
     }
     pub fn empty_tuple() -> Value {
         Value::Tuple(Vec::new())
     }
+    /// Converts value into a canonical form
+    ///
+    /// This makes values that mean the same thing equal with `==`:
+    ///
+    /// * insignificant zeros of decimals and big integers are removed
+    ///   (i.e. `1.50n` becomes `1.5n`, negative zero becomes zero);
+    /// * negative zero floats become positive and all NaNs are the same.
+    ///
+    /// Nested values are normalized too. Date and time values are always
+    /// stored with microsecond precision, so they need no normalization.
+    /// Order of elements in sets is kept, see [`sort_sets`](Value::sort_sets).
+    pub fn normalize(&mut self) {
+        use Value::*;
+        match self {
+            BigInt(v) => *v = v.canonical(),
+            Decimal(v) => *v = v.canonical(),
+            Float32(v) if *v == 0.0 => *v = 0.0,
+            Float32(v) if v.is_nan() => *v = f32::NAN,
+            Float64(v) if *v == 0.0 => *v = 0.0,
+            Float64(v) if v.is_nan() => *v = f64::NAN,
+            Set(items) | Tuple(items) | Array(items)
+            | NamedTuple { fields: items, .. }
+            | MultiArray { elements: items, .. }
+            => items.iter_mut().for_each(Value::normalize),
+            Object { fields, .. } => {
+                fields.iter_mut().flatten().for_each(Value::normalize);
+            }
+            SparseObject(obj) => {
+                obj.fields.iter_mut().flatten().flatten()
+                    .for_each(Value::normalize);
+            }
+            Range(rng) if rng.empty => *rng = crate::model::Range::empty(),
+            Range(rng) => {
+                rng.lower.iter_mut().chain(rng.upper.iter_mut())
+                    .for_each(|v| v.normalize());
+            }
+            _ => {}
+        }
+    }
+    /// Sorts elements of the sets, including nested ones
+    ///
+    /// Sets returned by the database have no particular order unless
+    /// `ORDER BY` is used, so sorting them allows comparing results
+    /// regardless of the order. Order used is not meaningful by itself,
+    /// but it's the same for equal values. Arrays are not sorted.
+    pub fn sort_sets(&mut self) {
+        use Value::*;
+        match self {
+            Set(items) => {
+                items.iter_mut().for_each(Value::sort_sets);
+                items.sort_by(compare);
+            }
+            Tuple(items) | Array(items)
+            | NamedTuple { fields: items, .. }
+            | MultiArray { elements: items, .. }
+            => items.iter_mut().for_each(Value::sort_sets),
+            Object { fields, .. } => {
+                fields.iter_mut().flatten().for_each(Value::sort_sets);
+            }
+            SparseObject(obj) => {
+                obj.fields.iter_mut().flatten().flatten()
+                    .for_each(Value::sort_sets);
+            }
+            Range(rng) => {
+                rng.lower.iter_mut().chain(rng.upper.iter_mut())
+                    .for_each(|v| v.sort_sets());
+            }
+            _ => {}
+        }
+    }
+    /// Compares values by meaning rather than by representation
+    ///
+    /// Values are equal if they are equal after
+    /// [normalization](Value::normalize). Also, shapes of objects and named
+    /// tuples are compared only by names of the elements, so that values
+    /// coming from different queries (or constructed manually) can be
+    /// compared. Sets are compared in order, use
+    /// [`sort_sets`](Value::sort_sets) on both values first if the order
+    /// doesn't matter.
+    pub fn semantic_eq(&self, other: &Value) -> bool {
+        let mut a = self.clone();
+        let mut b = other.clone();
+        a.normalize();
+        b.normalize();
+        compare(&a, &b) == Ordering::Equal
+    }
+}
+
+/// Total order of the values, only meaningful for normalized values
+fn compare(a: &Value, b: &Value) -> Ordering {
+    use Value::*;
+    match (a, b) {
+        (Nothing, Nothing) => Ordering::Equal,
+        (Uuid(a), Uuid(b)) => a.cmp(b),
+        (Str(a), Str(b)) | (Json(a), Json(b)) => a.cmp(b),
+        (Bytes(a), Bytes(b)) => a.cmp(b),
+        (Int16(a), Int16(b)) => a.cmp(b),
+        (Int32(a), Int32(b)) => a.cmp(b),
+        (Int64(a), Int64(b)) => a.cmp(b),
+        (Float32(a), Float32(b)) => compare_float(*a as f64, *b as f64),
+        (Float64(a), Float64(b)) => compare_float(*a, *b),
+        (BigInt(a), BigInt(b)) => {
+            (a.negative, a.weight, &a.digits)
+                .cmp(&(b.negative, b.weight, &b.digits))
+        }
+        (ConfigMemory(a), ConfigMemory(b)) => a.0.cmp(&b.0),
+        (Decimal(a), Decimal(b)) => {
+            (a.negative, a.weight, a.decimal_digits, &a.digits)
+                .cmp(&(b.negative, b.weight, b.decimal_digits, &b.digits))
+        }
+        (Bool(a), Bool(b)) => a.cmp(b),
+        (Datetime(a), Datetime(b)) => a.cmp(b),
+        (LocalDatetime(a), LocalDatetime(b)) => a.cmp(b),
+        (LocalDate(a), LocalDate(b)) => a.cmp(b),
+        (LocalTime(a), LocalTime(b)) => a.cmp(b),
+        (Duration(a), Duration(b)) => a.cmp(b),
+        (RelativeDuration(a), RelativeDuration(b)) => a.cmp(b),
+        (DateDuration(a), DateDuration(b)) => a.cmp(b),
+        (Set(a), Set(b)) | (Tuple(a), Tuple(b)) | (Array(a), Array(b)) => {
+            compare_seq(a.iter().map(Some), b.iter().map(Some))
+        }
+        (Object { shape: sa, fields: a }, Object { shape: sb, fields: b })
+        => {
+            let names_a = sa.elements.iter().map(|e| &e.name);
+            let names_b = sb.elements.iter().map(|e| &e.name);
+            names_a.cmp(names_b).then_with(|| {
+                compare_seq(a.iter().map(Option::as_ref),
+                            b.iter().map(Option::as_ref))
+            })
+        }
+        (SparseObject(a), SparseObject(b)) => {
+            let mut a = a.pairs().collect::<Vec<_>>();
+            let mut b = b.pairs().collect::<Vec<_>>();
+            a.sort_by_key(|(name, _)| *name);
+            b.sort_by_key(|(name, _)| *name);
+            a.iter().map(|(name, _)| name)
+                .cmp(b.iter().map(|(name, _)| name))
+                .then_with(|| {
+                    compare_seq(a.iter().map(|(_, v)| *v),
+                                b.iter().map(|(_, v)| *v))
+                })
+        }
+        (NamedTuple { shape: sa, fields: a },
+         NamedTuple { shape: sb, fields: b })
+        => {
+            let names_a = sa.elements.iter().map(|e| &e.name);
+            let names_b = sb.elements.iter().map(|e| &e.name);
+            names_a.cmp(names_b).then_with(|| {
+                compare_seq(a.iter().map(Some), b.iter().map(Some))
+            })
+        }
+        (MultiArray { dimensions: da, elements: a },
+         MultiArray { dimensions: db, elements: b })
+        => {
+            da.cmp(db).then_with(|| {
+                compare_seq(a.iter().map(Some), b.iter().map(Some))
+            })
+        }
+        (Enum(a), Enum(b)) => (**a).cmp(&**b),
+        (Range(a), Range(b)) => {
+            (a.empty, a.inc_lower, a.inc_upper)
+                .cmp(&(b.empty, b.inc_lower, b.inc_upper))
+                .then_with(|| compare_seq(
+                    vec![a.lower.as_deref(), a.upper.as_deref()].into_iter(),
+                    vec![b.lower.as_deref(), b.upper.as_deref()].into_iter(),
+                ))
+        }
+        (Unknown { type_id: ta, data: a }, Unknown { type_id: tb, data: b })
+        => (ta, a).cmp(&(tb, b)),
+        (a, b) => rank(a).cmp(&rank(b)),
+    }
+}
+
+fn compare_float(a: f64, b: f64) -> Ordering {
+    // NaN goes last, all NaNs are equal
+    a.partial_cmp(&b).unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+}
+
+fn compare_seq<'a>(mut a: impl Iterator<Item=Option<&'a Value>>,
+                   mut b: impl Iterator<Item=Option<&'a Value>>)
+    -> Ordering
+{
+    loop {
+        let ord = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(None), Some(None)) => Ordering::Equal,
+            (Some(None), Some(Some(_))) => Ordering::Less,
+            (Some(Some(_)), Some(None)) => Ordering::Greater,
+            (Some(Some(a)), Some(Some(b))) => compare(a, b),
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+}
+
+/// Order of the value kinds, used when comparing values of different types
+fn rank(value: &Value) -> u8 {
+    use Value::*;
+    match value {
+        Nothing => 0,
+        Uuid(..) => 1,
+        Str(..) => 2,
+        Bytes(..) => 3,
+        Int16(..) => 4,
+        Int32(..) => 5,
+        Int64(..) => 6,
+        Float32(..) => 7,
+        Float64(..) => 8,
+        BigInt(..) => 9,
+        ConfigMemory(..) => 10,
+        Decimal(..) => 11,
+        Bool(..) => 12,
+        Datetime(..) => 13,
+        LocalDatetime(..) => 14,
+        LocalDate(..) => 15,
+        LocalTime(..) => 16,
+        Duration(..) => 17,
+        RelativeDuration(..) => 18,
+        DateDuration(..) => 19,
+        Json(..) => 20,
+        Set(..) => 21,
+        Object { .. } => 22,
+        SparseObject(..) => 23,
+        Tuple(..) => 24,
+        NamedTuple { .. } => 25,
+        Array(..) => 26,
+        MultiArray { .. } => 27,
+        Enum(..) => 28,
+        Range(..) => 29,
+        Unknown { .. } => 30,
+    }
 }
 
 impl SparseObject {
@@ -127,6 +393,45 @@ impl SparseObject {
     }
 }
 
+impl FreeObject {
+    /// Returns value of the field, or `None` if there is no such field or
+    /// its value is an empty set
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.pairs().find(|(n, _)| *n == name).and_then(|(_, v)| v)
+    }
+    /// Returns `true` if object has a field (even if it's empty)
+    pub fn contains(&self, name: &str) -> bool {
+        self.pairs().any(|(n, _)| n == name)
+    }
+    /// Names of the fields in the order they are returned by the query
+    pub fn names(&self) -> impl Iterator<Item=&str> {
+        self.pairs().map(|(n, _)| n)
+    }
+    /// Field names and values in the order they are returned by the query
+    pub fn pairs(&self) -> impl Iterator<Item=(&str, Option<&Value>)> {
+        self.shape.0.elements.iter().zip(&self.fields)
+            .filter(|(el, _)| !el.flag_implicit)
+            .map(|(el, val)| (&*el.name, val.as_ref()))
+    }
+}
+
+impl std::convert::TryFrom<Value> for FreeObject {
+    type Error = Value;
+    /// Converts `Value::Object`, returns original value otherwise
+    fn try_from(value: Value) -> Result<FreeObject, Value> {
+        match value {
+            Value::Object { shape, fields } => Ok(FreeObject { shape, fields }),
+            value => Err(value),
+        }
+    }
+}
+
+impl From<FreeObject> for Value {
+    fn from(obj: FreeObject) -> Value {
+        Value::Object { shape: obj.shape, fields: obj.fields }
+    }
+}
+
 impl PartialEq for SparseObject {
     fn eq(&self, other: &SparseObject) -> bool {
         let mut num = 0;
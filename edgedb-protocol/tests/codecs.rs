@@ -6,7 +6,7 @@ use std::sync::Arc;
 
 use bytes::Bytes;
 
-use edgedb_protocol::codec::{build_codec};
+use edgedb_protocol::codec::{build_codec, build_codec_with_fallback};
 use edgedb_protocol::codec::{Codec, ObjectShape};
 use edgedb_protocol::features::ProtocolVersion;
 use edgedb_protocol::value::{Value, SparseObject};
@@ -282,6 +282,38 @@ fn null_codec() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[test]
+fn unknown_scalar_fallback() -> Result<(), Box<dyn Error>> {
+    let type_id = "00000000-0000-0000-0000-0000000001ff".parse()?;
+    let descriptors = [
+        Descriptor::BaseScalar(BaseScalarTypeDescriptor { id: type_id }),
+        Descriptor::Array(ArrayTypeDescriptor {
+            id: "00000000-0000-0000-0000-000000000001".parse()?,
+            type_pos: TypePos(0),
+            dimensions: vec![None],
+        }),
+    ];
+    assert!(build_codec(Some(TypePos(1)), &descriptors).is_err());
+    let codec = build_codec_with_fallback(Some(TypePos(0)), &descriptors)?;
+    encoding_eq!(&codec, b"\x01\x02\x03",
+        Value::Unknown { type_id, data: b"\x01\x02\x03".to_vec() });
+    let codec = build_codec_with_fallback(Some(TypePos(1)), &descriptors)?;
+    encoding_eq!(&codec, bconcat!(b"\0\0\0\x01\0\0\0\0\0\0\0\0"
+                                  b"\0\0\0\x01\0\0\0\x01"
+                                  b"\0\0\0\x02ab"),
+        Value::Array(vec![
+            Value::Unknown { type_id, data: b"ab".to_vec() },
+        ]));
+    let mut buf = bytes::BytesMut::new();
+    assert!(codec.encode(&mut buf, &Value::Array(vec![
+        Value::Unknown {
+            type_id: "00000000-0000-0000-0000-000000000101".parse()?,
+            data: b"ab".to_vec(),
+        },
+    ])).is_err());
+    Ok(())
+}
+
 #[test]
 fn object_codec() -> Result<(), Box<dyn Error>> {
     let elements = vec![
@@ -837,6 +869,58 @@ fn array() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[test]
+fn multi_dimensional_array() -> Result<(), Box<dyn Error>> {
+    let codec = build_codec(Some(TypePos(1)),
+        &[
+            Descriptor::BaseScalar(
+                BaseScalarTypeDescriptor {
+                    id: "00000000-0000-0000-0000-000000000105".parse()?,
+                },
+            ),
+            Descriptor::Array(
+                ArrayTypeDescriptor {
+                    id: "b0105467-a177-635f-e207-0a21867f9be0".parse()?,
+                    type_pos: TypePos(0),
+                    dimensions: vec![None, None],
+                },
+            ),
+        ],
+    )?;
+
+    encoding_eq!(&codec, bconcat!(b"\0\0\0\x02\0\0\0\0\0\0\0\x00"
+            b"\0\0\0\x02\0\0\0\x01\0\0\0\x03\0\0\0\x01"
+            b"\0\0\0\x08\0\0\0\0\0\0\0\x01"
+            b"\0\0\0\x08\0\0\0\0\0\0\0\x02"
+            b"\0\0\0\x08\0\0\0\0\0\0\0\x03"
+            b"\0\0\0\x08\0\0\0\0\0\0\0\x04"
+            b"\0\0\0\x08\0\0\0\0\0\0\0\x05"
+            b"\0\0\0\x08\0\0\0\0\0\0\0\x06"),
+        Value::MultiArray {
+            dimensions: vec![2, 3],
+            elements: (1..=6).map(Value::Int64).collect(),
+        });
+
+    let mut buf = bytes::BytesMut::new();
+    assert!(codec.encode(&mut buf, &Value::MultiArray {
+        dimensions: vec![2, 2],
+        elements: (1..=3).map(Value::Int64).collect(),
+    }).is_err());
+    assert!(codec.encode(&mut buf, &Value::MultiArray {
+        dimensions: vec![],
+        elements: vec![Value::Int64(1)],
+    }).is_err());
+    assert!(codec.encode(&mut buf, &Value::MultiArray {
+        dimensions: vec![usize::MAX, 2],
+        elements: vec![],
+    }).is_err());
+    // truncated: 2x3 array with only one element
+    assert!(codec.decode(bconcat!(b"\0\0\0\x02\0\0\0\0\0\0\0\x00"
+            b"\0\0\0\x02\0\0\0\x01\0\0\0\x03\0\0\0\x01"
+            b"\0\0\0\x08\0\0\0\0\0\0\0\x01")).is_err());
+    Ok(())
+}
+
 #[test]
 fn enums() -> Result<(), Box<dyn Error>> {
     let codec = build_codec(Some(TypePos(0)),
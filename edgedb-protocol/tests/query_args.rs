@@ -0,0 +1,195 @@
+use std::error::Error;
+
+use bytes::BytesMut;
+
+use edgedb_protocol::codec::EnumValue;
+use edgedb_protocol::common::Cardinality;
+use edgedb_protocol::descriptors::{Descriptor, TypePos, InputTypedesc};
+use edgedb_protocol::descriptors::BaseScalarTypeDescriptor;
+use edgedb_protocol::descriptors::{ObjectShapeDescriptor, ShapeElement};
+use edgedb_protocol::descriptors::EnumerationTypeDescriptor;
+use edgedb_protocol::descriptors::RangeTypeDescriptor;
+use edgedb_protocol::features::ProtocolVersion;
+use edgedb_protocol::model::Range;
+use edgedb_protocol::query_arg::{QueryArgs, QueryArg, Encoder};
+use edgedb_protocol::query_arg::{DescriptorContext};
+
+mod base;
+
+
+fn positional(type_pos: &[u16]) -> Descriptor {
+    Descriptor::ObjectShape(ObjectShapeDescriptor {
+        id: "00000000-0000-0000-0000-0000000000AA".parse().unwrap(),
+        elements: type_pos.iter().enumerate().map(|(idx, pos)| {
+            ShapeElement {
+                flag_implicit: false,
+                flag_link_property: false,
+                flag_link: false,
+                cardinality: Some(Cardinality::One),
+                name: idx.to_string(),
+                type_pos: TypePos(*pos),
+            }
+        }).collect(),
+    })
+}
+
+fn encode(descriptors: Vec<Descriptor>, args: &impl QueryArgs)
+    -> Result<BytesMut, Box<dyn Error>>
+{
+    let desc = InputTypedesc::new(ProtocolVersion::current(), descriptors,
+        "00000000-0000-0000-0000-0000000000AA".parse()?)?;
+    let mut buf = BytesMut::new();
+    args.encode(&mut Encoder::new(&desc.as_query_arg_context(), &mut buf))?;
+    Ok(buf)
+}
+
+#[test]
+fn range() -> Result<(), Box<dyn Error>> {
+    let descriptors = vec![
+        Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+            id: "00000000-0000-0000-0000-000000000105".parse()?,
+        }),
+        Descriptor::Range(RangeTypeDescriptor {
+            id: "7f8919fd845bb1badae19d40d96ea0a8".parse()?,
+            type_pos: TypePos(0),
+        }),
+        positional(&[1]),
+    ];
+    assert_eq!(&encode(descriptors.clone(), &(Range::from(7i64..39),))?[..],
+        bconcat!(b"\0\0\0\x01\0\0\0\0\0\0\0\x19"
+                 b"\x02\0\0\0\x08\0\0\0\0\0\0\0\x07\0\0\0\x08\0\0\0\0\0\0\0'"));
+    assert_eq!(&encode(descriptors.clone(), &(Range::<i64>::empty(),))?[..],
+        b"\0\0\0\x01\0\0\0\0\0\0\0\x01\x01");
+    // element type is checked
+    assert!(encode(descriptors, &(Range::from(7i32..39),)).is_err());
+    Ok(())
+}
+
+#[test]
+fn enumeration() -> Result<(), Box<dyn Error>> {
+    let descriptors = vec![
+        Descriptor::Enumeration(EnumerationTypeDescriptor {
+            id: "ac5dc6a4-2656-11ec-ae6d-73b11a2ab7d4".parse()?,
+            members: vec!["waiting".into(), "done".into()],
+        }),
+        Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+            id: "00000000-0000-0000-0000-000000000101".parse()?,
+        }),
+    ];
+    let mut with_enum = descriptors.clone();
+    with_enum.push(positional(&[0]));
+    assert_eq!(&encode(with_enum, &(EnumValue::from("done"),))?[..],
+        b"\0\0\0\x01\0\0\0\0\0\0\0\x04done");
+
+    let mut with_str = descriptors;
+    with_str.push(positional(&[1]));
+    assert!(encode(with_str, &(EnumValue::from("done"),)).is_err());
+    Ok(())
+}
+
+fn params(names: &[&str]) -> Vec<Descriptor> {
+    let mut descriptors = vec![
+        Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+            id: "00000000-0000-0000-0000-000000000100".parse().unwrap(),
+        }),
+    ];
+    let mut shape = positional(&vec![0; names.len()]);
+    if let Descriptor::ObjectShape(shape) = &mut shape {
+        for (el, name) in shape.elements.iter_mut().zip(names) {
+            el.name = name.to_string();
+        }
+    }
+    descriptors.push(shape);
+    descriptors
+}
+
+fn error(descriptors: Vec<Descriptor>, args: &impl QueryArgs) -> String {
+    format!("{:#}", encode(descriptors, args).unwrap_err())
+}
+
+#[test]
+fn argument_mismatch() {
+    let id = uuid::Uuid::from_u128(1);
+    assert_eq!(error(params(&["user_id"]), &(id,)),
+        "DescriptorMismatch: query expects named arguments \
+         ($user_id: std::uuid), but 1 positional arguments were provided");
+    assert_eq!(error(params(&["0", "1"]), &(id,)),
+        "DescriptorMismatch: missing $1: std::uuid; \
+         query expects ($0: std::uuid, $1: std::uuid)");
+    assert_eq!(error(params(&["0"]), &(id, id, id)),
+        "DescriptorMismatch: unexpected positional args 1, 2; \
+         query expects ($0: std::uuid)");
+    assert_eq!(error(params(&["0", "1"]), &(id, "x")),
+        "DescriptorMismatch: argument $1: \
+         unexpected type std::uuid, expected std::str");
+    assert_eq!(error(params(&["0"]), &()),
+        "DescriptorMismatch: query arguments expected: \
+         missing $0: std::uuid");
+}
+
+#[test]
+fn external_slot() -> Result<(), Box<dyn Error>> {
+    struct External(&'static [u8]);
+    impl QueryArg for External {
+        fn encode_slot(&self, enc: &mut Encoder)
+            -> Result<(), edgedb_errors::Error>
+        {
+            enc.external_slot(self.0.len(), Box::new(self.0))
+        }
+        fn check_descriptor(ctx: &DescriptorContext, pos: TypePos)
+            -> Result<(), edgedb_errors::Error>
+        {
+            <bytes::Bytes as QueryArg>::check_descriptor(ctx, pos)
+        }
+    }
+
+    let desc = InputTypedesc::new(ProtocolVersion::current(), vec![
+        Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+            id: "00000000-0000-0000-0000-000000000102".parse()?,
+        }),
+        positional(&[0, 0]),
+    ], "00000000-0000-0000-0000-0000000000AA".parse()?)?;
+    let mut buf = BytesMut::new();
+    let ctx = desc.as_query_arg_context();
+    let mut encoder = Encoder::with_external(&ctx, &mut buf);
+    (External(b"hello"), bytes::Bytes::from_static(b"xy"))
+        .encode(&mut encoder)?;
+    let external = encoder.take_external();
+    assert_eq!(&buf[..],
+        b"\0\0\0\x02\0\0\0\0\0\0\0\x05\0\0\0\0\0\0\0\x02xy");
+    assert_eq!(external.len(), 1);
+    assert_eq!(external[0].offset, 12);
+    assert_eq!(external[0].len, 5);
+    assert_eq!(external[0].source.downcast_ref::<&[u8]>(),
+               Some(&&b"hello"[..]));
+
+    // external slots are opt-in
+    let mut buf = BytesMut::new();
+    let mut encoder = Encoder::new(&ctx, &mut buf);
+    assert!((External(b"hello"), bytes::Bytes::from_static(b"xy"))
+        .encode(&mut encoder).is_err());
+    Ok(())
+}
+
+#[cfg(feature="with-serde")]
+#[test]
+fn json() -> Result<(), Box<dyn Error>> {
+    use edgedb_protocol::model::{Json, AsJson};
+
+    #[derive(serde::Serialize)]
+    struct Point { x: i32, y: i32 }
+
+    let descriptors = vec![
+        Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+            id: "00000000-0000-0000-0000-00000000010F".parse()?,
+        }),
+        positional(&[0]),
+    ];
+    let expected = b"\0\0\0\x01\0\0\0\0\0\0\0\x0f\x01{\"x\":1,\"y\":-2}";
+    let point = Point { x: 1, y: -2 };
+    assert_eq!(&encode(descriptors.clone(), &(AsJson(&point),))?[..],
+               expected);
+    assert_eq!(&encode(descriptors, &(Json::from_serialize(&point)?,))?[..],
+               expected);
+    Ok(())
+}
@@ -0,0 +1,164 @@
+#![cfg(feature="random-values")]
+
+use std::error::Error;
+use std::sync::Arc;
+
+use bytes::BytesMut;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use edgedb_protocol::codec::{self, build_codec, Codec};
+use edgedb_protocol::descriptors::{Descriptor, TypePos};
+use edgedb_protocol::descriptors::BaseScalarTypeDescriptor;
+use edgedb_protocol::descriptors::{ObjectShapeDescriptor, ShapeElement};
+use edgedb_protocol::descriptors::{SetDescriptor, TupleTypeDescriptor};
+use edgedb_protocol::descriptors::{NamedTupleTypeDescriptor, TupleElement};
+use edgedb_protocol::descriptors::ArrayTypeDescriptor;
+use edgedb_protocol::descriptors::EnumerationTypeDescriptor;
+use edgedb_protocol::descriptors::RangeTypeDescriptor;
+use edgedb_protocol::descriptors::InputShapeTypeDescriptor;
+use edgedb_protocol::common::Cardinality;
+use edgedb_protocol::model::Uuid;
+use edgedb_protocol::random_values::ValueGenerator;
+use edgedb_protocol::value::Value;
+
+const SCALARS: &[Uuid] = &[
+    codec::STD_UUID, codec::STD_STR, codec::STD_BYTES, codec::STD_INT16,
+    codec::STD_INT32, codec::STD_INT64, codec::STD_FLOAT32,
+    codec::STD_FLOAT64, codec::STD_DECIMAL, codec::STD_BOOL,
+    codec::STD_DATETIME, codec::CAL_LOCAL_DATETIME, codec::CAL_LOCAL_DATE,
+    codec::CAL_LOCAL_TIME, codec::STD_DURATION, codec::CAL_RELATIVE_DURATION,
+    codec::CAL_DATE_DURATION, codec::STD_JSON, codec::STD_BIGINT,
+    codec::CFG_MEMORY,
+];
+
+fn element(name: &str, pos: u16, card: Cardinality) -> ShapeElement {
+    ShapeElement {
+        flag_implicit: false,
+        flag_link_property: false,
+        flag_link: false,
+        cardinality: Some(card),
+        name: name.into(),
+        type_pos: TypePos(pos),
+    }
+}
+
+/// All base scalars, followed by the composite types using them
+fn descriptors() -> Vec<Descriptor> {
+    let mut result = SCALARS.iter()
+        .map(|id| Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+            id: *id,
+        }))
+        .collect::<Vec<_>>();
+    let n = SCALARS.len() as u16;
+    let id = Uuid::from_u128;
+    result.extend(vec![
+        // n
+        Descriptor::Tuple(TupleTypeDescriptor {
+            id: id(1),
+            element_types: (0..n).map(TypePos).collect(),
+        }),
+        // n + 1
+        Descriptor::Array(ArrayTypeDescriptor {
+            id: id(2),
+            type_pos: TypePos(1),
+            dimensions: vec![None],
+        }),
+        // n + 2
+        Descriptor::Enumeration(EnumerationTypeDescriptor {
+            id: id(3),
+            members: vec!["One".into(), "Two".into()],
+        }),
+        // n + 3, range<int64>
+        Descriptor::Range(RangeTypeDescriptor {
+            id: id(4),
+            type_pos: TypePos(5),
+        }),
+        // n + 4, range<datetime>
+        Descriptor::Range(RangeTypeDescriptor {
+            id: id(5),
+            type_pos: TypePos(10),
+        }),
+        // n + 5
+        Descriptor::NamedTuple(NamedTupleTypeDescriptor {
+            id: id(6),
+            elements: vec![
+                TupleElement { name: "a".into(), type_pos: TypePos(n+2) },
+                TupleElement { name: "b".into(), type_pos: TypePos(n+3) },
+                TupleElement { name: "c".into(), type_pos: TypePos(n+4) },
+            ],
+        }),
+        // n + 6
+        Descriptor::Set(SetDescriptor {
+            id: id(7),
+            type_pos: TypePos(n+5),
+        }),
+        // n + 7
+        Descriptor::ObjectShape(ObjectShapeDescriptor {
+            id: id(8),
+            elements: vec![
+                element("scalars", n, Cardinality::One),
+                element("names", n+1, Cardinality::AtMostOne),
+                element("items", n+6, Cardinality::Many),
+            ],
+        }),
+        // n + 8
+        Descriptor::InputShape(InputShapeTypeDescriptor {
+            id: id(9),
+            elements: vec![
+                element("name", 1, Cardinality::AtMostOne),
+                element("number", 5, Cardinality::One),
+            ],
+        }),
+    ]);
+    result
+}
+
+fn roundtrip(codec: &Arc<dyn Codec>, value: &Value)
+    -> Result<(), Box<dyn Error>>
+{
+    let mut buf = BytesMut::new();
+    codec.encode(&mut buf, value)?;
+    assert_eq!(&codec.decode(&buf)?, value);
+    Ok(())
+}
+
+#[test]
+fn object() -> Result<(), Box<dyn Error>> {
+    let descriptors = descriptors();
+    let root = Some(TypePos(SCALARS.len() as u16 + 7));
+    let codec = build_codec(root, &descriptors)?;
+    let gen = ValueGenerator::new(&descriptors, root);
+    for seed in 0..200 {
+        let value = gen.generate(&mut StdRng::seed_from_u64(seed))?;
+        roundtrip(&codec, &value)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn input_shape() -> Result<(), Box<dyn Error>> {
+    let descriptors = descriptors();
+    let root = Some(TypePos(SCALARS.len() as u16 + 8));
+    let codec = build_codec(root, &descriptors)?;
+    let gen = ValueGenerator::new(&descriptors, root).max_len(10);
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..100 {
+        roundtrip(&codec, &gen.generate(&mut rng)?)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn unknown_scalar() {
+    let descriptors = vec![
+        Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+            id: Uuid::from_u128(0xFFFF),
+        }),
+    ];
+    let gen = ValueGenerator::new(&descriptors, Some(TypePos(0)));
+    assert!(gen.generate(&mut StdRng::seed_from_u64(0)).is_err());
+    let gen = ValueGenerator::new(&descriptors, None);
+    assert_eq!(gen.generate(&mut StdRng::seed_from_u64(0)).unwrap(),
+               Value::Nothing);
+}
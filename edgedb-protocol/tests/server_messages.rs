@@ -7,6 +7,7 @@ use bytes::{Bytes, BytesMut};
 
 use edgedb_protocol::common::{Capabilities};
 use edgedb_protocol::encoding::{Input, Output};
+use edgedb_protocol::errors::DecodeError;
 use edgedb_protocol::features::ProtocolVersion;
 use edgedb_protocol::server_message::{Authentication};
 use edgedb_protocol::server_message::{CommandComplete0, CommandComplete1};
@@ -298,3 +299,43 @@ fn state_data_description() -> Result<(), Box<dyn Error>> {
     );
     Ok(())
 }
+
+fn decode_malformed(data: &'static [u8]) -> Result<ServerMessage, DecodeError>
+{
+    ServerMessage::decode(&mut Input::new(
+        ProtocolVersion::current(),
+        Bytes::from_static(data),
+    ))
+}
+
+#[test]
+fn malformed() {
+    // incomplete frame header
+    assert!(matches!(decode_malformed(b""),
+                     Err(DecodeError::Underflow { .. })));
+    assert!(matches!(decode_malformed(b"Z\0\0"),
+                     Err(DecodeError::Underflow { .. })));
+    // handshake extension with missing headers
+    assert!(matches!(
+        decode_malformed(b"v\0\0\0\x14\0\x01\0\0\0\x01\0\0\0\x03ext\0\x01"),
+        Err(DecodeError::Underflow { .. })));
+    // authentication methods count is much larger than the message
+    assert!(matches!(
+        decode_malformed(b"R\0\0\0\x0c\0\0\0\x0a\xff\xff\xff\xff"),
+        Err(DecodeError::Underflow { .. })));
+    // too many data chunks
+    assert!(matches!(
+        decode_malformed(b"D\0\0\0\x06\xff\xff"),
+        Err(DecodeError::Underflow { .. })));
+    // annotations consume the whole CommandComplete
+    assert!(matches!(
+        decode_malformed(b"C\0\0\0\x1e\0\x01\
+            \0\0\0\x09aaaaaaaaa\0\0\0\x09bbbbbbbbb"),
+        Err(DecodeError::Underflow { .. })));
+    // invalid transaction state
+    assert!(matches!(
+        decode_malformed(b"Z\0\0\0\x07\0\0\x01"),
+        Err(DecodeError::InvalidTransactionState {
+            transaction_state: 1, ..
+        })));
+}
@@ -0,0 +1,51 @@
+use std::collections::HashSet;
+
+use edgedb_protocol::test_vectors::*;
+
+
+#[test]
+fn server_messages_roundtrip() {
+    for vector in server_messages() {
+        assert_server_message(&vector);
+    }
+}
+
+#[test]
+fn client_messages_roundtrip() {
+    for vector in client_messages() {
+        assert_client_message(&vector);
+    }
+}
+
+#[test]
+fn descriptors_decode() {
+    for vector in descriptors() {
+        assert_descriptors(&vector);
+    }
+}
+
+#[test]
+fn values_roundtrip() {
+    for vector in values() {
+        assert_value(&vector);
+    }
+}
+
+#[test]
+fn unique_names() {
+    let mut names = HashSet::new();
+    let all = server_messages().iter().map(|v| v.name)
+        .chain(client_messages().iter().map(|v| v.name))
+        .chain(descriptors().iter().map(|v| v.name))
+        .chain(values().iter().map(|v| v.name))
+        .collect::<Vec<_>>();
+    for name in all {
+        assert!(names.insert(name), "duplicate vector {:?}", name);
+    }
+}
+
+#[test]
+#[should_panic(expected="at byte 1")]
+fn wire_mismatch() {
+    assert_wire_eq("example", b"\x01\x02\x03", b"\x01\x03\x03");
+}
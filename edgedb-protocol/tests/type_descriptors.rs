@@ -4,10 +4,17 @@ use bytes::{Bytes, Buf};
 use edgedb_protocol::encoding::Input;
 use edgedb_protocol::errors::DecodeError;
 use edgedb_protocol::features::ProtocolVersion;
+use edgedb_protocol::model::Uuid;
+use edgedb_protocol::common::Cardinality;
+use edgedb_protocol::value::{Value, FreeObject};
+use edgedb_protocol::{QueryResult, WithId};
 use edgedb_protocol::descriptors::{Descriptor, TypePos};
+use edgedb_protocol::descriptors::{InputTypedesc, OutputTypedesc};
 use edgedb_protocol::descriptors::TupleTypeDescriptor;
 use edgedb_protocol::descriptors::{ObjectShapeDescriptor, ShapeElement};
 use edgedb_protocol::descriptors::BaseScalarTypeDescriptor;
+use edgedb_protocol::descriptors::{SetDescriptor, ArrayTypeDescriptor};
+use edgedb_protocol::descriptors::{EnumerationTypeDescriptor, rust_type};
 
 mod base;
 
@@ -189,3 +196,281 @@ fn object() -> Result<(), Box<dyn Error>> {
         ]);
     Ok(())
 }
+
+#[test]
+fn malformed() {
+    // truncated base scalar
+    assert!(matches!(decode(b"\x02\0\0\0\0\0\0\0"),
+                     Err(DecodeError::Underflow { .. })));
+    // tuple with element count larger than the data
+    assert!(matches!(
+        decode(b"\x04\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\xff\0\x05\0\0"),
+        Err(DecodeError::Underflow { .. })));
+    // object shape with truncated element
+    assert!(matches!(
+        decode(b"\x01\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\x01\0\x01\0\0\0\0\0"),
+        Err(DecodeError::Underflow { .. })));
+    // unknown descriptor type
+    assert!(matches!(
+        decode(b"\x0A\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\xff\0\0"),
+        Err(DecodeError::InvalidTypeDescriptor { descriptor: 0x0A, .. })));
+    // array with zero dimension size
+    assert!(matches!(
+        decode(b"\x06\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\xff\0\0\0\x01\0\0\0\0"),
+        Err(DecodeError::InvalidArrayShape { .. })));
+}
+
+#[test]
+fn assemble_typedesc() -> Result<(), Box<dyn Error>> {
+    let descriptors = vec![
+        Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+            id: "00000000-0000-0000-0000-000000000101".parse()?,
+        }),
+        Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+            id: "00000000-0000-0000-0000-000000000105".parse()?,
+        }),
+        Descriptor::Tuple(TupleTypeDescriptor {
+            id: "6c87a50a-fce2-dcae-6872-8c4c9c4d1e7c".parse()?,
+            element_types: vec![TypePos(0), TypePos(1)],
+        }),
+    ];
+    let out = OutputTypedesc::new(ProtocolVersion::current(),
+        descriptors.clone(),
+        "6c87a50a-fce2-dcae-6872-8c4c9c4d1e7c".parse()?)?;
+    assert_eq!(out.root_pos(), Some(TypePos(2)));
+
+    let data = bconcat!(b"\0\0\0\x02"
+                        b"\0\0\0\0\0\0\0\x02hi"
+                        b"\0\0\0\0\0\0\0\x08\0\0\0\0\0\0\0\x07");
+    let codec = out.build_codec()?;
+    assert_eq!(codec.decode(data)?, Value::Tuple(vec![
+        Value::Str("hi".into()),
+        Value::Int64(7),
+    ]));
+    let ctx = out.as_queryable_context();
+    let mut state = <(String, i64)>::prepare(&ctx, TypePos(2))?;
+    let data = Bytes::copy_from_slice(data);
+    assert_eq!(<(String, i64)>::decode(&mut state, &data)?, ("hi".into(), 7));
+
+    let no_result = OutputTypedesc::new(ProtocolVersion::current(),
+        Vec::new(), "00000000-0000-0000-0000-000000000000".parse()?)?;
+    assert_eq!(no_result.root_pos(), None);
+
+    let inp = InputTypedesc::new(ProtocolVersion::current(),
+        descriptors.clone(),
+        "00000000-0000-0000-0000-000000000105".parse()?)?;
+    assert_eq!(inp.root(), Some(&descriptors[1]));
+
+    assert!(matches!(
+        InputTypedesc::new(ProtocolVersion::current(), descriptors,
+            "00000000-0000-0000-0000-0000000000FF".parse()?),
+        Err(DecodeError::UuidNotFound { .. })));
+    Ok(())
+}
+
+#[test]
+fn free_object() -> Result<(), Box<dyn Error>> {
+    // SELECT { a := 1, b := "x" }
+    let element = |name: &str, pos| ShapeElement {
+        flag_implicit: false,
+        flag_link_property: false,
+        flag_link: false,
+        cardinality: Some(Cardinality::One),
+        name: name.into(),
+        type_pos: TypePos(pos),
+    };
+    let out = OutputTypedesc::new(ProtocolVersion::current(), vec![
+        Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+            id: "00000000-0000-0000-0000-000000000105".parse()?,
+        }),
+        Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+            id: "00000000-0000-0000-0000-000000000101".parse()?,
+        }),
+        Descriptor::ObjectShape(ObjectShapeDescriptor {
+            id: "00000000-0000-0000-0000-0000000000AA".parse()?,
+            elements: vec![element("a", 0), element("b", 1)],
+        }),
+    ], "00000000-0000-0000-0000-0000000000AA".parse()?)?;
+    let ctx = out.as_queryable_context();
+    let mut state = FreeObject::prepare(&ctx, TypePos(2))?;
+    let data = Bytes::copy_from_slice(bconcat!(b"\0\0\0\x02"
+        b"\0\0\0\0\0\0\0\x08\0\0\0\0\0\0\0\x01"
+        b"\0\0\0\0\0\0\0\x01x"));
+    let obj = FreeObject::decode(&mut state, &data)?;
+    assert_eq!(obj.get("a"), Some(&Value::Int64(1)));
+    assert_eq!(obj.get("b"), Some(&Value::Str("x".into())));
+    assert_eq!(obj.get("c"), None);
+    assert!(!obj.contains("c"));
+    assert_eq!(obj.names().collect::<Vec<_>>(), vec!["a", "b"]);
+
+    assert!(FreeObject::prepare(&ctx, TypePos(0)).is_err());
+    Ok(())
+}
+
+#[test]
+fn with_id() -> Result<(), Box<dyn Error>> {
+    // SELECT User { name, id }
+    let element = |name: &str, pos| ShapeElement {
+        flag_implicit: false,
+        flag_link_property: false,
+        flag_link: false,
+        cardinality: Some(Cardinality::One),
+        name: name.into(),
+        type_pos: TypePos(pos),
+    };
+    let out = OutputTypedesc::new(ProtocolVersion::current(), vec![
+        Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+            id: "00000000-0000-0000-0000-000000000100".parse()?,
+        }),
+        Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+            id: "00000000-0000-0000-0000-000000000101".parse()?,
+        }),
+        Descriptor::ObjectShape(ObjectShapeDescriptor {
+            id: "00000000-0000-0000-0000-0000000000AA".parse()?,
+            elements: vec![element("name", 1), element("id", 0)],
+        }),
+        Descriptor::ObjectShape(ObjectShapeDescriptor {
+            id: "00000000-0000-0000-0000-0000000000AB".parse()?,
+            elements: vec![element("name", 1)],
+        }),
+    ], "00000000-0000-0000-0000-0000000000AA".parse()?)?;
+    let ctx = out.as_queryable_context();
+    let mut state = WithId::<FreeObject>::prepare(&ctx, TypePos(2))?;
+    let data = Bytes::copy_from_slice(bconcat!(b"\0\0\0\x02"
+        b"\0\0\0\0\0\0\0\x01x"
+        b"\0\0\0\0\0\0\0\x10"
+        b"\x01\x02\x03\x04\x05\x06\x07\x08"
+        b"\x09\x0a\x0b\x0c\x0d\x0e\x0f\x10"));
+    let obj = WithId::<FreeObject>::decode(&mut state, &data)?;
+    assert_eq!(obj.id, "01020304-0506-0708-090a-0b0c0d0e0f10".parse::<Uuid>()?);
+    assert_eq!(obj.value.get("name"), Some(&Value::Str("x".into())));
+
+    assert!(WithId::<FreeObject>::prepare(&ctx, TypePos(3)).is_err());
+    assert!(WithId::<FreeObject>::prepare(&ctx, TypePos(0)).is_err());
+    Ok(())
+}
+
+#[test]
+fn bytes_result() -> Result<(), Box<dyn Error>> {
+    // SELECT (b'abc', b'de')
+    let out = OutputTypedesc::new(ProtocolVersion::current(), vec![
+        Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+            id: "00000000-0000-0000-0000-000000000102".parse()?,
+        }),
+        Descriptor::Tuple(TupleTypeDescriptor {
+            id: "00000000-0000-0000-0000-0000000000AA".parse()?,
+            element_types: vec![TypePos(0), TypePos(0)],
+        }),
+    ], "00000000-0000-0000-0000-0000000000AA".parse()?)?;
+    let ctx = out.as_queryable_context();
+    let mut state = <(Bytes, Bytes)>::prepare(&ctx, TypePos(1))?;
+    let data = Bytes::copy_from_slice(bconcat!(b"   "
+        b"       abc"
+        b"       de"));
+    let (a, b) = <(Bytes, Bytes)>::decode(&mut state, &data)?;
+    assert_eq!(&a[..], b"abc");
+    assert_eq!(&b[..], b"de");
+    // values share memory with the message
+    assert_eq!(a.as_ptr(), data[12..].as_ptr());
+    assert_eq!(b.as_ptr(), data[23..].as_ptr());
+    Ok(())
+}
+
+#[test]
+fn rust_types() -> Result<(), Box<dyn Error>> {
+    // SELECT User { id, name, nick, tags, kind, friends: { name } }
+    let element = |name: &str, pos, card| ShapeElement {
+        flag_implicit: name == "id",
+        flag_link_property: false,
+        flag_link: name == "friends",
+        cardinality: Some(card),
+        name: name.into(),
+        type_pos: TypePos(pos),
+    };
+    let descriptors = vec![
+        Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+            id: "00000000-0000-0000-0000-000000000100".parse()?,
+        }),
+        Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+            id: "00000000-0000-0000-0000-000000000101".parse()?,
+        }),
+        Descriptor::Array(ArrayTypeDescriptor {
+            id: "00000000-0000-0000-0000-0000000000A1".parse()?,
+            type_pos: TypePos(1),
+            dimensions: vec![None],
+        }),
+        Descriptor::Enumeration(EnumerationTypeDescriptor {
+            id: "00000000-0000-0000-0000-0000000000E1".parse()?,
+            members: vec!["Admin".into(), "Guest".into()],
+        }),
+        Descriptor::ObjectShape(ObjectShapeDescriptor {
+            id: "00000000-0000-0000-0000-0000000000AB".parse()?,
+            elements: vec![element("name", 1, Cardinality::One)],
+        }),
+        Descriptor::ObjectShape(ObjectShapeDescriptor {
+            id: "00000000-0000-0000-0000-0000000000AA".parse()?,
+            elements: vec![
+                element("id", 0, Cardinality::One),
+                element("name", 1, Cardinality::One),
+                element("nick", 1, Cardinality::AtMostOne),
+                element("tags", 2, Cardinality::One),
+                element("kind", 3, Cardinality::One),
+                element("friends", 4, Cardinality::Many),
+            ],
+        }),
+        Descriptor::Set(SetDescriptor {
+            id: "00000000-0000-0000-0000-0000000000AC".parse()?,
+            type_pos: TypePos(5),
+        }),
+        Descriptor::Tuple(TupleTypeDescriptor {
+            id: "00000000-0000-0000-0000-0000000000AD".parse()?,
+            element_types: vec![TypePos(0)],
+        }),
+    ];
+    assert_eq!(rust_type(&descriptors, TypePos(0)),
+               "edgedb_protocol::model::Uuid");
+    assert_eq!(rust_type(&descriptors, TypePos(2)), "Vec<String>");
+    assert_eq!(rust_type(&descriptors, TypePos(3)), "String");
+    assert_eq!(rust_type(&descriptors, TypePos(6)),
+               "Vec<struct { name: String, nick: Option<String>, \
+                tags: Vec<String>, kind: String, \
+                friends: Vec<struct { name: String }> }>");
+    assert_eq!(rust_type(&descriptors, TypePos(7)),
+               "(edgedb_protocol::model::Uuid,)");
+    assert_eq!(rust_type(&descriptors, TypePos(100)), "<invalid>");
+
+    let out = OutputTypedesc::new(ProtocolVersion::current(),
+        descriptors.clone(),
+        "00000000-0000-0000-0000-0000000000A1".parse()?)?;
+    assert_eq!(out.rust_type(), "Vec<String>");
+    let ctx = out.as_queryable_context();
+    assert_eq!(ctx.rust_type(TypePos(1)), "String");
+    use edgedb_protocol::queryable::Queryable;
+    assert_eq!(
+        <i64 as Queryable>::check_descriptor(&ctx, TypePos(1))
+            .unwrap_err().to_string(),
+        "unexpected type std::str (`String`), expected std::int64");
+    assert_eq!(
+        <i64 as Queryable>::check_descriptor(&ctx, TypePos(2))
+            .unwrap_err().to_string(),
+        "unexpected type `Vec<String>`, expected std::int64");
+
+    let no_result = OutputTypedesc::new(ProtocolVersion::current(),
+        Vec::new(), "00000000-0000-0000-0000-000000000000".parse()?)?;
+    assert_eq!(no_result.rust_type(), "()");
+
+    // malformed descriptor referring to itself
+    let cycle = OutputTypedesc::new(ProtocolVersion::current(), vec![
+        Descriptor::Set(SetDescriptor {
+            id: "00000000-0000-0000-0000-0000000000AC".parse()?,
+            type_pos: TypePos(0),
+        }),
+    ], "00000000-0000-0000-0000-0000000000AC".parse()?)?;
+    assert_eq!(cycle.rust_type(), "Vec<Vec<<invalid>>>");
+    assert_eq!(
+        <i64 as Queryable>::check_descriptor(
+            &cycle.as_queryable_context(), TypePos(0)
+        ).unwrap_err().to_string(),
+        "unexpected type `Vec<Vec<<invalid>>>`, expected std::int64");
+    Ok(())
+}
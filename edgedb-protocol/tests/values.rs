@@ -0,0 +1,124 @@
+use std::error::Error;
+
+use edgedb_protocol::codec::{build_codec, ObjectShape, ShapeElement};
+use edgedb_protocol::descriptors::{Descriptor, TypePos};
+use edgedb_protocol::descriptors::BaseScalarTypeDescriptor;
+use edgedb_protocol::model::BigInt;
+use edgedb_protocol::value::Value;
+
+
+fn decimal(data: &[u8]) -> Result<Value, Box<dyn Error>> {
+    let codec = build_codec(Some(TypePos(0)),
+        &[
+            Descriptor::BaseScalar(BaseScalarTypeDescriptor {
+                id: "00000000-0000-0000-0000-000000000108".parse()?,
+            })
+        ]
+    )?;
+    Ok(codec.decode(data)?)
+}
+
+fn shape(names: &[&str]) -> ObjectShape {
+    shape_with_implicit(names, &[])
+}
+
+fn shape_with_implicit(names: &[&str], implicit: &[&str]) -> ObjectShape {
+    ObjectShape::new(names.iter().map(|name| ShapeElement {
+        flag_implicit: implicit.contains(name),
+        flag_link_property: false,
+        flag_link: false,
+        cardinality: None,
+        name: name.to_string(),
+    }).collect())
+}
+
+#[test]
+fn normalize() -> Result<(), Box<dyn Error>> {
+    // 1.50
+    let mut val = decimal(b"\0\x02\0\0\0\0\0\x02\0\x01\x13\x88")?;
+    val.normalize();
+    // 1.5
+    assert_eq!(val, decimal(b"\0\x02\0\0\0\0\0\x01\0\x01\x13\x88")?);
+
+    // -0.00
+    let mut val = decimal(b"\0\0\0\0\x40\0\0\x02")?;
+    val.normalize();
+    assert_eq!(val, decimal(b"\0\0\0\0\0\0\0\0")?);
+
+    let mut val = Value::Set(vec![Value::Float64(-0.0)]);
+    val.normalize();
+    match val {
+        Value::Set(items) => match items[..] {
+            [Value::Float64(f)] => assert!(f.is_sign_positive()),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+#[test]
+fn semantic_eq() -> Result<(), Box<dyn Error>> {
+    assert!(decimal(b"\0\x02\0\0\0\0\0\x02\0\x01\x13\x88")?
+        .semantic_eq(&decimal(b"\0\x02\0\0\0\0\0\x01\0\x01\x13\x88")?));
+    assert!(!decimal(b"\0\x02\0\0\0\0\0\x02\0\x01\x13\x88")?
+        .semantic_eq(&decimal(b"\0\x02\0\0\0\0\0\x01\0\x01\x07\xD0")?));
+    assert!(Value::Float64(f64::NAN).semantic_eq(&Value::Float64(-f64::NAN)));
+    assert!(Value::Float32(0.0).semantic_eq(&Value::Float32(-0.0)));
+    assert!(!Value::Int32(1).semantic_eq(&Value::Int64(1)));
+    assert!(Value::BigInt(BigInt::from(0)).semantic_eq(
+        &Value::BigInt(BigInt::from(-0i64))));
+
+    let a = Value::Object {
+        shape: shape(&["id", "name"]),
+        fields: vec![Some(Value::Int64(1)), Some(Value::Str("x".into()))],
+    };
+    let b = Value::Object {
+        shape: shape_with_implicit(&["id", "name"], &["id"]),
+        fields: vec![Some(Value::Int64(1)), Some(Value::Str("x".into()))],
+    };
+    assert_ne!(a, b);
+    assert!(a.semantic_eq(&b));
+    let c = Value::Object {
+        shape: shape(&["id", "title"]),
+        fields: vec![Some(Value::Int64(1)), Some(Value::Str("x".into()))],
+    };
+    assert!(!a.semantic_eq(&c));
+    let d = Value::Object {
+        shape: shape(&["id", "name"]),
+        fields: vec![Some(Value::Int64(1)), None],
+    };
+    assert!(!a.semantic_eq(&d));
+    Ok(())
+}
+
+#[test]
+fn sort_sets() {
+    let mut a = Value::Set(vec![
+        Value::Str("b".into()),
+        Value::Str("a".into()),
+        Value::Str("c".into()),
+    ]);
+    let mut b = Value::Set(vec![
+        Value::Str("c".into()),
+        Value::Str("b".into()),
+        Value::Str("a".into()),
+    ]);
+    assert!(!a.semantic_eq(&b));
+    a.sort_sets();
+    b.sort_sets();
+    assert_eq!(a, b);
+
+    let mut arr = Value::Array(vec![Value::Int16(2), Value::Int16(1)]);
+    let orig = arr.clone();
+    arr.sort_sets();
+    assert_eq!(arr, orig);
+
+    let mut nested = Value::Tuple(vec![
+        Value::Set(vec![Value::Int16(2), Value::Int16(1)]),
+    ]);
+    nested.sort_sets();
+    assert_eq!(nested, Value::Tuple(vec![
+        Value::Set(vec![Value::Int16(1), Value::Int16(2)]),
+    ]));
+}
@@ -0,0 +1,10 @@
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut builder = edgedb_tokio::Builder::from_env().await?;
+    builder.system_database();
+    let conn = edgedb_tokio::Client::new(&builder.build()?);
+    let names = conn.query::<String, _>(
+        "SELECT sys::Database.name", &()).await?;
+    println!("Databases: {}", names.join(", "));
+    Ok(())
+}
@@ -6,17 +6,25 @@ use std::time::{Duration};
 
 use rustls::client::ServerCertVerifier;
 
+use edgedb_protocol::features::ProtocolVersion;
+
 use crate::credentials::{Credentials, TlsSecurity};
+use crate::entropy::{Entropy, OsEntropy};
 use crate::errors::{ClientError};
 use crate::errors::{ClientNoCredentialsError};
 use crate::errors::{Error, ErrorKind, ResultExt};
-use crate::tls;
+use crate::tls::{self, TlsConfigure};
+use crate::trace::{ConnectProgress, ProgressCallback};
 
 pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 pub const DEFAULT_WAIT: Duration = Duration::from_secs(30);
 pub const DEFAULT_POOL_SIZE: usize = 10;
 pub const DEFAULT_HOST: &str = "localhost";
 pub const DEFAULT_PORT: u16 = 5656;
+/// Name of the system database used for instance-level operations
+///
+/// See [`Builder::system_database`].
+pub const SYSTEM_DATABASE: &str = "__edgedbsys__";
 
 type Verifier = Arc<dyn ServerCertVerifier>;
 
@@ -34,6 +42,7 @@ pub struct Builder {
     user: String,
     password: Option<String>,
     database: String,
+    system_database: bool,
     pem: Option<String>,
     tls_security: TlsSecurity,
     instance_name: Option<String>,
@@ -43,6 +52,12 @@ pub struct Builder {
     connect_timeout: Duration,
     insecure_dev_mode: bool,
     creds_file_outdated: bool,
+    min_protocol: Option<ProtocolVersion>,
+    max_protocol: ProtocolVersion,
+    trace_connection: bool,
+    connect_progress: Option<ProgressCallback>,
+    entropy: Arc<dyn Entropy>,
+    tls_configure: Option<Arc<dyn TlsConfigure>>,
 
     // Pool configuration
     pub(crate) max_connections: usize,
@@ -75,6 +90,12 @@ pub(crate) struct ConfigInner {
     pub connect_timeout: Duration,
     #[allow(dead_code)] // TODO(tailhook) maybe for future things
     pub insecure_dev_mode: bool,
+    pub min_protocol: Option<ProtocolVersion>,
+    pub max_protocol: ProtocolVersion,
+    pub trace_connection: bool,
+    pub connect_progress: Option<ProgressCallback>,
+    pub entropy: Arc<dyn Entropy>,
+    pub tls_configure: Option<Arc<dyn TlsConfigure>>,
 
     // Pool configuration
     pub max_connections: usize,
@@ -648,6 +669,7 @@ impl Builder {
             user: "edgedb".into(),
             password: None,
             database: "edgedb".into(),
+            system_database: false,
             tls_security: TlsSecurity::Default,
             pem: None,
             instance_name: None,
@@ -657,6 +679,12 @@ impl Builder {
             initialized: false,
             insecure_dev_mode: false,
             creds_file_outdated: false,
+            min_protocol: None,
+            max_protocol: ProtocolVersion::current(),
+            trace_connection: false,
+            connect_progress: None,
+            entropy: Arc::new(OsEntropy),
+            tls_configure: None,
 
             max_connections: DEFAULT_POOL_SIZE,
         }
@@ -669,6 +697,7 @@ impl Builder {
             user: "edgedb".into(),
             password: None,
             database: "edgedb".into(),
+            system_database: false,
             tls_security: TlsSecurity::Default,
             pem: None,
             instance_name: None,
@@ -679,6 +708,12 @@ impl Builder {
             connect_timeout: self.connect_timeout,
             insecure_dev_mode: self.insecure_dev_mode,
             creds_file_outdated: false,
+            min_protocol: self.min_protocol.clone(),
+            max_protocol: self.max_protocol.clone(),
+            trace_connection: self.trace_connection,
+            connect_progress: self.connect_progress.clone(),
+            entropy: self.entropy.clone(),
+            tls_configure: self.tls_configure.clone(),
 
             max_connections: self.max_connections,
         };
@@ -759,6 +794,9 @@ impl Builder {
         self
     }
     /// Set the database name.
+    ///
+    /// To connect to the system database use
+    /// [`system_database`](Self::system_database) instead.
     pub fn database(&mut self, database: impl Into<String>) -> &mut Self {
         self.database = database.into();
         self
@@ -767,6 +805,38 @@ impl Builder {
     pub fn get_database(&self) -> &str {
         &self.database
     }
+    /// Connect to the system database for instance-level operations.
+    ///
+    /// The system database can be used to create and drop databases or
+    /// look at the instance-wide state, but it doesn't contain any
+    /// application data. Since it's unusual to have many concurrent
+    /// administrative queries, the pool is limited to a single connection
+    /// unless [`max_connections`](Self::max_connections) is called
+    /// afterwards.
+    ///
+    /// Other credentials (address, user, password) are kept intact, so this
+    /// is usually called after [`from_env`](Self::from_env):
+    /// ```rust,no_run
+    /// # async fn main_() -> Result<(), edgedb_tokio::Error> {
+    /// let mut builder = edgedb_tokio::Builder::from_env().await?;
+    /// builder.system_database();
+    /// let client = edgedb_tokio::Client::new(&builder.build()?);
+    /// let names = client.query::<String, _>(
+    ///     "SELECT sys::Database.name", &()).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Note: [`build`](Self::build) refuses to connect to the
+    /// [`SYSTEM_DATABASE`](crate::SYSTEM_DATABASE) unless this method is
+    /// used, so that the system database is not mixed up with a regular
+    /// application pool by setting it in the environment or a DSN.
+    pub fn system_database(&mut self) -> &mut Self {
+        self.database = SYSTEM_DATABASE.into();
+        self.system_database = true;
+        self.max_connections = 1;
+        self
+    }
     /// Set the time to wait for the database server to become available.
     ///
     /// This works by ignoring certain errors known to happen while the
@@ -833,6 +903,78 @@ impl Builder {
         self
     }
 
+    /// Set the highest protocol version to request from the server.
+    ///
+    /// By default the latest version supported by this client is requested.
+    /// Lowering it may be useful for proxies or servers that don't handle
+    /// newer protocol versions correctly. Versions higher than
+    /// [`ProtocolVersion::current()`] are rejected when building the config.
+    pub fn max_protocol_version(&mut self, value: ProtocolVersion)
+        -> &mut Self
+    {
+        self.max_protocol = value;
+        self
+    }
+
+    /// Set the lowest protocol version that is acceptable.
+    ///
+    /// Connection fails if the server negotiates a version lower than this
+    /// one. By default, any version supported by the client is accepted.
+    pub fn min_protocol_version(&mut self, value: ProtocolVersion)
+        -> &mut Self
+    {
+        self.min_protocol = Some(value);
+        self
+    }
+
+    /// Record timings of every step of connection establishment.
+    ///
+    /// Trace of the latest connection attempt is available via
+    /// [`Client::last_connection_trace`](crate::Client::last_connection_trace)
+    /// and every step is logged at debug level. This is useful to find out
+    /// why establishing a connection is slow (e.g. slow DNS or a
+    /// non-responding TLS handshake). Disabled by default.
+    pub fn trace_connection(&mut self, value: bool) -> &mut Self {
+        self.trace_connection = value;
+        self
+    }
+
+    /// Set a callback that reports progress of waiting for the server
+    ///
+    /// The callback is invoked after every failed connection attempt that
+    /// is going to be retried, i.e. while waiting for the server to become
+    /// available (see [`wait_until_available`](Self::wait_until_available)).
+    /// This allows startup scripts and orchestration tools to show what is
+    /// going on instead of hanging silently. The callback must not block.
+    pub fn on_connect_progress(&mut self,
+        callback: impl Fn(&ConnectProgress) + Send + Sync + 'static)
+        -> &mut Self
+    {
+        self.connect_progress = Some(ProgressCallback(Arc::new(callback)));
+        self
+    }
+
+    /// Set the source of randomness used by the connections
+    ///
+    /// Useful on platforms where random number generator of the operating
+    /// system is not available or when a specific generator is required.
+    /// See [`Entropy`] for details. [`OsEntropy`] is used by default.
+    pub fn entropy(&mut self, entropy: impl Entropy + 'static) -> &mut Self {
+        self.entropy = Arc::new(entropy);
+        self
+    }
+
+    /// Customize TLS configuration of the connections
+    ///
+    /// See [`TlsConfigure`] for what can be changed. Certificate
+    /// verification settings of the builder still apply.
+    pub fn tls_configure(&mut self, configure: impl TlsConfigure + 'static)
+        -> &mut Self
+    {
+        self.tls_configure = Some(Arc::new(configure));
+        self
+    }
+
     fn insecure(&self) -> bool {
         use TlsSecurity::Insecure;
         self.insecure_dev_mode || self.tls_security == Insecure
@@ -874,6 +1016,37 @@ impl Builder {
                 Run `edgedb project init` or use environment variables \
                 to configure connection."));
         }
+        if self.system_database && self.database != SYSTEM_DATABASE {
+            return Err(ClientError::with_message(format!(
+                "system database connection is requested, \
+                 but database is set to {:?}", self.database)));
+        }
+        if !self.system_database && self.database == SYSTEM_DATABASE {
+            return Err(ClientError::with_message(format!(
+                "database {:?} is reserved for administrative operations, \
+                 use `Builder::system_database()` to connect to it",
+                 SYSTEM_DATABASE)));
+        }
+        let (max_major, max_minor) = self.max_protocol.version_tuple();
+        let current = ProtocolVersion::current();
+        if !current.is_at_least(max_major, max_minor) {
+            return Err(ClientError::with_message(format!(
+                "max_protocol_version {}.{} is higher than {}.{} \
+                 supported by the client",
+                max_major, max_minor,
+                current.version_tuple().0, current.version_tuple().1)));
+        }
+        if let Some(min) = &self.min_protocol {
+            let (major, minor) = min.version_tuple();
+            if !self.max_protocol.is_at_least(major, minor) {
+                return Err(ClientError::with_message(format!(
+                    "min_protocol_version {}.{} is higher than \
+                     max_protocol_version {}.{}",
+                    major, minor,
+                    self.max_protocol.version_tuple().0,
+                    self.max_protocol.version_tuple().1)));
+            }
+        }
         let address = self.addr.clone();
         let verifier = match self.tls_security {
             _ if self.insecure() => Arc::new(tls::NullVerifier) as Verifier,
@@ -915,6 +1088,12 @@ impl Builder {
             wait: self.wait,
             connect_timeout: self.connect_timeout,
             insecure_dev_mode: self.insecure_dev_mode,
+            min_protocol: self.min_protocol.clone(),
+            max_protocol: self.max_protocol.clone(),
+            trace_connection: self.trace_connection,
+            connect_progress: self.connect_progress.clone(),
+            entropy: self.entropy.clone(),
+            tls_configure: self.tls_configure.clone(),
 
             // Pool configuration
             max_connections: self.max_connections,
@@ -932,6 +1111,24 @@ fn validate_certs(data: &str) -> Result<(), Error> {
     Ok(())
 }
 
+#[cfg(feature="zeroize")]
+impl Drop for Builder {
+    fn drop(&mut self) {
+        use zeroize::Zeroize;
+
+        self.password.zeroize();
+    }
+}
+
+#[cfg(feature="zeroize")]
+impl Drop for ConfigInner {
+    fn drop(&mut self) {
+        use zeroize::Zeroize;
+
+        self.password.zeroize();
+    }
+}
+
 impl fmt::Debug for Config {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("Config")
@@ -941,3 +1138,31 @@ impl fmt::Debug for Config {
             .finish()
     }
 }
+
+#[test]
+fn system_database() {
+    let mut builder = Builder::uninitialized();
+    builder.host_port(None::<&str>, None);
+    builder.database(SYSTEM_DATABASE);
+    assert!(builder.build().is_err());
+    builder.system_database();
+    let config = builder.build().unwrap();
+    assert_eq!(config.0.database, SYSTEM_DATABASE);
+    assert_eq!(config.0.max_connections, 1);
+    builder.database("edgedb");
+    assert!(builder.build().is_err());
+}
+
+#[test]
+fn max_protocol_version() {
+    let mut builder = Builder::uninitialized();
+    builder.host_port(None::<&str>, None);
+    let (major, minor) = ProtocolVersion::current().version_tuple();
+    builder.max_protocol_version(ProtocolVersion::new(major, minor + 1));
+    assert!(builder.build().is_err());
+    builder.max_protocol_version(ProtocolVersion::new(major + 1, 0));
+    assert!(builder.build().is_err());
+    builder.max_protocol_version(ProtocolVersion::new(0, 13));
+    assert_eq!(builder.build().unwrap().0.max_protocol,
+               ProtocolVersion::new(0, 13));
+}
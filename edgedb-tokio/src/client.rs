@@ -1,20 +1,49 @@
 use std::sync::Arc;
 use std::future::Future;
+use std::time::Duration;
 
-use bytes::BytesMut;
-use edgedb_protocol::model::Json;
+use bytes::{Bytes, BytesMut};
+use tokio::time::{sleep, timeout};
+use edgedb_protocol::model::{self, Json, Uuid};
+use edgedb_protocol::value::Value;
 use edgedb_protocol::common::CompilationOptions;
+use edgedb_protocol::features::ProtocolVersion;
 use edgedb_protocol::common::{IoFormat, Capabilities, Cardinality};
-use edgedb_protocol::query_arg::{QueryArgs, Encoder};
-use edgedb_protocol::QueryResult;
+use edgedb_protocol::query_arg::{QueryArgs, Encoder, ExternalData};
+use edgedb_protocol::{QueryResult, WithId};
+use edgedb_protocol::server_message::CommandDataDescription1;
 
-use crate::raw::Pool;
+use crate::raw::{Pool, Connection, Request};
 use crate::builder::Config;
 use crate::errors::{Error, ErrorKind};
 use crate::errors::{ProtocolEncodingError, NoResultExpected, NoDataError};
+use crate::errors::{ResultCardinalityMismatchError};
+use crate::errors::{ClientConnectionClosedError};
+use crate::errors::{ClientConnectionError};
+use crate::errors::{ProtocolOutOfOrderError};
 use crate::transaction::{Transaction, transaction};
-use crate::options::{TransactionOptions, RetryOptions};
+use crate::options::{TransactionOptions, RetryOptions, OfflineQueue};
 use crate::raw::Options;
+use crate::trace::ConnectionTrace;
+use crate::schema_watch::SchemaWatcher;
+
+/// Information about an executed query
+///
+/// Returned by [`Client::query_with_metadata`] along with the results.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ResultMetadata {
+    /// Id of the output type descriptor
+    ///
+    /// Can be used as a cache key for the result codec.
+    pub output_typedesc_id: Uuid,
+    /// Result cardinality as inferred by the server
+    pub cardinality: Cardinality,
+    /// Capabilities used by the query
+    pub capabilities: Capabilities,
+    /// Command status, e.g. `SELECT` or `INSERT`
+    pub status: String,
+}
 
 /// EdgeDB Client
 ///
@@ -24,6 +53,34 @@ use crate::raw::Options;
 /// gets database connection configuration from environment). You can also use
 /// [`Builder`](crate::Builder) to [`build`](`crate::Builder::build`) custom
 /// [`Config`] and [create a client](Client::new) using that config.
+///
+/// # Result Cardinality
+///
+/// Query method should be picked according to the expected number of
+/// results:
+///
+/// * [`query_single`](Client::query_single) returns `Option<T>` (for
+///   `AtMostOne` cardinality)
+/// * [`query_required_single`](Client::query_required_single) returns `T`
+///   (for `One` cardinality)
+/// * [`query`](Client::query) returns `Vec<T>` (for `Many` cardinality)
+/// * [`query_required`](Client::query_required) returns non-empty `Vec<T>`
+///   (for `AtLeastOne` cardinality)
+///
+/// Single-result methods fail with
+/// [`ResultCardinalityMismatchError`][crate::errors::ResultCardinalityMismatchError]
+/// if the query can return more than one element, and required methods fail
+/// with [`NoDataError`][crate::errors::NoDataError] if no elements were
+/// returned.
+///
+/// # Multiple Runtimes
+///
+/// Client (and its clones) can be used from several tokio runtimes, which
+/// is common in tests where every test has its own runtime. Pooled
+/// connections are only reused on the runtime they were established on,
+/// connections of any other runtime (whether it has been shut down or not)
+/// are closed and replaced by new ones. So switching between runtimes
+/// often makes the pool reconnect often.
 #[derive(Debug, Clone)]
 pub struct Client {
     options: Arc<Options>,
@@ -43,6 +100,33 @@ impl Client {
         }
     }
 
+    /// Switch the client to a new configuration without downtime.
+    ///
+    /// New connections are established using the new config, idle
+    /// connections are closed, and connections that are currently used by
+    /// queries or transactions are closed as soon as they are released.
+    /// This can be used to rotate passwords or TLS certificates, or to
+    /// move to a different host. The change applies to all clones of this
+    /// client.
+    ///
+    /// Note: pool size
+    /// ([`max_connections`](crate::Builder::max_connections)) of the new
+    /// config is ignored, the pool keeps its original size.
+    ///
+    /// ```rust,no_run
+    /// # async fn main_(client: edgedb_tokio::Client)
+    /// #     -> Result<(), edgedb_tokio::Error>
+    /// # {
+    /// let mut builder = edgedb_tokio::Builder::from_env().await?;
+    /// builder.password("new-password");
+    /// client.reload_config(&builder.build()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn reload_config(&self, config: &Config) {
+        self.pool.reload_config(config);
+    }
+
     /// Ensure that there is at least one working connection to the pool.
     ///
     /// This can be used at application startup to ensure that you have a
@@ -52,6 +136,75 @@ impl Client {
         Ok(())
     }
 
+    /// Return protocol version negotiated with the server.
+    ///
+    /// This establishes a connection if there is none in the pool yet. See
+    /// [`Builder::max_protocol_version`](crate::Builder::max_protocol_version)
+    /// to limit the version requested from the server.
+    pub async fn protocol_version(&self) -> Result<ProtocolVersion, Error> {
+        let conn = self.pool.acquire().await?;
+        Ok(conn.proto().clone())
+    }
+
+    /// Returns trace of the most recent connection establishment
+    ///
+    /// Returns `None` if no connection has been made yet or tracing is not
+    /// enabled with
+    /// [`Builder::trace_connection`](crate::Builder::trace_connection). The
+    /// trace is recorded for failed connection attempts too.
+    pub fn last_connection_trace(&self) -> Option<ConnectionTrace> {
+        self.pool.last_connection_trace()
+    }
+
+    /// Returns current schema version
+    ///
+    /// Schema version is the name of the most recently applied migration,
+    /// or `None` if the database has no migrations. Note: schema changes
+    /// done by DDL outside of migrations are not reflected.
+    pub async fn schema_version(&self) -> Result<Option<String>, Error> {
+        self.query_single("
+            SELECT (
+                SELECT schema::Migration
+                FILTER NOT EXISTS .<parents[IS schema::Migration]
+                LIMIT 1
+            ).name
+        ", &()).await
+    }
+
+    /// Start watching for schema changes
+    ///
+    /// Fetches current schema version and spawns a background task that
+    /// checks it every `interval`. Whenever new migration is applied, all
+    /// connections of this client (and its clones) are closed as soon as
+    /// they are released. This prevents errors caused by stale type
+    /// descriptors in long-running services. See [`SchemaWatcher`] for the
+    /// details.
+    ///
+    /// Must be called within the tokio runtime.
+    ///
+    /// ```rust,no_run
+    /// # async fn main_(client: edgedb_tokio::Client)
+    /// #     -> Result<(), edgedb_tokio::Error>
+    /// # {
+    /// use std::time::Duration;
+    ///
+    /// let mut watcher = client.watch_schema(Duration::from_secs(10)).await?;
+    /// loop {
+    ///     let version = watcher.changed().await?;
+    ///     println!("Migrated to {:?}", version);
+    /// }
+    /// # }
+    /// ```
+    pub async fn watch_schema(&self, interval: Duration)
+        -> Result<SchemaWatcher, Error>
+    {
+        SchemaWatcher::new(self.clone(), interval).await
+    }
+
+    pub(crate) fn invalidate_connections(&self) {
+        self.pool.invalidate();
+    }
+
     /// Execute a query and return a collection of results.
     ///
     /// You will usually have to specify the return type for the query:
@@ -70,8 +223,22 @@ impl Client {
         where A: QueryArgs,
               R: QueryResult,
     {
-        let mut conn = self.pool.acquire().await?;
+        self.query_with_metadata(query, arguments).await
+            .map(|(rows, _)| rows)
+    }
 
+    /// Execute a query and return results along with the query metadata
+    ///
+    /// This is similar to [`query()`](Self::query), but also returns
+    /// [`ResultMetadata`]: output type descriptor id, cardinality,
+    /// capabilities and the command status. This allows tooling to cache and
+    /// inspect queries without a separate
+    /// [`describe_queries`](Self::describe_queries) round-trip.
+    pub async fn query_with_metadata<R, A>(&self, query: &str, arguments: &A)
+        -> Result<(Vec<R>, ResultMetadata), Error>
+        where A: QueryArgs,
+              R: QueryResult,
+    {
         let flags = CompilationOptions {
             implicit_limit: None,
             implicit_typenames: false,
@@ -81,32 +248,257 @@ impl Client {
             io_format: IoFormat::Binary,
             expected_cardinality: Cardinality::Many,
         };
-        let desc = conn.parse(&flags, query).await?;
-        let inp_desc = desc.input()
-            .map_err(ProtocolEncodingError::with_source)?;
+        let (mut conn, desc) = self.acquire_and_parse(&flags, query).await?;
+        let (arguments, external) =
+            encode_streamed_arguments(&desc, arguments)?;
 
-        let mut arg_buf = BytesMut::with_capacity(8);
-        arguments.encode(&mut Encoder::new(
-            &inp_desc.as_query_arg_context(),
-            &mut arg_buf,
-        ))?;
-
-        let data = conn.execute(&flags, query, &desc, &arg_buf.freeze()).await?;
+        let response = conn.execute_external(&flags, query,
+            &self.options.state, &desc, &arguments, external).await?;
 
         let out_desc = desc.output()
             .map_err(ProtocolEncodingError::with_source)?;
         match out_desc.root_pos() {
             Some(root_pos) => {
-                let ctx = out_desc.as_queryable_context();
+                let mut ctx = out_desc.as_queryable_context();
+                ctx.unknown_scalars = self.options.unknown_scalars;
                 let mut state = R::prepare(&ctx, root_pos)?;
-                let rows = data.into_iter()
+                let rows = response.data.into_iter()
                     .flat_map(|chunk| chunk.data)
                     .map(|chunk| R::decode(&mut state, &chunk))
                     .collect::<Result<_, _>>()?;
-                Ok(rows)
+                let metadata = ResultMetadata {
+                    output_typedesc_id: desc.output_typedesc_id,
+                    cardinality: desc.result_cardinality,
+                    capabilities: desc.capabilities,
+                    status: String::from_utf8_lossy(&response.status_data)
+                        .into_owned(),
+                };
+                Ok((rows, metadata))
+            }
+            None => Err(NoResultExpected::with_message(
+                "query doesn't return any data")),
+        }
+    }
+
+    /// Acquire a connection from the pool and parse the query
+    ///
+    /// The server may close a connection while it's idle in the pool, which
+    /// is only noticed on the next request. No query has been executed at
+    /// this point, so the query is retried on a new connection according to
+    /// the [`IdleConnection`](crate::RetryCondition::IdleConnection) rule.
+    async fn acquire_and_parse(&self, flags: &CompilationOptions, query: &str)
+        -> Result<(Connection, CommandDataDescription1), Error>
+    {
+        let mut iteration = 0;
+        loop {
+            let mut conn = self.acquire(&[query]).await?;
+            match conn.parse(flags, query, &self.options.state).await {
+                Ok(desc) => return Ok((conn, desc)),
+                Err(e) => self.retry_parse(&mut iteration, e).await?,
+            }
+        }
+    }
+
+    /// Same as [`acquire_and_parse`](Self::acquire_and_parse) but parses
+    /// multiple queries in a single round trip
+    async fn acquire_and_parse_many(&self, flags: &CompilationOptions,
+                                    queries: &[&str])
+        -> Result<(Connection, Vec<CommandDataDescription1>), Error>
+    {
+        let mut iteration = 0;
+        loop {
+            let mut conn = self.acquire(queries).await?;
+            match conn.parse_many(flags, queries, &self.options.state).await {
+                Ok(descs) => return Ok((conn, descs)),
+                Err(e) => self.retry_parse(&mut iteration, e).await?,
+            }
+        }
+    }
+
+    /// Acquire a connection, waiting in the offline queue if enabled
+    async fn acquire(&self, queries: &[&str]) -> Result<Connection, Error> {
+        let err = match self.pool.acquire().await {
+            Err(e) if e.is::<ClientConnectionError>() => e,
+            res => return res,
+        };
+        let queue = match &self.options.offline {
+            Some(queue) if queue.accepts(queries) => queue,
+            _ => return Err(err),
+        };
+        let _slot = match queue.enter() {
+            Some(slot) => slot,
+            None => return Err(err.context("offline queue is full")),
+        };
+        log::info!("Server is unreachable, query is queued: {:#}", err);
+        let wait = async {
+            // only one queued query probes the server at a time, and the
+            // lock is fair, so queries leave the queue in submission order.
+            // Once the server is back each query connects right away, so
+            // the queue drains without waiting for the probe interval.
+            let _probe = queue.probe.lock().await;
+            loop {
+                match self.pool.acquire().await {
+                    Err(e) if e.is::<ClientConnectionError>() => {
+                        log::debug!("Server is still unreachable: {:#}", e);
+                    }
+                    res => return res,
+                }
+                sleep(queue.get_probe_interval()).await;
+            }
+        };
+        match timeout(queue.get_ttl(), wait).await {
+            Ok(res) => res,
+            Err(_) => Err(err.context(
+                "query has expired in the offline queue")),
+        }
+    }
+
+    /// Waits before the next parse attempt or returns the error if it
+    /// shouldn't be retried
+    async fn retry_parse(&self, iteration: &mut u32, e: Error)
+        -> Result<(), Error>
+    {
+        if !e.is::<ClientConnectionClosedError>() {
+            return Err(e);
+        }
+        let rule = self.options.retry.get_rule(&e);
+        if *iteration >= rule.attempts {
+            return Err(e);
+        }
+        log::info!("Retrying query on {:#}", e);
+        *iteration += 1;
+        let backoff = rule.delay(*iteration, &*self.pool.entropy());
+        self.options.retry.notify_retry(*iteration, &e, backoff);
+        sleep(backoff).await;
+        Ok(())
+    }
+
+    /// Execute a query along with a query counting all the results
+    ///
+    /// This is a helper for paginated endpoints, which need a page of
+    /// results and the total number of items. It takes two round trips on
+    /// the same connection: both queries are parsed in the first one and
+    /// executed in the second one (arguments can't be encoded before the
+    /// server describes their types). Running the queries one by one takes
+    /// four round trips.
+    ///
+    /// `count_query` must return a single integer, and is usually the same
+    /// filter as `query` without `OFFSET` and `LIMIT`:
+    ///
+    /// ```rust,no_run
+    /// # async fn main_(client: edgedb_tokio::Client)
+    /// #     -> Result<(), edgedb_tokio::Error>
+    /// # {
+    /// let (names, total) = client.query_counted::<String, _, _>(
+    ///     "SELECT User.name ORDER BY User.name OFFSET <int64>$0 LIMIT 10",
+    ///     &(20_i64,),
+    ///     "SELECT count(User)",
+    ///     &(),
+    /// ).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Queries are executed outside of a transaction, so the total may not
+    /// match the page exactly if data is modified concurrently. Arguments
+    /// streamed with [`StreamArg`](crate::StreamArg) are not supported.
+    pub async fn query_counted<R, A, C>(&self,
+                                        query: &str, arguments: &A,
+                                        count_query: &str,
+                                        count_arguments: &C)
+        -> Result<(Vec<R>, usize), Error>
+        where A: QueryArgs,
+              C: QueryArgs,
+              R: QueryResult,
+    {
+        let flags = CompilationOptions {
+            implicit_limit: None,
+            implicit_typenames: false,
+            implicit_typeids: false,
+            explicit_objectids: true,
+            allow_capabilities: Capabilities::MODIFICATIONS,
+            io_format: IoFormat::Binary,
+            expected_cardinality: Cardinality::Many,
+        };
+        let (mut conn, descs) = self.acquire_and_parse_many(
+            &flags, &[query, count_query]).await?;
+        let (desc, count_desc) = (&descs[0], &descs[1]);
+        let arguments = encode_arguments(desc, arguments)?;
+        let count_arguments = encode_arguments(count_desc, count_arguments)?;
+
+        let mut responses = conn.execute_many(&flags, &[
+            Request { query, desc, arguments },
+            Request {
+                query: count_query,
+                desc: count_desc,
+                arguments: count_arguments,
+            },
+        ], &self.options.state).await?.into_iter();
+        let (response, count_response) = match
+            (responses.next(), responses.next())
+        {
+            (Some(r), Some(c)) => (r, c),
+            _ => return Err(ProtocolOutOfOrderError::with_message(
+                "expected a response for each query")),
+        };
+
+        let out_desc = desc.output()
+            .map_err(ProtocolEncodingError::with_source)?;
+        let rows = match out_desc.root_pos() {
+            Some(root_pos) => {
+                let mut ctx = out_desc.as_queryable_context();
+                ctx.unknown_scalars = self.options.unknown_scalars;
+                let mut state = R::prepare(&ctx, root_pos)?;
+                response.data.into_iter()
+                    .flat_map(|chunk| chunk.data)
+                    .map(|chunk| R::decode(&mut state, &chunk))
+                    .collect::<Result<_, _>>()?
+            }
+            None => return Err(NoResultExpected::with_message(
+                "query doesn't return any data")),
+        };
+
+        let out_desc = count_desc.output()
+            .map_err(ProtocolEncodingError::with_source)?;
+        let root_pos = out_desc.root_pos()
+            .ok_or_else(|| NoResultExpected::with_message(
+                "count query doesn't return any data"))?;
+        let ctx = out_desc.as_queryable_context();
+        let mut state = i64::prepare(&ctx, root_pos)
+            .map_err(|e| e.context("count query must return an integer"))?;
+        let mut counts = count_response.data.into_iter()
+            .flat_map(|chunk| chunk.data);
+        let count = match (counts.next(), counts.next()) {
+            (Some(bytes), None) => i64::decode(&mut state, &bytes)?,
+            (None, _) => return Err(NoDataError::with_message(
+                "count query returned zero results")),
+            (Some(_), Some(_)) => {
+                return Err(ResultCardinalityMismatchError::with_message(
+                    "expected one row from count query, \
+                     but it returned more"));
             }
-            None => Err(NoResultExpected::build()),
+        };
+        let count = usize::try_from(count)
+            .map_err(ProtocolEncodingError::with_source)?;
+        Ok((rows, count))
+    }
+
+    /// Execute a query and return a non-empty collection of results
+    ///
+    /// This is similar to [`query()`](Self::query), but if the query returns
+    /// an empty set, a [`NoDataError`][crate::errors::NoDataError] is raised.
+    /// This is useful for queries of `AtLeastOne` cardinality.
+    pub async fn query_required<R, A>(&self, query: &str, arguments: &A)
+        -> Result<Vec<R>, Error>
+        where A: QueryArgs,
+              R: QueryResult,
+    {
+        let rows = self.query(query, arguments).await?;
+        if rows.is_empty() {
+            return Err(NoDataError::with_message(
+                "query returned zero results"));
         }
+        Ok(rows)
     }
 
     /// Execute a query and return a single result
@@ -130,8 +522,6 @@ impl Client {
         where A: QueryArgs,
               R: QueryResult,
     {
-        let mut conn = self.pool.acquire().await?;
-
         let flags = CompilationOptions {
             implicit_limit: None,
             implicit_typenames: false,
@@ -141,33 +531,35 @@ impl Client {
             io_format: IoFormat::Binary,
             expected_cardinality: Cardinality::AtMostOne,
         };
-        let desc = conn.parse(&flags, query).await?;
-        let inp_desc = desc.input()
-            .map_err(ProtocolEncodingError::with_source)?;
+        let (mut conn, desc) = self.acquire_and_parse(&flags, query).await?;
+        let (arguments, external) =
+            encode_streamed_arguments(&desc, arguments)?;
 
-        let mut arg_buf = BytesMut::with_capacity(8);
-        arguments.encode(&mut Encoder::new(
-            &inp_desc.as_query_arg_context(),
-            &mut arg_buf,
-        ))?;
-
-        let data = conn.execute(&flags, query, &desc, &arg_buf.freeze()).await?;
+        let data = conn.execute_external(&flags, query,
+            &self.options.state, &desc, &arguments, external).await?
+            .data;
 
         let out_desc = desc.output()
             .map_err(ProtocolEncodingError::with_source)?;
         match out_desc.root_pos() {
             Some(root_pos) => {
-                let ctx = out_desc.as_queryable_context();
+                let mut ctx = out_desc.as_queryable_context();
+                ctx.unknown_scalars = self.options.unknown_scalars;
                 let mut state = R::prepare(&ctx, root_pos)?;
-                let bytes = data.into_iter().next()
-                    .and_then(|chunk| chunk.data.into_iter().next());
+                let mut rows = data.into_iter().flat_map(|chunk| chunk.data);
+                let bytes = rows.next();
+                if rows.next().is_some() {
+                    return Err(ResultCardinalityMismatchError::with_message(
+                        "expected at most one row, but query returned more"));
+                }
                 if let Some(bytes) = bytes {
                     Ok(Some(R::decode(&mut state, &bytes)?))
                 } else {
                     Ok(None)
                 }
             }
-            None => Err(NoResultExpected::build()),
+            None => Err(NoResultExpected::with_message(
+                "query doesn't return any data")),
         }
     }
 
@@ -206,12 +598,63 @@ impl Client {
                         "query row returned zero results"))
     }
 
+    /// Execute a query and collect results into a map
+    ///
+    /// Every row returned by the query must be a two-element tuple: the
+    /// first element is used as a key and the second one as a value. The
+    /// result can be collected into any [`FromIterator`] of pairs, such as
+    /// [`HashMap`](std::collections::HashMap) or
+    /// [`BTreeMap`](std::collections::BTreeMap):
+    ///
+    /// ```rust,ignore
+    /// let names: HashMap<Uuid, String> = client.query_map(
+    ///     "SELECT (User.id, User.name)",
+    ///     &(),
+    /// ).await?;
+    /// ```
+    ///
+    /// Values can be objects too, as long as the value type derives
+    /// `Queryable`. Use [`query_map_by_id`](Self::query_map_by_id) to key
+    /// objects by their `id` without wrapping them into a tuple.
+    ///
+    /// If the same key is returned multiple times, the last value wins.
+    pub async fn query_map<K, V, M, A>(&self, query: &str, arguments: &A)
+        -> Result<M, Error>
+        where A: QueryArgs,
+              (K, V): QueryResult,
+              M: FromIterator<(K, V)>,
+    {
+        let rows = self.query::<(K, V), _>(query, arguments).await?;
+        Ok(rows.into_iter().collect())
+    }
+
+    /// Execute a query returning objects and collect them into a map keyed
+    /// by object `id`
+    ///
+    /// The `id` must be selected in the shape. The object (including the
+    /// `id`) is decoded into `V`, so a `V` deriving `Queryable` must have an
+    /// `id` field as well:
+    ///
+    /// ```rust,ignore
+    /// let users: HashMap<Uuid, User> = client.query_map_by_id(
+    ///     "SELECT User { id, name, age }",
+    ///     &(),
+    /// ).await?;
+    /// ```
+    pub async fn query_map_by_id<V, M, A>(&self, query: &str, arguments: &A)
+        -> Result<M, Error>
+        where A: QueryArgs,
+              V: QueryResult,
+              M: FromIterator<(Uuid, V)>,
+    {
+        let rows = self.query::<WithId<V>, _>(query, arguments).await?;
+        Ok(rows.into_iter().map(|row| (row.id, row.value)).collect())
+    }
+
     /// Execute a query and return the result as JSON.
     pub async fn query_json(&self, query: &str, arguments: &impl QueryArgs)
         -> Result<Json, Error>
     {
-        let mut conn = self.pool.acquire().await?;
-
         let flags = CompilationOptions {
             implicit_limit: None,
             implicit_typenames: false,
@@ -221,17 +664,13 @@ impl Client {
             io_format: IoFormat::Json,
             expected_cardinality: Cardinality::Many,
         };
-        let desc = conn.parse(&flags, query).await?;
-        let inp_desc = desc.input()
-            .map_err(ProtocolEncodingError::with_source)?;
-
-        let mut arg_buf = BytesMut::with_capacity(8);
-        arguments.encode(&mut Encoder::new(
-            &inp_desc.as_query_arg_context(),
-            &mut arg_buf,
-        ))?;
+        let (mut conn, desc) = self.acquire_and_parse(&flags, query).await?;
+        let (arguments, external) =
+            encode_streamed_arguments(&desc, arguments)?;
 
-        let data = conn.execute(&flags, query, &desc, &arg_buf.freeze()).await?;
+        let data = conn.execute_external(&flags, query,
+            &self.options.state, &desc, &arguments, external).await?
+            .data;
 
         let out_desc = desc.output()
             .map_err(ProtocolEncodingError::with_source)?;
@@ -251,7 +690,8 @@ impl Client {
                         "query row returned zero results"))
                 }
             }
-            None => Err(NoResultExpected::build()),
+            None => Err(NoResultExpected::with_message(
+                "query doesn't return any data")),
         }
     }
 
@@ -265,8 +705,6 @@ impl Client {
                                    query: &str, arguments: &impl QueryArgs)
         -> Result<Option<Json>, Error>
     {
-        let mut conn = self.pool.acquire().await?;
-
         let flags = CompilationOptions {
             implicit_limit: None,
             implicit_typenames: false,
@@ -276,17 +714,13 @@ impl Client {
             io_format: IoFormat::Json,
             expected_cardinality: Cardinality::AtMostOne,
         };
-        let desc = conn.parse(&flags, query).await?;
-        let inp_desc = desc.input()
-            .map_err(ProtocolEncodingError::with_source)?;
-
-        let mut arg_buf = BytesMut::with_capacity(8);
-        arguments.encode(&mut Encoder::new(
-            &inp_desc.as_query_arg_context(),
-            &mut arg_buf,
-        ))?;
+        let (mut conn, desc) = self.acquire_and_parse(&flags, query).await?;
+        let (arguments, external) =
+            encode_streamed_arguments(&desc, arguments)?;
 
-        let data = conn.execute(&flags, query, &desc, &arg_buf.freeze()).await?;
+        let data = conn.execute_external(&flags, query,
+            &self.options.state, &desc, &arguments, external).await?
+            .data;
 
         let out_desc = desc.output()
             .map_err(ProtocolEncodingError::with_source)?;
@@ -295,8 +729,12 @@ impl Client {
                 let ctx = out_desc.as_queryable_context();
                 // JSON objects are returned as strings :(
                 let mut state = String::prepare(&ctx, root_pos)?;
-                let bytes = data.into_iter().next()
-                    .and_then(|chunk| chunk.data.into_iter().next());
+                let mut rows = data.into_iter().flat_map(|chunk| chunk.data);
+                let bytes = rows.next();
+                if rows.next().is_some() {
+                    return Err(ResultCardinalityMismatchError::with_message(
+                        "expected at most one row, but query returned more"));
+                }
                 if let Some(bytes) = bytes {
                     // we trust database to produce valid json
                     let s = String::decode(&mut state, &bytes)?;
@@ -305,7 +743,8 @@ impl Client {
                     Ok(None)
                 }
             }
-            None => Err(NoResultExpected::build()),
+            None => Err(NoResultExpected::with_message(
+                "query doesn't return any data")),
         }
     }
 
@@ -325,6 +764,34 @@ impl Client {
                         "query row returned zero results"))
     }
 
+    /// Describe input and output types of multiple queries
+    ///
+    /// All queries are parsed over a single connection in one round trip
+    /// (when the server supports protocol 1.0), so this is the preferred way
+    /// to introspect a lot of queries at once, e.g. for code generation.
+    ///
+    /// Descriptions are returned in the same order as queries. Use
+    /// [`input()`](CommandDataDescription1::input) and
+    /// [`output()`](CommandDataDescription1::output) to decode type
+    /// descriptors. If any query fails to compile, the error is returned
+    /// with the index of the query in the context.
+    pub async fn describe_queries(&self, queries: &[&str])
+        -> Result<Vec<CommandDataDescription1>, Error>
+    {
+        let mut conn = self.pool.acquire().await?;
+
+        let flags = CompilationOptions {
+            implicit_limit: None,
+            implicit_typenames: false,
+            implicit_typeids: false,
+            explicit_objectids: true,
+            allow_capabilities: Capabilities::MODIFICATIONS,
+            io_format: IoFormat::Binary,
+            expected_cardinality: Cardinality::Many,
+        };
+        conn.parse_many(&flags, queries, &self.options.state).await
+    }
+
     /// Execute a transaction
     ///
     /// Transaction body must be encompassed in the closure. The closure **may
@@ -339,6 +806,13 @@ impl Client {
     /// anywhere and do not send to another coroutine. Pass to all further
     /// function calls by reference.
     ///
+    /// # Errors
+    ///
+    /// Queries made on this client (or any of its clones) from within the
+    /// closure fail with [`InterfaceError`](crate::errors::InterfaceError),
+    /// because they would silently run outside of the transaction. Use
+    /// methods of the [`Transaction`] object instead.
+    ///
     /// # Example
     ///
     /// ```rust,no_run
@@ -377,6 +851,9 @@ impl Client {
             options: Arc::new(Options {
                 transaction: options,
                 retry: self.options.retry.clone(),
+                state: self.options.state.clone(),
+                offline: self.options.offline.clone(),
+                unknown_scalars: self.options.unknown_scalars,
             }),
             pool: self.pool.clone(),
         }
@@ -396,8 +873,276 @@ impl Client {
             options: Arc::new(Options {
                 transaction: self.options.transaction.clone(),
                 retry: options,
+                state: self.options.state.clone(),
+                offline: self.options.offline.clone(),
+                unknown_scalars: self.options.unknown_scalars,
+            }),
+            pool: self.pool.clone(),
+        }
+    }
+    /// Returns client that queues queries while the server is unreachable
+    ///
+    /// This method returns a "shallow copy" of the current client. See
+    /// [`OfflineQueue`] for the details. Only queries run directly on the
+    /// client are queued, transactions fail as usual when the connection
+    /// can't be established.
+    ///
+    /// ```rust,no_run
+    /// # async fn main_(client: edgedb_tokio::Client)
+    /// #     -> Result<(), edgedb_tokio::Error>
+    /// # {
+    /// use std::time::Duration;
+    /// use edgedb_tokio::OfflineQueue;
+    ///
+    /// let client = client.with_offline_queue(OfflineQueue::default()
+    ///     .depth(1000)
+    ///     .ttl(Duration::from_secs(3600)));
+    /// client.query_required_single::<i64, _>("
+    ///     SELECT count((INSERT Reading { value := <float64>$0 }))
+    /// ", &(21.5,)).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_offline_queue(&self, queue: OfflineQueue) -> Self {
+        Client {
+            options: Arc::new(Options {
+                transaction: self.options.transaction.clone(),
+                retry: self.options.retry.clone(),
+                state: self.options.state.clone(),
+                offline: Some(queue),
+                unknown_scalars: self.options.unknown_scalars,
+            }),
+            pool: self.pool.clone(),
+        }
+    }
+    /// Returns client with the statement timeout set
+    ///
+    /// This method returns a "shallow copy" of the current client which sets
+    /// `query_execution_timeout` session config for every query (including
+    /// ones run in transactions). Server aborts queries that run longer than
+    /// this timeout with an error.
+    ///
+    /// The original client is not affected. The setting is not persisted on
+    /// the connection, so it goes away as soon as the returned client is
+    /// dropped. Timeout of zero disables the timeout.
+    ///
+    /// Requires EdgeDB 2.0 or later (protocol 1.0).
+    pub fn with_query_timeout(&self, timeout: Duration) -> Self {
+        let micros = timeout.as_micros().try_into().unwrap_or(i64::MAX);
+        let state = self.options.state.with_config(
+            "query_execution_timeout",
+            Value::Duration(model::Duration::from_micros(micros)),
+        );
+        Client {
+            options: Arc::new(Options {
+                transaction: self.options.transaction.clone(),
+                retry: self.options.retry.clone(),
+                state: Arc::new(state),
+                offline: self.options.offline.clone(),
+                unknown_scalars: self.options.unknown_scalars,
+            }),
+            pool: self.pool.clone(),
+        }
+    }
+    /// Returns client with global values set
+    ///
+    /// This method returns a "shallow copy" of the current client, which
+    /// sends the globals along with every query (including ones run in
+    /// transactions). Names without a module, like `current_user_id`, refer
+    /// to globals in the `default` module. Values set earlier are kept
+    /// unless overridden.
+    ///
+    /// ```rust,no_run
+    /// # async fn main_(client: edgedb_tokio::Client)
+    /// #     -> Result<(), edgedb_tokio::Error>
+    /// # {
+    /// use edgedb_protocol::value::Value;
+    ///
+    /// let user_client = client.with_globals([
+    ///     ("current_user", Value::Str("alice".into())),
+    /// ]);
+    /// let name = user_client.query_required_single::<String, _>(
+    ///     "SELECT global current_user", &()).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// This is cheap, so it's fine to create a client per request. With
+    /// the `tower` feature, `GlobalsLayer` does that in a middleware.
+    /// Requires EdgeDB 2.0 or later (protocol 1.0).
+    pub fn with_globals<K>(&self, globals: impl IntoIterator<Item=(K, Value)>)
+        -> Self
+        where K: AsRef<str>,
+    {
+        Client {
+            options: Arc::new(Options {
+                transaction: self.options.transaction.clone(),
+                retry: self.options.retry.clone(),
+                state: Arc::new(self.options.state.with_globals(globals)),
+                offline: self.options.offline.clone(),
+                unknown_scalars: self.options.unknown_scalars,
+            }),
+            pool: self.pool.clone(),
+        }
+    }
+    #[cfg(test)]
+    pub(crate) fn session_state(&self) -> &crate::state::SessionState {
+        &self.options.state
+    }
+    /// Returns client that adds session state to the errors
+    ///
+    /// This method returns a "shallow copy" of the current client. When
+    /// enabled, errors of failed queries (including ones run in
+    /// transactions) get an additional context line listing the config
+    /// settings and the names of the globals set on the client, along with
+    /// the capabilities allowed for the query. Values of the globals are
+    /// not included. This helps to find out why a query that works in the
+    /// REPL fails in the application.
+    ///
+    /// ```rust,no_run
+    /// # async fn main_(client: edgedb_tokio::Client)
+    /// #     -> Result<(), edgedb_tokio::Error>
+    /// # {
+    /// let client = client.with_error_state_snapshot(true);
+    /// if let Err(e) = client.query::<i64, _>("SELECT 1/0", &()).await {
+    ///     // prints something like:
+    ///     // session state: config {}; globals {default::current_user:
+    ///     // <redacted>}; allowed capabilities: MODIFICATIONS
+    ///     for line in e.contexts() {
+    ///         eprintln!("{}", line);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_error_state_snapshot(&self, enable: bool) -> Self {
+        Client {
+            options: Arc::new(Options {
+                transaction: self.options.transaction.clone(),
+                retry: self.options.retry.clone(),
+                state: Arc::new(
+                    self.options.state.with_error_snapshot(enable)),
+                offline: self.options.offline.clone(),
+                unknown_scalars: self.options.unknown_scalars,
+            }),
+            pool: self.pool.clone(),
+        }
+    }
+    /// Returns client that tolerates unknown scalar types in results
+    ///
+    /// This method returns a "shallow copy" of the current client. When
+    /// enabled, values of scalar types that this library doesn't know (for
+    /// example ones added by newer server versions or extensions) are
+    /// returned as [`Value::Unknown`] with the type id and raw data, instead
+    /// of failing the whole query. This only works for dynamically typed
+    /// results (i.e. [`Value`]), static types still reject unknown types.
+    /// Applies to transactions started by the returned client too.
+    pub fn with_unknown_scalars(&self, enable: bool) -> Self {
+        Client {
+            options: Arc::new(Options {
+                transaction: self.options.transaction.clone(),
+                retry: self.options.retry.clone(),
+                state: self.options.state.clone(),
+                offline: self.options.offline.clone(),
+                unknown_scalars: enable,
             }),
             pool: self.pool.clone(),
         }
     }
 }
+
+/// Encode arguments of a query that doesn't support streamed arguments
+fn encode_arguments<A: QueryArgs>(desc: &CommandDataDescription1,
+                                  arguments: &A)
+    -> Result<Bytes, Error>
+{
+    let inp_desc = desc.input()
+        .map_err(ProtocolEncodingError::with_source)?;
+    let ctx = inp_desc.as_query_arg_context();
+    let mut arg_buf = BytesMut::with_capacity(8);
+    arguments.encode(&mut Encoder::new(&ctx, &mut arg_buf))?;
+    Ok(arg_buf.freeze())
+}
+
+/// Encode arguments, returning data of streamed arguments separately
+pub(crate) fn encode_streamed_arguments<A: QueryArgs>(
+    desc: &CommandDataDescription1, arguments: &A)
+    -> Result<(Bytes, Vec<ExternalData>), Error>
+{
+    let inp_desc = desc.input()
+        .map_err(ProtocolEncodingError::with_source)?;
+    let ctx = inp_desc.as_query_arg_context();
+    let mut arg_buf = BytesMut::with_capacity(8);
+    let mut encoder = Encoder::with_external(&ctx, &mut arg_buf);
+    arguments.encode(&mut encoder)?;
+    let external = encoder.take_external();
+    Ok((arg_buf.freeze(), external))
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use edgedb_protocol::client_message::ClientMessage;
+
+    use crate::options::OfflineQueue;
+    use crate::raw::test_server::{TestServer, Reply, default_reply};
+
+    use super::Client;
+
+    #[tokio::test]
+    async fn retry_parse_on_idle_connection() {
+        let mut server = TestServer::new();
+        server.start_with(Arc::new(|index, msg| match msg {
+            // first connection is closed by the server while idle
+            ClientMessage::Parse(_) if index == 0 => Reply::Close,
+            msg => default_reply(msg),
+        }));
+        let client = Client::new(&server.config());
+        client.ensure_connected().await.unwrap();
+        assert_eq!(server.accepted(), 1);
+        let value = client.query_required_single::<String, _>(
+            "SELECT 'ok'", &()).await.unwrap();
+        assert_eq!(value, "ok");
+        assert_eq!(server.accepted(), 2);
+    }
+
+    #[tokio::test]
+    async fn offline_queue_drain() {
+        let parsed = Arc::new(Mutex::new(Vec::new()));
+        let mut server = TestServer::new();
+        server.start_with(Arc::new({
+            let parsed = parsed.clone();
+            move |index, msg| match msg {
+                // the first attempt of each query fails, so all of them
+                // are queued, and the first probe succeeds
+                ClientMessage::ClientHandshake(_) if index < 4 => Reply::Close,
+                ClientMessage::Parse(parse) => {
+                    parsed.lock().unwrap().push(parse.command_text.clone());
+                    default_reply(msg)
+                }
+                msg => default_reply(msg),
+            }
+        }));
+        // no query is ever sleeping for the probe interval, otherwise the
+        // test would not finish
+        let client = Client::new(&server.config())
+            .with_offline_queue(OfflineQueue::default()
+                                .probe_interval(Duration::from_secs(3600)));
+        let queries = (0..4).map(|i| {
+            let client = client.clone();
+            tokio::spawn(async move {
+                client.query_required_single::<String, _>(
+                    &format!("INSERT Item {{ n := {i} }}"), &()).await
+            })
+        }).collect::<Vec<_>>();
+        for query in queries {
+            assert_eq!(query.await.unwrap().unwrap(), "ok");
+        }
+        // queries leave the queue in submission order
+        assert_eq!(*parsed.lock().unwrap(), (0..4)
+            .map(|i| format!("INSERT Item {{ n := {i} }}"))
+            .collect::<Vec<_>>());
+    }
+}
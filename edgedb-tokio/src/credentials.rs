@@ -29,12 +29,19 @@ pub enum TlsSecurity {
 #[derive(Debug)]
 #[non_exhaustive]
 pub struct Credentials {
+    /// Host name (defaults to `localhost`)
     pub host: Option<String>,
+    /// Port number
     pub port: u16,
+    /// User name
     pub user: String,
+    /// Password
     pub password: Option<String>,
+    /// Database name (defaults to `edgedb`)
     pub database: Option<String>,
+    /// Certificate authority (PEM encoded) to verify server certificate
     pub tls_ca: Option<String>,
+    /// TLS security mode
     pub tls_security: TlsSecurity,
     pub(crate) file_outdated: bool,
 }
@@ -61,6 +68,25 @@ struct CredentialsCompat {
 }
 
 
+#[cfg(feature="zeroize")]
+impl Drop for Credentials {
+    fn drop(&mut self) {
+        use zeroize::Zeroize;
+
+        self.password.zeroize();
+    }
+}
+
+#[cfg(feature="zeroize")]
+impl Drop for CredentialsCompat {
+    fn drop(&mut self) {
+        use zeroize::Zeroize;
+
+        self.password.zeroize();
+    }
+}
+
+
 fn default_port() -> u16 {
     5656
 }
@@ -114,7 +140,7 @@ impl<'de> Deserialize<'de> for Credentials {
     where
         D: serde::de::Deserializer<'de>,
     {
-        let creds = CredentialsCompat::deserialize(deserializer)?;
+        let mut creds = CredentialsCompat::deserialize(deserializer)?;
         let expected_verify = match creds.tls_security {
             Some(TlsSecurity::Strict) => Some(true),
             Some(TlsSecurity::NoHostVerification) => Some(false),
@@ -145,13 +171,15 @@ impl<'de> Deserialize<'de> for Credentials {
                 creds.tls_cert_data,
             )))
         } else {
+            // fields are taken rather than moved, as `CredentialsCompat`
+            // implements `Drop` when "zeroize" feature is enabled
             Ok(Credentials {
-                host: creds.host,
+                host: creds.host.take(),
                 port: creds.port,
-                user: creds.user,
-                password: creds.password,
-                database: creds.database,
-                tls_ca: creds.tls_ca.or(creds.tls_cert_data.clone()),
+                user: std::mem::take(&mut creds.user),
+                password: creds.password.take(),
+                database: creds.database.take(),
+                tls_ca: creds.tls_ca.take().or(creds.tls_cert_data.take()),
                 tls_security: creds.tls_security.unwrap_or(
                     match creds.tls_verify_hostname {
                         None => TlsSecurity::Default,
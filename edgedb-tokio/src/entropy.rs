@@ -0,0 +1,31 @@
+//! Source of randomness used by the client
+use std::fmt;
+
+use rand::{thread_rng, RngCore};
+
+
+/// Source of random bytes
+///
+/// Client uses it for SCRAM authentication nonces and for the jitter of
+/// the reconnection delays. By default [`OsEntropy`] is used. Custom
+/// implementation can be set with [`Builder::entropy`](crate::Builder::entropy)
+/// for platforms where random number generator of the operating system is
+/// not available (e.g. WebAssembly), or when a certified generator is
+/// required.
+pub trait Entropy: fmt::Debug + Send + Sync {
+    /// Fill the buffer with random bytes
+    ///
+    /// Bytes must be cryptographically secure, as they are used in the
+    /// authentication.
+    fn fill_bytes(&self, buf: &mut [u8]);
+}
+
+/// Default entropy source, thread-local generator seeded by the OS
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsEntropy;
+
+impl Entropy for OsEntropy {
+    fn fill_bytes(&self, buf: &mut [u8]) {
+        thread_rng().fill_bytes(buf)
+    }
+}
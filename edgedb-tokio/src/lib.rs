@@ -37,19 +37,37 @@ mod raw;
 mod builder;
 mod client;
 mod credentials;
+mod entropy;
 mod errors;
+#[cfg(feature="tower")]
+mod middleware;
 mod options;
+mod schema_watch;
 mod sealed;
 mod server_params;
+mod state;
+mod stream_arg;
 mod tls;
+mod trace;
 mod transaction;
 
-pub use builder::{Builder, Config};
-pub use credentials::TlsSecurity;
-pub use client::Client;
+pub use builder::{Builder, Config, SYSTEM_DATABASE};
+pub use credentials::{Credentials, TlsSecurity};
+pub use entropy::{Entropy, OsEntropy};
+pub use client::{Client, ResultMetadata};
 pub use errors::Error;
-pub use options::{TransactionOptions, RetryOptions};
+pub use options::{TransactionOptions, RetryOptions, RetryCondition};
+pub use options::OfflineQueue;
+pub use schema_watch::SchemaWatcher;
+pub use trace::{ConnectionTrace, TraceStep, ConnectStage, ConnectProgress};
+pub use stream_arg::StreamArg;
+pub use tls::TlsConfigure;
 pub use transaction::{Transaction};
+#[cfg(feature="tower")]
+pub use middleware::{GlobalsLayer, GlobalsService};
+
+/// Version of `rustls` used by [`TlsConfigure`]
+pub use rustls;
 
 /// Create a connection to the database with default parameters
 ///
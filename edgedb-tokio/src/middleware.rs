@@ -0,0 +1,211 @@
+//! Tower middleware for per-request globals
+use std::fmt;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http::Request;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use edgedb_protocol::value::Value;
+
+use crate::client::Client;
+
+/// Tower layer that provides a client with request-scoped globals
+///
+/// For every request the extractor function computes globals, usually from
+/// something the authentication middleware has put into the request
+/// extensions. A [`Client`] with these
+/// [globals](Client::with_globals) is then inserted into the request
+/// extensions, so it can be used in handlers. Globals that are not returned
+/// by the extractor keep the values set on the original client. Extractor
+/// may return anything iterable, e.g. an `Option` of a single pair.
+///
+/// With [axum](https://docs.rs/axum) the client is available via the
+/// `Extension` extractor:
+///
+/// ```rust,no_run
+/// use http::Request;
+/// use edgedb_protocol::value::Value;
+/// use edgedb_tokio::{Client, GlobalsLayer};
+/// # type Body = ();
+/// # #[derive(Clone)]
+/// # struct UserId(edgedb_protocol::model::Uuid);
+/// # fn main_(client: Client) {
+///
+/// // used as `Router::new().route(...).layer(layer).layer(auth_layer)`,
+/// // so the outer authentication layer sets `UserId` before
+/// let layer = GlobalsLayer::new(client, |req: &Request<Body>| {
+///     req.extensions().get::<UserId>()
+///         .map(|user| ("current_user_id", Value::Uuid(user.0)))
+/// });
+/// # drop(layer);
+/// # }
+///
+/// // axum handler
+/// # struct Extension<T>(T);
+/// async fn my_posts(Extension(client): Extension<Client>) -> String {
+///     client.query_json(
+///         "SELECT Post { title } FILTER .author.id = global current_user_id",
+///         &(),
+///     ).await.unwrap().into()
+/// }
+/// ```
+///
+/// This requires `tower` feature to be enabled.
+pub struct GlobalsLayer<F> {
+    client: Client,
+    extract: Arc<F>,
+}
+
+/// Service created by the [`GlobalsLayer`]
+pub struct GlobalsService<S, F> {
+    inner: S,
+    client: Client,
+    extract: Arc<F>,
+}
+
+impl<F> GlobalsLayer<F> {
+    /// Create a layer deriving clients from the `client`
+    pub fn new(client: Client, extract: F) -> GlobalsLayer<F> {
+        GlobalsLayer {
+            client,
+            extract: Arc::new(extract),
+        }
+    }
+}
+
+impl<S, F> Layer<S> for GlobalsLayer<F> {
+    type Service = GlobalsService<S, F>;
+    fn layer(&self, inner: S) -> GlobalsService<S, F> {
+        GlobalsService {
+            inner,
+            client: self.client.clone(),
+            extract: self.extract.clone(),
+        }
+    }
+}
+
+impl<S, F, B, G, K> Service<Request<B>> for GlobalsService<S, F>
+    where S: Service<Request<B>>,
+          F: Fn(&Request<B>) -> G,
+          G: IntoIterator<Item=(K, Value)>,
+          K: AsRef<str>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+    fn poll_ready(&mut self, cx: &mut Context<'_>)
+        -> Poll<Result<(), S::Error>>
+    {
+        self.inner.poll_ready(cx)
+    }
+    fn call(&mut self, mut req: Request<B>) -> S::Future {
+        let client = self.client.with_globals((self.extract)(&req));
+        req.extensions_mut().insert(client);
+        self.inner.call(req)
+    }
+}
+
+impl<F> Clone for GlobalsLayer<F> {
+    fn clone(&self) -> Self {
+        GlobalsLayer {
+            client: self.client.clone(),
+            extract: self.extract.clone(),
+        }
+    }
+}
+
+impl<S: Clone, F> Clone for GlobalsService<S, F> {
+    fn clone(&self) -> Self {
+        GlobalsService {
+            inner: self.inner.clone(),
+            client: self.client.clone(),
+            extract: self.extract.clone(),
+        }
+    }
+}
+
+impl<F> fmt::Debug for GlobalsLayer<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GlobalsLayer")
+            .field("client", &self.client)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: fmt::Debug, F> fmt::Debug for GlobalsService<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GlobalsService")
+            .field("inner", &self.inner)
+            .field("client", &self.client)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::Infallible;
+    use std::future::{ready, Ready};
+    use std::task::{Context, Poll};
+
+    use http::Request;
+    use tower_layer::Layer;
+    use tower_service::Service;
+
+    use edgedb_protocol::value::Value;
+
+    use crate::builder::Builder;
+    use crate::client::Client;
+    use crate::state::SessionState;
+
+    use super::GlobalsLayer;
+
+    /// Returns the client inserted by the middleware
+    struct Handler;
+
+    impl Service<Request<()>> for Handler {
+        type Response = Option<Client>;
+        type Error = Infallible;
+        type Future = Ready<Result<Option<Client>, Infallible>>;
+        fn poll_ready(&mut self, _: &mut Context<'_>)
+            -> Poll<Result<(), Infallible>>
+        {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: Request<()>) -> Self::Future {
+            ready(Ok(req.extensions().get::<Client>().cloned()))
+        }
+    }
+
+    #[tokio::test]
+    async fn globals() {
+        let config = Builder::uninitialized()
+            .host_port(Some("localhost"), None)
+            .build().unwrap();
+        let tenant = ("tenant", Value::Str("acme".into()));
+        let client = Client::new(&config).with_globals([tenant.clone()]);
+        let layer = GlobalsLayer::new(client, |req: &Request<()>| {
+            req.headers().get("x-user")
+                .and_then(|user| user.to_str().ok())
+                .map(|user| ("current_user", Value::Str(user.into())))
+        });
+        let mut service = layer.layer(Handler);
+
+        let req = Request::builder()
+            .header("x-user", "alice")
+            .body(()).unwrap();
+        let client = service.call(req).await.unwrap().unwrap();
+        assert_eq!(client.session_state(),
+                   &SessionState::default().with_globals([
+                       tenant.clone(),
+                       ("current_user", Value::Str("alice".into())),
+                   ]));
+
+        // globals of the original client are kept
+        let req = Request::builder().body(()).unwrap();
+        let client = service.call(req).await.unwrap().unwrap();
+        assert_eq!(client.session_state(),
+                   &SessionState::default().with_globals([tenant]));
+    }
+}
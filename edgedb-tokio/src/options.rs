@@ -2,15 +2,17 @@ use std::collections::HashMap;
 use std::default::Default;
 use std::fmt;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Duration;
 
-use rand::{thread_rng, Rng};
-
 use edgedb_errors::Error;
 
+use crate::entropy::Entropy;
+
 trait Assert: Send + Sync + 'static {}
 impl Assert for RetryOptions {}
 impl Assert for TransactionOptions {}
+impl Assert for OfflineQueue {}
 
 
 /// Transaction isolation level
@@ -30,6 +32,16 @@ pub enum IsolationLevel {
 pub enum RetryCondition {
     TransactionConflict,
     NetworkError,
+    /// Connection taken from the pool turned out to be closed (e.g. by the
+    /// server's idle timeout) before the query was sent for execution
+    ///
+    /// Unlike [`NetworkError`](RetryCondition::NetworkError), this is
+    /// detected before the query is executed, so retrying is safe for any
+    /// query, not only in transactions. By default it's retried with a
+    /// fresh connection without a backoff. Network errors that happen
+    /// while a query is being executed are never retried outside of a
+    /// transaction.
+    IdleConnection,
 }
 
 /// Options for [`transaction()`](crate::Client::transaction)
@@ -54,12 +66,49 @@ pub struct RetryOptions(Arc<RetryOptionsInner>);
 struct RetryOptionsInner {
     default: RetryRule,
     overrides: HashMap<RetryCondition, RetryRule>,
+    on_retry: Option<OnRetry>,
 }
 
+/// Options for queueing queries while the server is unreachable
+///
+/// This mode is intended for deployments with intermittent connectivity,
+/// such as edge or IoT devices. When no connection can be established,
+/// a query waits for the server to become reachable again instead of
+/// failing immediately. Queued queries are sent when the connection is
+/// restored, or fail with the original connection error if
+/// [`ttl`](OfflineQueue::ttl) passes first or the queue is full.
+///
+/// By default only queries that may modify data (ones that contain
+/// `INSERT`, `UPDATE` or `DELETE` statements, DDL, `CONFIGURE` and anything
+/// else that isn't a plain `SELECT`) are queued, as the result of a read
+/// is usually useless after a long delay. Use
+/// [`reads`](OfflineQueue::reads) to queue all queries.
+///
+/// Must be set on a [`Client`](crate::Client) via
+/// [`with_offline_queue`](crate::Client::with_offline_queue). The queue is
+/// shared by all clients using clones of the same `OfflineQueue`.
+#[derive(Debug, Clone)]
+pub struct OfflineQueue {
+    depth: usize,
+    ttl: Duration,
+    probe_interval: Duration,
+    reads: bool,
+    queued: Arc<AtomicUsize>,
+    pub(crate) probe: Arc<tokio::sync::Mutex<()>>,
+}
+
+/// Place in the [`OfflineQueue`], released on drop
+pub(crate) struct QueueSlot(Arc<AtomicUsize>);
+
+#[derive(Clone)]
+struct OnRetry(Arc<dyn Fn(u32, &Error, Duration) + Send + Sync>);
+
 #[derive(Clone)]
 pub(crate) struct RetryRule {
     pub(crate) attempts: u32,
     pub(crate) backoff: Arc<dyn Fn(u32) -> Duration + Send + Sync>,
+    /// Upper bound of the random delay added to the backoff
+    pub(crate) jitter: Duration,
 }
 
 impl Default for TransactionOptions {
@@ -90,28 +139,172 @@ impl TransactionOptions {
     }
 }
 
+impl Default for OfflineQueue {
+    fn default() -> OfflineQueue {
+        OfflineQueue {
+            depth: 100,
+            ttl: Duration::from_secs(300),
+            probe_interval: Duration::from_secs(1),
+            reads: false,
+            queued: Arc::new(AtomicUsize::new(0)),
+            probe: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+}
+
+impl OfflineQueue {
+    /// Maximum number of queries waiting for the connection (default `100`)
+    ///
+    /// Queries submitted when the queue is full fail immediately.
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+    /// Maximum time a query waits in the queue (default 5 minutes)
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+    /// Interval between connection attempts while offline (default `1s`)
+    ///
+    /// Only one connection attempt is made at a time regardless of the
+    /// number of queued queries.
+    pub fn probe_interval(mut self, interval: Duration) -> Self {
+        self.probe_interval = interval;
+        self
+    }
+    /// Queue queries that don't modify data too (default `false`)
+    pub fn reads(mut self, reads: bool) -> Self {
+        self.reads = reads;
+        self
+    }
+    pub(crate) fn get_ttl(&self) -> Duration {
+        self.ttl
+    }
+    pub(crate) fn get_probe_interval(&self) -> Duration {
+        self.probe_interval
+    }
+    /// Returns whether any of the queries should wait in the queue
+    pub(crate) fn accepts(&self, queries: &[&str]) -> bool {
+        self.reads || queries.iter().any(|q| is_modification(q))
+    }
+    /// Takes a place in the queue, returns `None` if it's full
+    pub(crate) fn enter(&self) -> Option<QueueSlot> {
+        let depth = self.depth;
+        self.queued.fetch_update(Ordering::SeqCst, Ordering::SeqCst,
+                                 |n| if n < depth { Some(n+1) } else { None })
+            .ok()?;
+        Some(QueueSlot(self.queued.clone()))
+    }
+}
+
+impl Drop for QueueSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Checks whether query may modify data
+///
+/// This is a lexical check, which is conservative: every statement must
+/// start with one of the known read-only keywords (`SELECT`, `WITH`, `FOR`,
+/// `GROUP`) and must not contain `INSERT`, `UPDATE` or `DELETE`, so DDL,
+/// `CONFIGURE` and anything unrecognized is treated as a modification.
+/// Keywords are looked up outside of string literals, quoted identifiers
+/// and comments.
+fn is_modification(query: &str) -> bool {
+    let mut chars = query.char_indices().peekable();
+    let mut statement_start = true;
+    while let Some((start, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '#' => {
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+            }
+            ';' => statement_start = true,
+            _ if statement_start && !(c.is_alphanumeric() || c == '_') => {
+                return true;
+            }
+            '\'' | '"' | '`' => {
+                while let Some((_, q)) = chars.next() {
+                    if q == '\\' && c != '`' {
+                        chars.next();
+                    } else if q == c {
+                        break;
+                    }
+                }
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some((idx, c)) = chars.next_if(
+                    |&(_, c)| c.is_alphanumeric() || c == '_')
+                {
+                    end = idx + c.len_utf8();
+                }
+                let word = &query[start..end];
+                if statement_start && !["select", "with", "for", "group"]
+                    .iter().any(|kw| word.eq_ignore_ascii_case(kw))
+                {
+                    return true;
+                }
+                statement_start = false;
+                if ["insert", "update", "delete"].iter()
+                    .any(|kw| word.eq_ignore_ascii_case(kw))
+                {
+                    return true;
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
 impl Default for RetryRule {
     fn default() -> RetryRule {
         RetryRule {
             attempts: 3,
-            backoff: Arc::new(|n| {
-                Duration::from_millis(
-                    2u64.pow(n)*100 + thread_rng().gen_range(0..100)
-                )
-            }),
+            backoff: Arc::new(|n| Duration::from_millis(2u64.pow(n)*100)),
+            jitter: Duration::from_millis(100),
         }
     }
 }
 
 impl Default for RetryOptions {
     fn default() -> RetryOptions {
+        let mut overrides = HashMap::new();
+        overrides.insert(RetryCondition::IdleConnection, RetryRule {
+            attempts: 3,
+            backoff: Arc::new(|_| Duration::from_secs(0)),
+            jitter: Duration::ZERO,
+        });
         RetryOptions(Arc::new(RetryOptionsInner {
             default: RetryRule::default(),
-            overrides: HashMap::new(),
+            overrides,
+            on_retry: None,
         }))
     }
 }
 
+impl RetryRule {
+    /// Delay before the retry number `attempt`
+    ///
+    /// Jitter is taken from the configured entropy source, so that
+    /// concurrent clients don't retry in lockstep.
+    pub(crate) fn delay(&self, attempt: u32, entropy: &dyn Entropy)
+        -> Duration
+    {
+        let mut delay = (self.backoff)(attempt);
+        let jitter = self.jitter.as_millis() as u64;
+        if jitter > 0 {
+            let mut buf = [0u8; 8];
+            entropy.fill_bytes(&mut buf);
+            delay += Duration::from_millis(u64::from_le_bytes(buf) % jitter);
+        }
+        delay
+    }
+}
+
 impl RetryOptions {
     /// Create a new [`RetryOptions`] object with the default rule
     pub fn new(self, attempts: u32,
@@ -122,12 +315,14 @@ impl RetryOptions {
             default: RetryRule {
                 attempts,
                 backoff: Arc::new(backoff),
+                jitter: Duration::ZERO,
             },
             overrides: HashMap::new(),
+            on_retry: self.0.on_retry.clone(),
         }))
     }
     /// Add a retrying rule for a specific condition
-    pub fn with_rule<F>(mut self,
+    pub fn with_rule(mut self,
         condition: RetryCondition,
         attempts: u32,
         backoff: impl Fn(u32) -> Duration + Send + Sync + 'static)
@@ -137,16 +332,43 @@ impl RetryOptions {
         inner.overrides.insert(condition, RetryRule {
             attempts,
             backoff: Arc::new(backoff),
+            jitter: Duration::ZERO,
         });
         self
     }
+    /// Set a callback that is invoked before each retry
+    ///
+    /// Callback receives the number of the upcoming attempt (`1` for the
+    /// first retry), the error that caused the retry and the backoff that is
+    /// going to be waited before the next attempt. It can be used to log or
+    /// count transaction conflicts. The callback must not block, as it runs
+    /// on the async runtime.
+    pub fn on_retry(mut self,
+        callback: impl Fn(u32, &Error, Duration) + Send + Sync + 'static)
+        -> Self
+    {
+        let inner =  Arc::make_mut(&mut self.0);
+        inner.on_retry = Some(OnRetry(Arc::new(callback)));
+        self
+    }
+    pub(crate) fn notify_retry(&self, attempt: u32, err: &Error,
+                               backoff: Duration)
+    {
+        if let Some(OnRetry(callback)) = &self.0.on_retry {
+            callback(attempt, err, backoff);
+        }
+    }
     pub(crate) fn get_rule(&self, err: &Error) -> &RetryRule {
         use edgedb_errors::{TransactionConflictError, ClientError};
+        use edgedb_errors::ClientConnectionClosedError;
         use RetryCondition::*;
 
         if err.is::<TransactionConflictError>() {
             self.0.overrides.get(&TransactionConflict)
                 .unwrap_or(&self.0.default)
+        } else if err.is::<ClientConnectionClosedError>() {
+            self.0.overrides.get(&IdleConnection)
+                .unwrap_or(&self.0.default)
         } else if err.is::<ClientError>() {
             self.0.overrides.get(&NetworkError).unwrap_or(&self.0.default)
         } else {
@@ -192,11 +414,111 @@ fn debug_backoff() {
         "10s, 20s");
 }
 
+#[test]
+fn backoff_jitter() {
+    use edgedb_errors::{ErrorKind, TransactionConflictError};
+
+    #[derive(Debug)]
+    struct Fixed(u8);
+
+    impl Entropy for Fixed {
+        fn fill_bytes(&self, buf: &mut [u8]) {
+            buf.fill(self.0);
+        }
+    }
+
+    let rule = RetryRule::default();
+    assert_eq!(rule.delay(1, &Fixed(0)), Duration::from_millis(200));
+    // u64::MAX % 100 == 15
+    assert_eq!(rule.delay(1, &Fixed(0xFF)), Duration::from_millis(215));
+    let opts = RetryOptions::default().new(2, |_| Duration::from_secs(1));
+    let err = TransactionConflictError::with_message("conflict");
+    assert_eq!(opts.get_rule(&err).delay(1, &Fixed(0xFF)),
+               Duration::from_secs(1));
+}
+
+#[test]
+fn on_retry_survives_rule_changes() {
+    use std::sync::Mutex;
+    use edgedb_errors::{ErrorKind, TransactionConflictError};
+
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let opts = RetryOptions::default()
+        .on_retry({
+            let calls = calls.clone();
+            move |attempt, _, backoff| {
+                calls.lock().unwrap().push((attempt, backoff))
+            }
+        })
+        .new(5, |_| Duration::from_millis(10));
+    let err = TransactionConflictError::with_message("conflict");
+    opts.notify_retry(1, &err, Duration::from_millis(10));
+    assert_eq!(*calls.lock().unwrap(), vec![(1, Duration::from_millis(10))]);
+    assert!(format!("{:?}", opts).contains("OnRetry(..)"));
+}
+
+#[test]
+fn idle_connection_rule() {
+    use edgedb_errors::{ErrorKind, ClientConnectionClosedError};
+    use edgedb_errors::ClientConnectionEosError;
+    use crate::entropy::OsEntropy;
+
+    let opts = RetryOptions::default();
+    let idle = ClientConnectionClosedError::with_message("closed");
+    assert_eq!(opts.get_rule(&idle).attempts, 3);
+    assert_eq!(opts.get_rule(&idle).delay(1, &OsEntropy),
+               Duration::from_secs(0));
+    let eos = ClientConnectionEosError::with_message("eos");
+    assert!(opts.get_rule(&eos).delay(1, &OsEntropy)
+            >= Duration::from_millis(200));
+
+    let opts = opts.with_rule(RetryCondition::IdleConnection,
+                              0, |_| Duration::from_secs(0));
+    assert_eq!(opts.get_rule(&idle).attempts, 0);
+}
+
+#[test]
+fn modification_queries() {
+    assert!(is_modification("INSERT User { name := 'x' }"));
+    assert!(is_modification("with u := (select User) update u set {}"));
+    assert!(is_modification("SELECT (DELETE User)"));
+    assert!(!is_modification("SELECT User { name }"));
+    assert!(!is_modification("SELECT 'INSERT' ++ \"delete\""));
+    assert!(!is_modification("SELECT User.`update` # insert\n"));
+    assert!(!is_modification("SELECT 'it\\'s INSERT'"));
+    assert!(!is_modification("SELECT inserted"));
+    assert!(!is_modification("  # comment\n\tselect 1; group User by .name"));
+    assert!(is_modification("CREATE TYPE User"));
+    assert!(is_modification("# comment\n  configure instance set x := 1"));
+    assert!(is_modification("SELECT 1; DROP TYPE User"));
+    assert!(is_modification("(SELECT 1)"));
+}
+
+#[test]
+fn offline_queue_depth() {
+    let queue = OfflineQueue::default().depth(2);
+    let a = queue.enter();
+    let b = queue.clone().enter();
+    assert!(a.is_some() && b.is_some());
+    assert!(queue.enter().is_none());
+    drop(a);
+    assert!(queue.enter().is_some());
+    assert!(!queue.accepts(&["SELECT 1"]));
+    assert!(queue.clone().reads(true).accepts(&["SELECT 1"]));
+}
+
+impl fmt::Debug for OnRetry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("OnRetry(..)")
+    }
+}
+
 impl fmt::Debug for RetryRule {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("RetryRule")
             .field("attempts", &self.attempts)
             .field("backoff", &DebugBackoff(&*self.backoff, self.attempts))
+            .field("jitter", &self.jitter)
             .finish()
     }
 }
@@ -3,18 +3,19 @@ use std::collections::HashMap;
 use std::error::Error as _;
 use std::future::Future;
 use std::io;
+use std::net::SocketAddr;
 use std::str;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use bytes::{Bytes, BytesMut};
-use rand::{thread_rng, Rng};
-use scram::ScramClient;
+use rustls::Certificate;
 use tls_api::{TlsConnector, TlsConnectorBox, TlsStream, TlsStreamDyn};
 use tls_api::{TlsConnectorBuilder};
 use tls_api_not_tls::TlsConnector as PlainConnector;
 use tokio::io::{AsyncReadExt};
 use tokio::io::{AsyncWriteExt};
-use tokio::net::TcpStream;
+use tokio::net::{TcpStream, lookup_host};
 use tokio::time::sleep;
 use typemap::{TypeMap, DebugAny};
 use webpki::DnsNameRef;
@@ -22,23 +23,34 @@ use webpki::DnsNameRef;
 use edgedb_protocol::client_message::{ClientMessage, ClientHandshake};
 use edgedb_protocol::encoding::{Input, Output};
 use edgedb_protocol::features::ProtocolVersion;
+use edgedb_protocol::query_arg::ExternalData;
 use edgedb_protocol::server_message::ParameterStatus;
 use edgedb_protocol::server_message::{ServerMessage, Authentication};
 use edgedb_protocol::server_message::{ServerHandshake};
 use edgedb_protocol::value::Value;
 
 use crate::raw::ConnInner;
+use crate::raw::scram::{ScramClient, ChannelBinding};
+use crate::stream_arg::ArgReader;
 use crate::tls;
 use crate::builder::{Config, Address};
+use crate::entropy::Entropy;
 use crate::errors::{Error, ClientError, ErrorKind};
 use crate::errors::{ClientConnectionFailedTemporarilyError, ProtocolTlsError};
 use crate::errors::{ClientConnectionError, ClientConnectionFailedError};
 use crate::errors::{ClientEncodingError, ClientConnectionEosError};
 use crate::errors::{ProtocolEncodingError, ProtocolError};
 use crate::errors::{AuthenticationError, PasswordRequired};
-use crate::server_params::{SystemConfig};
+use crate::errors::{UnsupportedProtocolVersionError};
+use crate::server_params::{SystemConfig, ServerKeyData};
+use crate::state::StateCodec;
+use crate::trace::{Tracer, ConnectStage, ConnectProgress};
 
 const MAX_MESSAGE_SIZE: usize = 1_048_576;
+const STREAM_CHUNK_SIZE: usize = 65536;
+/// Connections are recycled this much earlier than server's
+/// `session_idle_timeout` to account for network latency and clock skew
+const IDLE_TIMEOUT_MARGIN: Duration = Duration::from_secs(1);
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub(crate) enum State {
@@ -55,8 +67,31 @@ impl ConnInner {
     pub fn is_consistent(&self) -> bool {
         matches!(self.state, State::Normal {..})
     }
+    /// Returns `true` if the server is about to close this connection due to
+    /// `session_idle_timeout`
+    pub fn is_expired(&self) -> bool {
+        let timeout = match self.params.get::<SystemConfig>() {
+            Some(SystemConfig { session_idle_timeout: Some(timeout) })
+                if !timeout.is_zero() => *timeout,
+            // zero timeout means idle connections are never closed
+            _ => return false,
+        };
+        match self.state {
+            State::Normal { idle_since } => {
+                idle_since.elapsed() + IDLE_TIMEOUT_MARGIN >= timeout
+            }
+            _ => false,
+        }
+    }
     pub async fn connect(config: &Config) -> Result<Self, Error> {
-        connect(config).await.map_err(|e| {
+        let mut tracer = Tracer::new(false);
+        ConnInner::connect_traced(config, &mut tracer).await
+    }
+    /// Same as [`connect`](Self::connect) but records connection stages
+    pub(crate) async fn connect_traced(config: &Config, tracer: &mut Tracer)
+        -> Result<Self, Error>
+    {
+        connect(config, tracer).await.map_err(|e| {
             if e.is::<ClientConnectionError>() {
                 e.refine_kind::<ClientConnectionFailedError>()
             } else {
@@ -71,13 +106,28 @@ impl ConnInner {
         send_messages(&mut self.stream, &mut self.out_buf, &self.proto, msgs)
             .await
     }
+    /// Send a message, inserting external argument data into it
+    ///
+    /// Arguments must be the last field of the message and `arguments_len`
+    /// is the length of the encoded arguments (without external data).
+    pub async fn send_external(&mut self, msg: &ClientMessage,
+        arguments_len: usize, external: Vec<ExternalData>)
+        -> Result<(), Error>
+    {
+        send_external(&mut self.stream, &mut self.out_buf, &self.proto,
+                      msg, arguments_len, external).await
+    }
     pub async fn message(&mut self) -> Result<ServerMessage, Error> {
         wait_message(&mut self.stream, &mut self.in_buf, &self.proto).await
     }
 }
 
-async fn connect(cfg: &Config) -> Result<ConnInner, Error> {
-    let tls = tls::connector(cfg.0.verifier.clone())
+async fn connect(cfg: &Config, tracer: &mut Tracer)
+    -> Result<ConnInner, Error>
+{
+    let server_cert = Arc::new(Mutex::new(None));
+    let tls = tls::connector(cfg.0.verifier.clone(), server_cert.clone(),
+                             cfg.0.tls_configure.as_deref())
         .map_err(|e| ClientError::with_source_ref(e)
                  .context("cannot create TLS connector"))?;
     match &cfg.0.address {
@@ -92,12 +142,33 @@ async fn connect(cfg: &Config) -> Result<ConnInner, Error> {
     let start = Instant::now();
     let wait = cfg.0.wait;
     let ref mut warned = false;
+    let mut attempts = 0;
     let conn = loop {
-        match connect_timeout(cfg, connect2(cfg, &tls, warned)).await {
+        let result = connect_timeout(cfg,
+            connect2(cfg, &tls, &server_cert, warned, tracer)
+        ).await;
+        match &result {
+            Ok(_) => tracer.done(),
+            Err(e) => tracer.fail(e),
+        }
+        match result {
             Err(e) if is_temporary(&e) => {
                 log::debug!("Temporary connection error: {:#}", e);
-                if wait > start.elapsed() {
-                    sleep(connect_sleep()).await;
+                attempts += 1;
+                let elapsed = start.elapsed();
+                if wait > elapsed {
+                    let remaining = wait - elapsed;
+                    if let Some(callback) = &cfg.0.connect_progress {
+                        (callback.0)(&ConnectProgress {
+                            attempts,
+                            last_error: &e,
+                            elapsed,
+                            remaining,
+                        });
+                    }
+                    // don't oversleep the deadline
+                    sleep(connect_sleep(cfg).min(remaining)).await;
+                    tracer.next_attempt();
                     continue;
                 } else if wait > Duration::new(0, 0) {
                     return Err(e.context(
@@ -116,47 +187,67 @@ async fn connect(cfg: &Config) -> Result<ConnInner, Error> {
     Ok(conn)
 }
 
-async fn connect2(cfg: &Config, tls: &TlsConnectorBox, warned: &mut bool)
+async fn connect2(cfg: &Config, tls: &TlsConnectorBox,
+                  server_cert: &Mutex<Option<Certificate>>,
+                  warned: &mut bool, tracer: &mut Tracer)
     -> Result<ConnInner, Error>
 {
-    let stream = match connect3(cfg, tls).await {
+    let (stream, server_cert) = match connect3(cfg, tls, tracer).await {
         Err(e) if e.is::<ProtocolTlsError>() => {
+            tracer.fail(&e);
             if !*warned {
                 log::warn!("TLS connection failed. \
                     Trying plaintext...");
                 *warned = true;
             }
-            connect3(
+            let stream = connect3(
                 cfg,
                 &PlainConnector::builder()
                     .map_err(ClientError::with_source_ref)?
                     .build().map_err(ClientError::with_source_ref)?
                     .into_dyn(),
-            ).await?
+                tracer,
+            ).await?;
+            (stream, None)
         }
         Err(e) => return Err(e),
-        Ok(r) => match r.get_alpn_protocol() {
-            Ok(Some(protocol)) if protocol == b"edgedb-binary" => r,
-            _ => match &cfg.0.address {
-                Address::Tcp(_) => {
-                    Err(ClientConnectionFailedError::with_message(
-                        "Server does not support the EdgeDB binary protocol."
-                    ))?
-                },
-                Address::Unix(_) => r,  // don't check ALPN on UNIX stream
-            }
+        Ok(r) => {
+            let stream = match r.get_alpn_protocol() {
+                Ok(Some(protocol)) if protocol == b"edgedb-binary" => r,
+                _ => match &cfg.0.address {
+                    Address::Tcp(_) => {
+                        Err(ClientConnectionFailedError::with_message(
+                            "Server does not support \
+                             the EdgeDB binary protocol."
+                        ))?
+                    },
+                    // don't check ALPN on UNIX stream
+                    Address::Unix(_) => r,
+                }
+            };
+            // certificate is only captured on TLS handshake, so it's
+            // always empty for UNIX sockets
+            let cert = server_cert.lock()
+                .expect("certificate mutex is not poisoned")
+                .as_ref().map(|cert| cert.0.clone());
+            (stream, cert)
         }
     };
-    connect4(cfg, stream).await
+    connect4(cfg, stream, server_cert, tracer).await
 }
 
-async fn connect3(cfg: &Config, tls: &TlsConnectorBox)
+async fn connect3(cfg: &Config, tls: &TlsConnectorBox, tracer: &mut Tracer)
     -> Result<TlsStream, Error>
 {
     match &cfg.0.address {
-        Address::Tcp(addr@(host,_)) => {
-            let conn = TcpStream::connect(addr).await
+        Address::Tcp((host, port)) => {
+            tracer.stage(ConnectStage::Dns);
+            let addrs = lookup_host((&host[..], *port)).await
+                .map_err(ClientConnectionError::with_source)?;
+            tracer.stage(ConnectStage::Tcp);
+            let conn = tcp_connect(addrs).await
                 .map_err(ClientConnectionError::with_source)?;
+            tracer.stage(ConnectStage::Tls);
             let is_valid_dns = DnsNameRef::try_from_ascii_str(host).is_ok();
             let host = if !is_valid_dns {
                 // FIXME: https://github.com/rustls/rustls/issues/184
@@ -183,6 +274,7 @@ async fn connect3(cfg: &Config, tls: &TlsConnectorBox)
             }
             #[cfg(unix)] {
                 use tokio::net::UnixStream;
+                tracer.stage(ConnectStage::Tcp);
                 let conn = UnixStream::connect(&path).await
                     .map_err(ClientConnectionError::with_source)?;
                 Ok(
@@ -197,10 +289,29 @@ async fn connect3(cfg: &Config, tls: &TlsConnectorBox)
     }
 }
 
-async fn connect4(cfg: &Config, mut stream: TlsStream)
+/// Same as `TcpStream::connect` but with name resolution done separately
+async fn tcp_connect(addrs: impl Iterator<Item=SocketAddr>)
+    -> io::Result<TcpStream>
+{
+    let mut last_err = None;
+    for addr in addrs {
+        match TcpStream::connect(addr).await {
+            Ok(conn) => return Ok(conn),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput,
+                       "could not resolve to any address")
+    }))
+}
+
+async fn connect4(cfg: &Config, mut stream: TlsStream,
+                  server_cert: Option<Vec<u8>>, tracer: &mut Tracer)
     -> Result<ConnInner, Error>
 {
-    let mut proto = ProtocolVersion::current();
+    tracer.stage(ConnectStage::Handshake);
+    let mut proto = cfg.0.max_protocol.clone();
     let mut out_buf = BytesMut::with_capacity(8192);
     let mut in_buf = BytesMut::with_capacity(8192);
 
@@ -221,18 +332,38 @@ async fn connect4(cfg: &Config, mut stream: TlsStream)
     if let ServerMessage::ServerHandshake(ServerHandshake {
         major_ver, minor_ver, extensions: _
     }) = msg {
+        let (max_major, max_minor) = cfg.0.max_protocol.version_tuple();
         proto = ProtocolVersion::new(major_ver, minor_ver);
+        if !proto.is_at_most(max_major, max_minor) {
+            return Err(UnsupportedProtocolVersionError::with_message(format!(
+                "server requested protocol {}.{} which is higher than \
+                 requested {}.{}",
+                major_ver, minor_ver, max_major, max_minor)));
+        }
+        if let Some(min) = &cfg.0.min_protocol {
+            let (min_major, min_minor) = min.version_tuple();
+            if !proto.is_at_least(min_major, min_minor) {
+                return Err(UnsupportedProtocolVersionError::with_message(
+                    format!("server supports protocol {}.{} only, \
+                             but at least {}.{} is required",
+                            major_ver, minor_ver, min_major, min_minor)));
+            }
+        }
         // TODO(tailhook) record extensions
         msg = wait_message(&mut stream, &mut in_buf, &proto).await?;
     }
+    tracer.stage(ConnectStage::Authentication);
     match msg {
         ServerMessage::Authentication(Authentication::Ok) => {}
         ServerMessage::Authentication(Authentication::Sasl { methods })
         => {
-            if methods.iter().any(|x| x == "SCRAM-SHA-256") {
+            let binding = ChannelBinding::negotiate(
+                server_cert.as_deref(), &methods);
+            if methods.iter().any(|x| x == binding.method()) {
                 if let Some(password) = &cfg.0.password {
                     scram(&mut stream, &mut in_buf, &mut out_buf, &proto,
-                          &cfg.0.user, password).await?;
+                          &cfg.0.user, password, binding,
+                          &*cfg.0.entropy).await?;
                 } else {
                     return Err(PasswordRequired::with_message(
                         "Password required for the specified user/host"));
@@ -252,15 +383,20 @@ async fn connect4(cfg: &Config, mut stream: TlsStream)
         }
     }
 
+    tracer.stage(ConnectStage::Ready);
     let mut server_params = TypeMap::custom();
+    let mut state_codec = None;
     loop {
         let msg = wait_message(&mut stream, &mut in_buf, &proto).await?;
         match msg {
             ServerMessage::ReadyForCommand(_) => {
                 break;
             }
-            ServerMessage::ServerKeyData(_) => {
-                // TODO(tailhook) store it somehow?
+            ServerMessage::ServerKeyData(key) => {
+                server_params.insert::<ServerKeyData>(ServerKeyData(key.data));
+            }
+            ServerMessage::StateDataDescription(desc) => {
+                state_codec = Some(StateCodec::new(desc, &proto)?);
             }
             ServerMessage::ParameterStatus(par) => {
                 match &par.name[..] {
@@ -294,28 +430,33 @@ async fn connect4(cfg: &Config, mut stream: TlsStream)
         proto,
         params: server_params,
         state: State::Normal { idle_since: Instant::now() },
+        state_codec,
         in_buf,
         out_buf,
         stream,
+        runtime: tokio::runtime::Handle::current().id(),
     })
 }
 
 async fn scram(
     stream: &mut TlsStream, in_buf: &mut BytesMut, out_buf: &mut BytesMut,
     proto: &ProtocolVersion,
-    user: &str, password: &str)
+    user: &str, password: &str, binding: ChannelBinding,
+    entropy: &dyn Entropy)
     -> Result<(), Error>
 {
     use edgedb_protocol::client_message::SaslInitialResponse;
     use edgedb_protocol::client_message::SaslResponse;
 
-    let scram = ScramClient::new(&user, &password, None);
+    log::debug!("Authenticating with SCRAM, channel binding: {:?}",
+                binding);
+    let scram = ScramClient::new(&user, &password, binding, entropy);
 
-    let (scram, first) = scram.client_first();
+    let first = scram.client_first();
     send_messages(stream, out_buf, &proto, &[
         ClientMessage::AuthenticationSaslInitialResponse(
             SaslInitialResponse {
-            method: "SCRAM-SHA-256".into(),
+            method: scram.method().into(),
             data: Bytes::copy_from_slice(first.as_bytes()),
         }),
     ]).await?;
@@ -335,9 +476,10 @@ async fn scram(
     let data = str::from_utf8(&data[..])
         .map_err(|e| ProtocolError::with_source(e).context(
             "invalid utf-8 in SCRAM-SHA-256 auth"))?;
-    let scram = scram.handle_server_first(&data)
-        .map_err(AuthenticationError::with_source)?;
-    let (scram, data) = scram.client_final();
+    let (data, server_final) = scram.handle_server_first(&data)?;
+    // client-final message contains the proof derived from password
+    #[cfg(feature="zeroize")]
+    let data = zeroize::Zeroizing::new(data);
     send_messages(stream, out_buf, &proto, &[
         ClientMessage::AuthenticationSaslResponse(
             SaslResponse {
@@ -359,9 +501,7 @@ async fn scram(
     let data = str::from_utf8(&data[..])
         .map_err(|_| ProtocolError::with_message(
             "invalid utf-8 in SCRAM-SHA-256 auth"))?;
-    scram.handle_server_final(&data)
-        .map_err(|e| AuthenticationError::with_message(format!(
-            "Authentication error: {}", e)))?;
+    server_final.verify(&data)?;
     loop {
         let msg = wait_message(stream, in_buf, &proto).await?;
         match msg {
@@ -433,6 +573,61 @@ async fn send_messages<'x>(
     Ok(())
 }
 
+async fn send_external(
+    stream: &mut TlsStream,
+    buf: &mut BytesMut,
+    proto: &ProtocolVersion,
+    message: &ClientMessage,
+    arguments_len: usize,
+    external: Vec<ExternalData>,
+) -> Result<(), Error> {
+    buf.truncate(0);
+    message.encode(&mut Output::new(proto, buf))
+        .map_err(ClientEncodingError::with_source)?;
+    let args_start = buf.len() - arguments_len;
+    let extra = external.iter().map(|ext| ext.len).sum::<usize>();
+    // both the message length and the arguments length must include
+    // the external data
+    for pos in [1, args_start - 4] {
+        let len = u32::from_be_bytes(buf[pos..pos+4].try_into().unwrap());
+        let len = usize::try_from(len).ok()
+            .and_then(|len| len.checked_add(extra))
+            .and_then(|len| u32::try_from(len).ok())
+            .ok_or_else(|| ClientEncodingError::with_message(
+                "arguments are too long"))?;
+        buf[pos..pos+4].copy_from_slice(&len.to_be_bytes());
+    }
+    let mut chunk = Vec::new();
+    let mut written = 0;
+    for ext in external {
+        let ArgReader(mut reader) = *ext.source.downcast::<ArgReader>()
+            .map_err(|_| ClientEncodingError::with_message(
+                "unsupported external argument"))?;
+        stream.write_all(&buf[written..args_start + ext.offset]).await
+            .map_err(ClientConnectionError::with_source)?;
+        written = args_start + ext.offset;
+        let mut left = ext.len;
+        chunk.resize(left.min(STREAM_CHUNK_SIZE), 0);
+        while left > 0 {
+            let max = left.min(chunk.len());
+            let n = reader.read(&mut chunk[..max]).await
+                .map_err(|e| ClientEncodingError::with_source(e)
+                    .context("cannot read streamed argument"))?;
+            if n == 0 {
+                return Err(ClientEncodingError::with_message(format!(
+                    "streamed argument is {} bytes shorter than declared",
+                    left)));
+            }
+            stream.write_all(&chunk[..n]).await
+                .map_err(ClientConnectionError::with_source)?;
+            left -= n;
+        }
+    }
+    stream.write_all(&buf[written..]).await
+        .map_err(ClientConnectionError::with_source)?;
+    send_messages(stream, buf, proto, &[ClientMessage::Sync]).await
+}
+
 fn conn_err(err: io::Error) -> Error {
     ClientConnectionError::with_source(err)
 }
@@ -474,8 +669,10 @@ async fn wait_message<'x>(stream: &mut TlsStream,
     Ok(result)
 }
 
-fn connect_sleep() -> Duration {
-    Duration::from_millis(thread_rng().gen_range(10u64..200u64))
+fn connect_sleep(cfg: &Config) -> Duration {
+    let mut buf = [0u8; 8];
+    cfg.0.entropy.fill_bytes(&mut buf);
+    Duration::from_millis(10 + u64::from_le_bytes(buf) % 190)
 }
 
 async fn connect_timeout<F, T>(cfg: &Config, f: F) -> Result<T, Error>
@@ -533,3 +730,75 @@ fn tls_fail(e: anyhow::Error) -> Error {
     }
     ClientConnectionError::with_source_ref(e)
 }
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::sync::mpsc;
+
+    use crate::builder::{Address, Builder};
+    use crate::errors::ClientConnectionError;
+    use crate::raw::ConnInner;
+    use crate::raw::test_server::TestServer;
+    use crate::trace::{Tracer, ConnectStage};
+
+    #[tokio::test]
+    async fn trace() {
+        use ConnectStage::*;
+
+        let mut server = TestServer::new();
+        let config = server.config();
+
+        let mut tracer = Tracer::new(true);
+        ConnInner::connect_traced(&config, &mut tracer).await.unwrap_err();
+        let trace = tracer.finish().unwrap();
+        assert!(!trace.is_success());
+        let steps = trace.steps();
+        assert_eq!(steps.len(), 1);
+        assert_eq!(steps[0].stage, Tcp);
+        assert!(steps[0].error.is_some());
+
+        server.start();
+        let mut tracer = Tracer::new(true);
+        ConnInner::connect_traced(&config, &mut tracer).await.unwrap();
+        let trace = tracer.finish().unwrap();
+        assert!(trace.is_success());
+        assert_eq!(
+            trace.steps().iter().map(|s| s.stage).collect::<Vec<_>>(),
+            [Tcp, Handshake, Authentication, Ready]);
+        assert!(trace.steps().iter().all(|s| s.error.is_none()));
+    }
+
+    #[tokio::test]
+    async fn connect_progress() {
+        let wait = Duration::from_secs(30);
+        let mut server = TestServer::new();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut builder = Builder::uninitialized();
+        builder.host_port(None::<&str>, None);
+        builder.wait_until_available(wait);
+        builder.on_connect_progress(move |p| {
+            tx.send((
+                p.attempts,
+                p.elapsed + p.remaining,
+                p.last_error.is::<ClientConnectionError>(),
+            )).ok();
+        });
+        let mut config = builder.build().unwrap();
+        Arc::get_mut(&mut config.0).unwrap()
+            .address = Address::Unix(server.path());
+
+        // socket doesn't exist yet, so attempts fail and are retried
+        let conn = tokio::spawn(async move {
+            ConnInner::connect(&config).await
+        });
+        for attempt in 1..=2 {
+            assert_eq!(rx.recv().await, Some((attempt, wait, true)));
+        }
+        server.start();
+        conn.await.unwrap().unwrap();
+        assert_eq!(server.accepted(), 1);
+    }
+}
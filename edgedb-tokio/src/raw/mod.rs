@@ -3,9 +3,14 @@
 mod connection;
 mod options;
 mod queries;
+mod scram;
+#[cfg(all(test, unix))]
+pub(crate) mod test_server;
 
 use std::sync::{Arc, Mutex as BlockingMutex};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::collections::VecDeque;
+use std::future::Future;
 
 use bytes::BytesMut;
 use tls_api::{TlsStream};
@@ -13,19 +18,41 @@ use tokio::sync::{self, Semaphore};
 
 use edgedb_protocol::features::ProtocolVersion;
 
-use crate::errors::{Error, ErrorKind, ClientError};
+use crate::errors::{Error, ErrorKind, ClientError, InterfaceError};
 use crate::builder::Config;
+use crate::entropy::Entropy;
+use crate::state::StateCodec;
+use crate::trace::{Tracer, ConnectionTrace};
+
+tokio::task_local! {
+    /// Address of the pool which connection is held by a transaction
+    /// running in the current task
+    static TRANSACTION_POOL: usize;
+}
 
 pub use options::Options;
+#[cfg(feature="unstable")]
+pub use queries::{Request, Response};
+#[cfg(not(feature="unstable"))]
+pub(crate) use queries::Request;
+#[cfg(feature="unstable")]
+pub use crate::server_params::{ServerParam, ServerKeyData};
+#[cfg(feature="unstable")]
+pub use crate::state::SessionState;
 
 #[derive(Clone, Debug)]
 pub struct Pool(Arc<PoolInner>);
 
 #[derive(Debug)]
 struct PoolInner {
-    pub config: Config,
+    pub config: BlockingMutex<Config>,
+    /// Incremented on each config reload or invalidation, connections
+    /// established with an older generation are closed instead of being
+    /// returned to the pool
+    pub generation: AtomicU64,
     pub semaphore: Arc<Semaphore>,
     pub queue: BlockingMutex<VecDeque<ConnInner>>,
+    pub last_trace: BlockingMutex<Option<ConnectionTrace>>,
 }
 
 #[derive(Debug)]
@@ -34,17 +61,23 @@ pub struct Connection {
     #[allow(dead_code)]  // needed only for Drop side effect
     permit: sync::OwnedSemaphorePermit,
     pool: Arc<PoolInner>,
+    generation: u64,
+    /// Connection was taken from the idle queue and no request has been
+    /// made on it yet
+    idle: bool,
 }
 
 #[derive(Debug)]
 pub struct ConnInner {
     proto: ProtocolVersion,
-    #[allow(dead_code)] // TODO
     params: typemap::TypeMap<dyn typemap::DebugAny + Send + Sync>,
     state: connection::State,
+    state_codec: Option<StateCodec>,
     in_buf: BytesMut,
     out_buf: BytesMut,
     stream: TlsStream,
+    /// Runtime which I/O driver the socket is registered with
+    runtime: tokio::runtime::Id,
 }
 
 trait AssertConn: Send + 'static {}
@@ -59,35 +92,123 @@ impl Pool {
             semaphore: Arc::new(Semaphore::new(config.0.max_connections)),
             queue: BlockingMutex::new(
                 VecDeque::with_capacity(config.0.max_connections)),
-            config: config.clone(),
+            last_trace: BlockingMutex::new(None),
+            config: BlockingMutex::new(config.clone()),
+            generation: AtomicU64::new(0),
         }))
     }
     pub async fn acquire(&self) -> Result<Connection, Error> {
+        let in_transaction = TRANSACTION_POOL
+            .try_with(|pool| *pool == self.address())
+            .unwrap_or(false);
+        if in_transaction {
+            return Err(InterfaceError::with_message(
+                "query is run on the client (or its clone) inside of the \
+                 transaction body, so it would be executed outside of the \
+                 transaction. Use methods of the `Transaction` object \
+                 instead, or create a separate client if that is intended"));
+        }
         self.0.acquire().await
     }
+    /// Runs the future marking this pool as being used by the transaction
+    ///
+    /// Any attempt to acquire a connection from this pool within the
+    /// future (but not in tasks spawned from it) fails.
+    pub async fn in_transaction<F: Future>(&self, f: F) -> F::Output {
+        TRANSACTION_POOL.scope(self.address(), f).await
+    }
+    fn address(&self) -> usize {
+        Arc::as_ptr(&self.0) as usize
+    }
+    /// Replace configuration used for new connections
+    ///
+    /// Idle connections are closed immediately, connections that are
+    /// currently in use are closed when released. Pool size is not changed.
+    pub fn reload_config(&self, config: &Config) {
+        *self.0.config.lock()
+            .expect("pool shared state mutex is not poisoned")
+            = config.clone();
+        self.invalidate();
+    }
+    /// Close all connections without changing the configuration
+    ///
+    /// Same as `reload_config` with the current config: idle connections
+    /// are closed immediately and ones that are in use are closed when
+    /// released, so any state cached on connections is discarded.
+    pub fn invalidate(&self) {
+        self.0.generation.fetch_add(1, Ordering::SeqCst);
+        self.0.queue.lock()
+            .expect("pool shared state mutex is not poisoned")
+            .clear();
+    }
+    /// Entropy source of the current configuration
+    pub(crate) fn entropy(&self) -> Arc<dyn Entropy> {
+        self.0.config.lock()
+            .expect("pool shared state mutex is not poisoned")
+            .0.entropy.clone()
+    }
+    pub fn last_connection_trace(&self) -> Option<ConnectionTrace> {
+        self.0.last_trace.lock()
+            .expect("pool shared state mutex is not poisoned")
+            .clone()
+    }
 }
 
 impl PoolInner {
     fn _next_conn(&self, _permit: &sync::OwnedSemaphorePermit)
         -> Option<ConnInner>
     {
-        self.queue.lock()
-            .expect("pool shared state mutex is not poisoned")
-            .pop_front()
+        let runtime = tokio::runtime::Handle::current().id();
+        let mut queue = self.queue.lock()
+            .expect("pool shared state mutex is not poisoned");
+        while let Some(conn) = queue.pop_front() {
+            if conn.runtime != runtime {
+                // I/O is registered with the runtime the connection was
+                // created on, so it can't be used after that runtime is
+                // shut down and only progresses while that runtime is
+                // driven. Ids are never reused, so a runtime that has been
+                // shut down never matches.
+                log::debug!("Closing connection created on \
+                             another tokio runtime");
+                continue;
+            }
+            if conn.is_expired() {
+                log::debug!("Closing connection that has been idle \
+                             for longer than session_idle_timeout");
+                continue;
+            }
+            return Some(conn);
+        }
+        None
     }
     async fn acquire(self: &Arc<Self>) -> Result<Connection, Error> {
         let permit = self.semaphore.clone().acquire_owned().await
             .map_err(|e| ClientError::with_source(e)
                      .context("cannot acquire connection"))?;
+        // generation must be read before the config, so that connection
+        // made with an outdated config is never considered fresh
+        let generation = self.generation.load(Ordering::SeqCst);
         if let Some(conn) = self._next_conn(&permit) {
             assert!(conn.is_consistent());
             return Ok(Connection {
                 inner: Some(conn),
                 permit,
                 pool: self.clone(),
+                generation,
+                idle: true,
             });
         }
-        let conn = ConnInner::connect(&self.config).await?;
+        let config = self.config.lock()
+            .expect("pool shared state mutex is not poisoned")
+            .clone();
+        let mut tracer = Tracer::new(config.0.trace_connection);
+        let result = ConnInner::connect_traced(&config, &mut tracer).await;
+        if let Some(trace) = tracer.finish() {
+            *self.last_trace.lock()
+                .expect("pool shared state mutex is not poisoned")
+                = Some(trace);
+        }
+        let conn = result?;
         // Make sure that connection is wrapped before we commit,
         // so that connection is returned into a pool if we fail
         // to commit because of async stuff
@@ -95,6 +216,8 @@ impl PoolInner {
             inner: Some(conn),
             permit,
             pool: self.clone(),
+            generation,
+            idle: false,
         });
     }
 }
@@ -108,7 +231,11 @@ impl Connection {
 impl Drop for Connection {
     fn drop(&mut self) {
         if let Some(conn) = self.inner.take() {
-            if conn.is_consistent() {
+            let generation = self.pool.generation.load(Ordering::SeqCst);
+            if generation != self.generation {
+                log::debug!("Closing connection made with an outdated \
+                             configuration or schema");
+            } else if conn.is_consistent() {
                 self.pool.queue.lock()
                     .expect("pool shared state mutex is not poisoned")
                     .push_back(conn);
@@ -116,3 +243,110 @@ impl Drop for Connection {
         }
     }
 }
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::time::Duration;
+
+    use tokio::runtime;
+    use tokio::task::spawn_blocking;
+
+    use crate::server_params::SystemConfig;
+
+    use super::{Pool, Connection};
+    use super::test_server::TestServer;
+
+    fn set_idle_timeout(conn: &mut Connection, timeout: Duration) {
+        conn.inner.as_mut().unwrap().params.insert::<SystemConfig>(
+            SystemConfig { session_idle_timeout: Some(timeout) });
+    }
+
+    #[tokio::test]
+    async fn expired_connection() {
+        let mut server = TestServer::new();
+        server.start();
+        let pool = Pool::new(&server.config());
+        let mut conn = pool.acquire().await.unwrap();
+        set_idle_timeout(&mut conn, Duration::from_secs(3600));
+        drop(conn);
+        let mut conn = pool.acquire().await.unwrap();
+        assert_eq!(server.accepted(), 1);
+        // timeout is within the margin, so the connection is expired
+        // as soon as it's returned into the pool
+        set_idle_timeout(&mut conn, Duration::from_secs(1));
+        drop(conn);
+        let conn = pool.acquire().await.unwrap();
+        assert_eq!(server.accepted(), 2);
+        assert!(!conn.inner.as_ref().unwrap().is_expired());
+    }
+
+    fn new_runtime() -> runtime::Runtime {
+        runtime::Builder::new_current_thread().enable_all().build().unwrap()
+    }
+
+    #[tokio::test]
+    async fn dead_runtime() {
+        let mut server = TestServer::new();
+        server.start();
+        let pool = Pool::new(&server.config());
+        let other = pool.clone();
+        spawn_blocking(move || {
+            new_runtime().block_on(async { other.acquire().await.unwrap() });
+        }).await.unwrap();
+        assert_eq!(server.accepted(), 1);
+        drop(pool.acquire().await.unwrap());
+        assert_eq!(server.accepted(), 2);
+        drop(pool.acquire().await.unwrap());
+        assert_eq!(server.accepted(), 2);
+    }
+
+    #[tokio::test]
+    async fn alive_runtime() {
+        let mut server = TestServer::new();
+        server.start();
+        let pool = Pool::new(&server.config());
+        let other = pool.clone();
+        // runtime is still alive, but isn't driven by anyone
+        let runtime = spawn_blocking(move || {
+            let runtime = new_runtime();
+            runtime.block_on(async { other.acquire().await.unwrap() });
+            runtime
+        }).await.unwrap();
+        assert_eq!(server.accepted(), 1);
+        drop(pool.acquire().await.unwrap());
+        assert_eq!(server.accepted(), 2);
+        drop(pool.acquire().await.unwrap());
+        assert_eq!(server.accepted(), 2);
+        spawn_blocking(move || drop(runtime)).await.unwrap();
+    }
+
+    fn idle_connections(pool: &Pool) -> usize {
+        pool.0.queue.lock().unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn reload_config() {
+        let mut old = TestServer::new();
+        old.start();
+        let mut new = TestServer::new();
+        new.start();
+        let pool = Pool::new(&old.config());
+        drop(pool.acquire().await.unwrap());
+        assert_eq!(idle_connections(&pool), 1);
+
+        let conn = pool.acquire().await.unwrap();
+        pool.reload_config(&new.config());
+        // connection in use is closed on release
+        drop(conn);
+        assert_eq!(idle_connections(&pool), 0);
+        drop(pool.acquire().await.unwrap());
+        assert_eq!((old.accepted(), new.accepted()), (1, 1));
+        assert_eq!(idle_connections(&pool), 1);
+
+        // idle connections are closed immediately
+        pool.reload_config(&old.config());
+        assert_eq!(idle_connections(&pool), 0);
+        drop(pool.acquire().await.unwrap());
+        assert_eq!((old.accepted(), new.accepted()), (2, 1));
+    }
+}
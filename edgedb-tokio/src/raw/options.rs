@@ -1,8 +1,14 @@
-use crate::options::{TransactionOptions, RetryOptions};
+use std::sync::Arc;
+
+use crate::options::{TransactionOptions, RetryOptions, OfflineQueue};
+use crate::state::SessionState;
 
 
 #[derive(Debug, Clone, Default)]
 pub struct Options {
     pub(crate) transaction: TransactionOptions,
     pub(crate) retry: RetryOptions,
+    pub(crate) state: Arc<SessionState>,
+    pub(crate) offline: Option<OfflineQueue>,
+    pub(crate) unknown_scalars: bool,
 }
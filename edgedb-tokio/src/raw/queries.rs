@@ -11,16 +11,66 @@ use edgedb_protocol::client_message::{OptimisticExecute};
 use edgedb_protocol::common::{CompilationOptions, CompilationFlags};
 use edgedb_protocol::common::{IoFormat, Cardinality, Capabilities};
 use edgedb_protocol::features::ProtocolVersion;
+use edgedb_protocol::query_arg::ExternalData;
 use edgedb_protocol::server_message::{PrepareComplete, CommandDataDescription1};
 use edgedb_protocol::server_message::{ServerMessage, Data};
+use edgedb_protocol::server_message::{StateDataDescription};
 
 use crate::errors::{Error, ErrorKind};
 use crate::errors::{ProtocolOutOfOrderError, ClientInconsistentError};
+use crate::errors::{ClientError, StateMismatchError};
+use crate::errors::{ClientConnectionError, ClientConnectionClosedError};
+use crate::errors::{IdleSessionTimeoutError};
 use crate::raw::{ConnInner, Connection};
 use crate::raw::connection::State;
+use crate::server_params::{ServerParam, ServerKeyData};
+use crate::state::{SessionState, StateCodec};
+
+/// Data rows along with the status of the completed command
+#[derive(Debug)]
+pub struct Response {
+    pub data: Vec<Data>,
+    pub status_data: Bytes,
+}
+
+/// Query along with its arguments for pipelined execution
+#[derive(Debug)]
+pub struct Request<'a> {
+    pub query: &'a str,
+    pub desc: &'a CommandDataDescription1,
+    pub arguments: Bytes,
+}
 
 pub(crate) struct Guard;
 
+fn execute1(opts: &CompilationOptions, query: &str,
+            state_typedesc_id: Uuid, state_data: Bytes,
+            desc: &CommandDataDescription1, arguments: &Bytes)
+    -> ClientMessage
+{
+    let mut cflags = CompilationFlags::empty();
+    if opts.implicit_typenames {
+        cflags |= CompilationFlags::INJECT_OUTPUT_TYPE_NAMES;
+    }
+    if opts.implicit_typeids {
+        cflags |= CompilationFlags::INJECT_OUTPUT_TYPE_IDS;
+    }
+    ClientMessage::Execute1(Execute1 {
+        annotations: HashMap::new(),
+        allowed_capabilities: opts.allow_capabilities,
+        compilation_flags: cflags,
+        implicit_limit: opts.implicit_limit,
+        output_format: opts.io_format,
+        expected_cardinality: opts.expected_cardinality,
+        command_text: query.into(),
+        state_typedesc_id,
+        state_data,
+        input_typedesc_id: desc.input_typedesc_id,
+        output_typedesc_id: desc.output_typedesc_id,
+        arguments: arguments.clone(),
+    })
+}
+
 impl ConnInner {
     fn begin_request(&mut self) -> Result<Guard, Error> {
         match self.state {
@@ -65,29 +115,63 @@ impl ConnInner {
             }
         }
     }
-    pub async fn parse(&mut self, flags: &CompilationOptions, query: &str)
+    fn encode_state(&self, state: &SessionState)
+        -> Result<(Uuid, Bytes), Error>
+    {
+        if state.is_default() {
+            return Ok((Uuid::from_u128(0), Bytes::new()));
+        }
+        match &self.state_codec {
+            Some(codec) => Ok((codec.typedesc_id, state.encode(codec)?)),
+            None => Err(ClientError::with_message(
+                "server does not support session state")),
+        }
+    }
+    fn update_state_codec(&mut self, desc: StateDataDescription)
+        -> Result<(), Error>
+    {
+        self.state_codec = Some(StateCodec::new(desc, &self.proto)?);
+        Ok(())
+    }
+    pub async fn parse(&mut self, flags: &CompilationOptions, query: &str,
+                       state: &SessionState)
         -> Result<CommandDataDescription1, Error>
     {
         if self.proto.is_1() {
-            self._parse1(flags, query).await
+            match self._parse1(flags, query, state).await {
+                // new state descriptor has been received with the error
+                Err(e) if e.is::<StateMismatchError>() => {
+                    self._parse1(flags, query, state).await
+                }
+                res => res,
+            }
         } else {
+            self.encode_state(state)?;
             let pre = self._prepare0(flags, query).await?;
             self._describe0(pre).await
         }
     }
-    async fn _parse1(&mut self, flags: &CompilationOptions, query: &str)
+    async fn _parse1(&mut self, flags: &CompilationOptions, query: &str,
+                     state: &SessionState)
         -> Result<CommandDataDescription1, Error>
     {
+        let (state_typedesc_id, state_data) = self.encode_state(state)?;
         let guard = self.begin_request()?;
         self.send_messages(&[
-            ClientMessage::Parse(Parse::new(flags, query)),
+            ClientMessage::Parse(Parse {
+                state_typedesc_id,
+                state_data,
+                ..Parse::new(flags, query)
+            }),
             ClientMessage::Sync,
         ]).await?;
 
         loop {
             let msg = self.message().await?;
             match msg {
-                ServerMessage::StateDataDescription(..) => {}
+                ServerMessage::StateDataDescription(desc) => {
+                    self.update_state_codec(desc)?;
+                }
                 ServerMessage::CommandDataDescription1(data_desc) => {
                     self.expect_ready(guard).await?;
                     return Ok(data_desc);
@@ -106,6 +190,81 @@ impl ConnInner {
             }
         }
     }
+    pub async fn parse_many(&mut self, flags: &CompilationOptions,
+                            queries: &[&str], state: &SessionState)
+        -> Result<Vec<CommandDataDescription1>, Error>
+    {
+        if queries.is_empty() {
+            return Ok(Vec::new());
+        }
+        if self.proto.is_1() {
+            match self._parse_many1(flags, queries, state).await {
+                Err(e) if e.is::<StateMismatchError>() => {
+                    self._parse_many1(flags, queries, state).await
+                }
+                res => res,
+            }
+        } else {
+            self.encode_state(state)?;
+            // protocol 0.x has only one unnamed statement slot, so no
+            // pipelining is possible
+            let mut result = Vec::with_capacity(queries.len());
+            for (idx, query) in queries.iter().enumerate() {
+                let pre = self._prepare0(flags, query).await
+                    .map_err(|e| e.context(format!("query #{}", idx)))?;
+                result.push(self._describe0(pre).await?);
+            }
+            Ok(result)
+        }
+    }
+    async fn _parse_many1(&mut self, flags: &CompilationOptions,
+                          queries: &[&str], state: &SessionState)
+        -> Result<Vec<CommandDataDescription1>, Error>
+    {
+        let (state_typedesc_id, state_data) = self.encode_state(state)?;
+        let guard = self.begin_request()?;
+        let mut messages = Vec::with_capacity(queries.len() + 1);
+        for query in queries {
+            messages.push(ClientMessage::Parse(Parse {
+                state_typedesc_id,
+                state_data: state_data.clone(),
+                ..Parse::new(flags, query)
+            }));
+        }
+        messages.push(ClientMessage::Sync);
+        self.send_messages(&messages).await?;
+
+        let mut result = Vec::with_capacity(queries.len());
+        loop {
+            let msg = self.message().await?;
+            match msg {
+                ServerMessage::StateDataDescription(desc) => {
+                    self.update_state_codec(desc)?;
+                }
+                ServerMessage::CommandDataDescription1(data_desc) => {
+                    result.push(data_desc);
+                    if result.len() == queries.len() {
+                        self.expect_ready(guard).await?;
+                        return Ok(result);
+                    }
+                }
+                ServerMessage::ErrorResponse(err) => {
+                    // server skips the rest of the messages until Sync
+                    self.expect_ready(guard).await
+                        .map_err(|e| log::warn!(
+                            "Error waiting for Ready after error: {e:#}"))
+                        .ok();
+                    let err: Error = err.into();
+                    return Err(err.context(
+                        format!("query #{}", result.len())));
+                }
+                _ => {
+                    return Err(ProtocolOutOfOrderError::with_message(format!(
+                        "Unsolicited message {:?}", msg)));
+                }
+            }
+        }
+    }
     async fn _prepare0(&mut self, flags: &CompilationOptions, query: &str)
         -> Result<PrepareComplete, Error>
     {
@@ -182,57 +341,67 @@ impl ConnInner {
         })
     }
     pub async fn execute(&mut self, opts: &CompilationOptions, query: &str,
-                         desc: &CommandDataDescription1, arguments: &Bytes)
-        -> Result<Vec<Data>, Error>
+                         state: &SessionState,
+                         desc: &CommandDataDescription1, arguments: &Bytes,
+                         external: Vec<ExternalData>)
+        -> Result<Response, Error>
     {
         if self.proto.is_1() {
-            self._execute1(opts, query, desc, arguments).await
+            if !external.is_empty() {
+                // external data can't be read twice, so the query
+                // can't be repeated on state mismatch
+                return self._execute1(opts, query, state, desc,
+                                      arguments, external).await;
+            }
+            // state is checked before the query is run, so it's safe to
+            // repeat the query with the updated descriptor
+            match self._execute1(opts, query, state, desc,
+                                 arguments, Vec::new()).await
+            {
+                Err(e) if e.is::<StateMismatchError>() => {
+                    self._execute1(opts, query, state, desc,
+                                   arguments, Vec::new()).await
+                }
+                res => res,
+            }
         } else {
-            self._execute0(arguments).await
+            self.encode_state(state)?;
+            self._execute0(arguments, external).await
         }
     }
 
     async fn _execute1(&mut self, opts: &CompilationOptions, query: &str,
-                       desc: &CommandDataDescription1, arguments: &Bytes)
-        -> Result<Vec<Data>, Error>
+                       state: &SessionState,
+                       desc: &CommandDataDescription1, arguments: &Bytes,
+                       external: Vec<ExternalData>)
+        -> Result<Response, Error>
     {
+        let (state_typedesc_id, state_data) = self.encode_state(state)?;
         let guard = self.begin_request()?;
-        let mut cflags = CompilationFlags::empty();
-        if opts.implicit_typenames {
-            cflags |= CompilationFlags::INJECT_OUTPUT_TYPE_NAMES;
-        }
-        if opts.implicit_typeids {
-            cflags |= CompilationFlags::INJECT_OUTPUT_TYPE_IDS;
+        let execute = execute1(opts, query, state_typedesc_id, state_data,
+                               desc, arguments);
+        if external.is_empty() {
+            self.send_messages(&[execute, ClientMessage::Sync]).await?;
+        } else {
+            self.send_external(&execute, arguments.len(), external).await?;
         }
-        self.send_messages(&[
-            ClientMessage::Execute1(Execute1 {
-                annotations: HashMap::new(),
-                allowed_capabilities: opts.allow_capabilities,
-                compilation_flags: cflags,
-                implicit_limit: opts.implicit_limit,
-                output_format: opts.io_format,
-                expected_cardinality: opts.expected_cardinality,
-                command_text: query.into(),
-                state_typedesc_id: Uuid::from_u128(0),  // TODO(tailhook)
-                state_data: Bytes::new(),
-                input_typedesc_id: desc.input_typedesc_id,
-                output_typedesc_id: desc.output_typedesc_id,
-                arguments: arguments.clone(),
-            }),
-            ClientMessage::Sync,
-        ]).await?;
 
         let mut result = Vec::new();
         loop {
             let msg = self.message().await?;
             match msg {
-                ServerMessage::StateDataDescription(..) => {}
+                ServerMessage::StateDataDescription(desc) => {
+                    self.update_state_codec(desc)?;
+                }
                 ServerMessage::Data(data) => {
                     result.push(data);
                 }
-                ServerMessage::CommandComplete1(_) => {
+                ServerMessage::CommandComplete1(complete) => {
                     self.expect_ready(guard).await?;
-                    return Ok(result);
+                    return Ok(Response {
+                        data: result,
+                        status_data: complete.status_data,
+                    });
                 }
                 ServerMessage::ErrorResponse(err) => {
                     self.expect_ready(guard).await
@@ -249,18 +418,109 @@ impl ConnInner {
         }
     }
 
-    async fn _execute0(&mut self, arguments: &Bytes)
-        -> Result<Vec<Data>, Error>
+    pub async fn execute_many(&mut self, opts: &CompilationOptions,
+                              requests: &[Request<'_>],
+                              state: &SessionState)
+        -> Result<Vec<Response>, Error>
+    {
+        if requests.is_empty() {
+            return Ok(Vec::new());
+        }
+        if self.proto.is_1() {
+            // state is checked before the first query is run, and the rest
+            // are skipped by the server after an error
+            match self._execute_many1(opts, requests, state).await {
+                Err(e) if e.is::<StateMismatchError>() => {
+                    self._execute_many1(opts, requests, state).await
+                }
+                res => res,
+            }
+        } else {
+            self.encode_state(state)?;
+            // protocol 0.x executes the last prepared statement, so every
+            // query has to be prepared again right before execution
+            let mut result = Vec::with_capacity(requests.len());
+            for (idx, req) in requests.iter().enumerate() {
+                self._prepare0(opts, req.query).await
+                    .map_err(|e| e.context(format!("query #{}", idx)))?;
+                let response = self._execute0(&req.arguments, Vec::new())
+                    .await
+                    .map_err(|e| e.context(format!("query #{}", idx)))?;
+                result.push(response);
+            }
+            Ok(result)
+        }
+    }
+
+    async fn _execute_many1(&mut self, opts: &CompilationOptions,
+                            requests: &[Request<'_>], state: &SessionState)
+        -> Result<Vec<Response>, Error>
     {
+        let (state_typedesc_id, state_data) = self.encode_state(state)?;
         let guard = self.begin_request()?;
-        self.send_messages(&[
-            ClientMessage::Execute0(Execute0 {
-                headers: HashMap::new(),
-                statement_name: Bytes::from(""),
-                arguments: arguments.clone(),
-            }),
-            ClientMessage::Sync,
-        ]).await?;
+        let mut messages = Vec::with_capacity(requests.len() + 1);
+        for req in requests {
+            messages.push(execute1(opts, req.query,
+                state_typedesc_id, state_data.clone(),
+                req.desc, &req.arguments));
+        }
+        messages.push(ClientMessage::Sync);
+        self.send_messages(&messages).await?;
+
+        let mut result = Vec::with_capacity(requests.len());
+        let mut data = Vec::new();
+        loop {
+            let msg = self.message().await?;
+            match msg {
+                ServerMessage::StateDataDescription(desc) => {
+                    self.update_state_codec(desc)?;
+                }
+                ServerMessage::Data(chunk) => {
+                    data.push(chunk);
+                }
+                ServerMessage::CommandComplete1(complete) => {
+                    result.push(Response {
+                        data: std::mem::take(&mut data),
+                        status_data: complete.status_data,
+                    });
+                    if result.len() == requests.len() {
+                        self.expect_ready(guard).await?;
+                        return Ok(result);
+                    }
+                }
+                ServerMessage::ErrorResponse(err) => {
+                    // server skips the rest of the messages until Sync
+                    self.expect_ready(guard).await
+                        .map_err(|e| log::warn!(
+                            "Error waiting for Ready after error: {e:#}"))
+                        .ok();
+                    let err: Error = err.into();
+                    return Err(err.context(
+                        format!("query #{}", result.len())));
+                }
+                _ => {
+                    return Err(ProtocolOutOfOrderError::with_message(format!(
+                        "Unsolicited message {:?}", msg)));
+                }
+            }
+        }
+    }
+
+    async fn _execute0(&mut self, arguments: &Bytes,
+                       external: Vec<ExternalData>)
+        -> Result<Response, Error>
+    {
+        let guard = self.begin_request()?;
+        let execute = ClientMessage::Execute0(Execute0 {
+            headers: HashMap::new(),
+            statement_name: Bytes::from(""),
+            arguments: arguments.clone(),
+        });
+        if external.is_empty() {
+            self.send_messages(&[execute, ClientMessage::Sync]).await?;
+        } else {
+            self.send_external(&execute, arguments.len(), external).await?;
+        }
 
         let mut result = Vec::new();
         loop {
@@ -269,9 +529,12 @@ impl ConnInner {
                 ServerMessage::Data(data) => {
                     result.push(data);
                 }
-                ServerMessage::CommandComplete0(_) => {
+                ServerMessage::CommandComplete0(complete) => {
                     self.expect_ready(guard).await?;
-                    return Ok(result);
+                    return Ok(Response {
+                        data: result,
+                        status_data: complete.status_data,
+                    });
                 }
                 ServerMessage::ErrorResponse(err) => {
                     self.expect_ready(guard).await
@@ -287,19 +550,28 @@ impl ConnInner {
             }
         }
     }
-    pub async fn statement(&mut self, flags: &CompilationOptions, query: &str)
+    pub async fn statement(&mut self, flags: &CompilationOptions, query: &str,
+                           state: &SessionState)
         -> Result<(), Error>
     {
         if self.proto.is_1() {
-            self._statement1(flags, query).await
+            match self._statement1(flags, query, state).await {
+                Err(e) if e.is::<StateMismatchError>() => {
+                    self._statement1(flags, query, state).await
+                }
+                res => res,
+            }
         } else {
+            self.encode_state(state)?;
             self._statement0(flags, query).await
         }
     }
 
-    async fn _statement1(&mut self, opts: &CompilationOptions, query: &str)
+    async fn _statement1(&mut self, opts: &CompilationOptions, query: &str,
+                         state: &SessionState)
         -> Result<(), Error>
     {
+        let (state_typedesc_id, state_data) = self.encode_state(state)?;
         let guard = self.begin_request()?;
         let mut cflags = CompilationFlags::empty();
         if opts.implicit_typenames {
@@ -317,8 +589,8 @@ impl ConnInner {
                 output_format: opts.io_format,
                 expected_cardinality: opts.expected_cardinality,
                 command_text: query.into(),
-                state_typedesc_id: Uuid::from_u128(0),  // TODO(tailhook)
-                state_data: Bytes::new(),
+                state_typedesc_id,
+                state_data,
                 input_typedesc_id: Uuid::from_u128(0),
                 output_typedesc_id: Uuid::from_u128(0),
                 arguments: Bytes::new(),
@@ -330,7 +602,9 @@ impl ConnInner {
         loop {
             let msg = self.message().await?;
             match msg {
-                ServerMessage::StateDataDescription(..) => {}
+                ServerMessage::StateDataDescription(desc) => {
+                    self.update_state_codec(desc)?;
+                }
                 ServerMessage::Data(data) => {
                     result.push(data);
                 }
@@ -393,20 +667,75 @@ impl ConnInner {
 }
 
 impl Connection {
-    pub async fn parse(&mut self, flags: &CompilationOptions, query: &str)
+    pub async fn parse(&mut self, flags: &CompilationOptions, query: &str,
+                       state: &SessionState)
         -> Result<CommandDataDescription1, Error>
     {
-        self.inner.as_mut().expect("connection is not dropped")
-            .parse(flags, query).await
+        let result = self.inner.as_mut().expect("connection is not dropped")
+            .parse(flags, query, state).await;
+        self.check_idle(result)
+            .map_err(|e| state.annotate(e, flags.allow_capabilities, None))
+    }
+    pub async fn parse_many(&mut self, flags: &CompilationOptions,
+                            queries: &[&str], state: &SessionState)
+        -> Result<Vec<CommandDataDescription1>, Error>
+    {
+        let result = self.inner.as_mut().expect("connection is not dropped")
+            .parse_many(flags, queries, state).await;
+        self.check_idle(result)
+            .map_err(|e| state.annotate(e, flags.allow_capabilities, None))
     }
     pub async fn execute(&mut self, opts: &CompilationOptions, query: &str,
+                         state: &SessionState,
                          desc: &CommandDataDescription1, arguments: &Bytes)
         -> Result<Vec<Data>, Error>
     {
+        self.execute_response(opts, query, state, desc, arguments).await
+            .map(|response| response.data)
+    }
+    pub async fn execute_response(&mut self, opts: &CompilationOptions,
+                                  query: &str, state: &SessionState,
+                                  desc: &CommandDataDescription1,
+                                  arguments: &Bytes)
+        -> Result<Response, Error>
+    {
+        self.execute_external(opts, query, state, desc, arguments,
+                              Vec::new()).await
+    }
+    /// Execute a query with argument data streamed from external sources
+    ///
+    /// External data is collected from the argument encoder created with
+    /// `Encoder::with_external` after encoding arguments.
+    pub async fn execute_external(&mut self, opts: &CompilationOptions,
+                                  query: &str, state: &SessionState,
+                                  desc: &CommandDataDescription1,
+                                  arguments: &Bytes,
+                                  external: Vec<ExternalData>)
+        -> Result<Response, Error>
+    {
+        self.idle = false;
+        self.inner.as_mut().expect("connection is not dropped")
+            .execute(opts, query, state, desc, arguments, external).await
+            .map_err(|e| state.annotate(e, opts.allow_capabilities,
+                                        Some(desc.capabilities)))
+    }
+    /// Execute multiple already parsed queries in a single round trip
+    ///
+    /// Responses are returned in the same order as requests. If any query
+    /// fails, the following ones are not executed.
+    pub async fn execute_many(&mut self, opts: &CompilationOptions,
+                              requests: &[Request<'_>],
+                              state: &SessionState)
+        -> Result<Vec<Response>, Error>
+    {
+        self.idle = false;
         self.inner.as_mut().expect("connection is not dropped")
-            .execute(opts, query, desc, arguments).await
+            .execute_many(opts, requests, state).await
+            .map_err(|e| state.annotate(e, opts.allow_capabilities, None))
     }
-    pub async fn statement(&mut self, query: &str) -> Result<(), Error> {
+    pub async fn statement(&mut self, query: &str, state: &SessionState)
+        -> Result<(), Error>
+    {
         let flags = CompilationOptions {
             implicit_limit: None,
             implicit_typenames: false,
@@ -416,10 +745,49 @@ impl Connection {
             io_format: IoFormat::Binary,
             expected_cardinality: Cardinality::Many, // no result is unsupported
         };
+        self.idle = false;
         self.inner.as_mut().expect("connection is not dropped")
-            .statement(&flags, query).await
+            .statement(&flags, query, state).await
+            .map_err(|e| state.annotate(e, flags.allow_capabilities, None))
+    }
+    /// Marks connection errors on the first request after the connection
+    /// was taken from the pool as [`ClientConnectionClosedError`]
+    ///
+    /// The server closes connections that are idle for too long (or on
+    /// restart), which is only noticed when the next request is sent. Only
+    /// parse requests are checked: they don't execute anything, so such
+    /// errors are safe to retry. Statements may have been executed before
+    /// the connection was closed, so their errors are returned as is.
+    fn check_idle<T>(&mut self, result: Result<T, Error>)
+        -> Result<T, Error>
+    {
+        let idle = std::mem::replace(&mut self.idle, false);
+        match result {
+            Err(e) if idle && (
+                e.is::<ClientConnectionError>() ||
+                e.is::<IdleSessionTimeoutError>()
+            ) => {
+                Err(e.refine_kind::<ClientConnectionClosedError>()
+                    .context("connection was closed while idle in the pool"))
+            }
+            result => result,
+        }
     }
     pub fn proto(&self) -> &ProtocolVersion {
         &self.inner.as_ref().expect("connection is not dropped").proto
     }
+    /// Returns parameter sent by the server on connection
+    pub fn get_param<T: ServerParam>(&self) -> Option<&T::Value>
+        where T::Value: typemap::DebugAny + Send + Sync,
+    {
+        self.inner.as_ref().expect("connection is not dropped")
+            .params.get::<T>()
+    }
+    /// Returns key data that can be used to cancel queries running on this
+    /// connection
+    ///
+    /// Key is only sent by the servers that support cancellation.
+    pub fn server_key_data(&self) -> Option<&[u8; 32]> {
+        self.get_param::<ServerKeyData>().map(|key| &key.0)
+    }
 }
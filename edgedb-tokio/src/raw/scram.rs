@@ -0,0 +1,362 @@
+//! SCRAM-SHA-256 and SCRAM-SHA-256-PLUS client (RFC 5802, RFC 7677)
+//!
+//! Channel binding uses `tls-server-end-point` type (RFC 5929), i.e. hash of
+//! the server certificate.
+use std::borrow::Cow;
+use std::num::NonZeroU32;
+
+use ring::{constant_time, digest, hmac, pbkdf2};
+
+use crate::entropy::Entropy;
+use crate::errors::{Error, ErrorKind};
+use crate::errors::{AuthenticationError, ProtocolError};
+
+pub const SCRAM_SHA_256: &str = "SCRAM-SHA-256";
+pub const SCRAM_SHA_256_PLUS: &str = "SCRAM-SHA-256-PLUS";
+
+/// Random bytes in the nonce, gives 24 characters when encoded in base64
+const NONCE_BYTES: usize = 18;
+
+/// Channel binding as negotiated with the server
+#[derive(Debug)]
+pub enum ChannelBinding {
+    /// Client doesn't support channel binding (e.g. no TLS is used)
+    Unsupported,
+    /// Client supports channel binding, but server doesn't offer it
+    NotOffered,
+    /// `tls-server-end-point` channel binding data
+    TlsServerEndPoint(Vec<u8>),
+}
+
+pub struct ScramClient {
+    password: String,
+    gs2_header: &'static str,
+    binding_data: Vec<u8>,
+    nonce: String,
+    client_first_bare: String,
+}
+
+pub struct ServerFinal {
+    server_signature: hmac::Tag,
+}
+
+impl ChannelBinding {
+    /// Choose channel binding by the certificate of the server and methods
+    /// offered by it
+    pub fn negotiate(server_cert: Option<&[u8]>, methods: &[String])
+        -> ChannelBinding
+    {
+        match server_cert {
+            Some(cert) if methods.iter().any(|m| m == SCRAM_SHA_256_PLUS)
+            => ChannelBinding::TlsServerEndPoint(server_end_point(cert)),
+            Some(_) => ChannelBinding::NotOffered,
+            None => ChannelBinding::Unsupported,
+        }
+    }
+    /// Name of the SASL method used with this channel binding
+    pub fn method(&self) -> &'static str {
+        match self {
+            ChannelBinding::TlsServerEndPoint(_) => SCRAM_SHA_256_PLUS,
+            _ => SCRAM_SHA_256,
+        }
+    }
+}
+
+impl ScramClient {
+    pub fn new(user: &str, password: &str, binding: ChannelBinding,
+               entropy: &dyn Entropy)
+        -> ScramClient
+    {
+        let mut bytes = [0u8; NONCE_BYTES];
+        entropy.fill_bytes(&mut bytes);
+        // base64 is printable ASCII without commas, as required for nonce
+        let nonce = base64::encode(&bytes);
+        ScramClient::with_nonce(user, password, binding, nonce)
+    }
+    fn with_nonce(user: &str, password: &str, binding: ChannelBinding,
+                  nonce: String)
+        -> ScramClient
+    {
+        let (gs2_header, binding_data) = match binding {
+            ChannelBinding::Unsupported => ("n,,", Vec::new()),
+            ChannelBinding::NotOffered => ("y,,", Vec::new()),
+            ChannelBinding::TlsServerEndPoint(data) => {
+                ("p=tls-server-end-point,,", data)
+            }
+        };
+        let password = stringprep::saslprep(password)
+            .unwrap_or(Cow::Borrowed(password))
+            .into_owned();
+        let user = user.replace('=', "=3D").replace(',', "=2C");
+        ScramClient {
+            password,
+            gs2_header,
+            client_first_bare: format!("n={},r={}", user, nonce),
+            nonce,
+            binding_data,
+        }
+    }
+    /// Name of the SASL method used
+    pub fn method(&self) -> &'static str {
+        if self.binding_data.is_empty() {
+            SCRAM_SHA_256
+        } else {
+            SCRAM_SHA_256_PLUS
+        }
+    }
+    pub fn client_first(&self) -> String {
+        format!("{}{}", self.gs2_header, self.client_first_bare)
+    }
+    /// Returns client-final message along with the data to verify the
+    /// server-final message
+    pub fn handle_server_first(&self, server_first: &str)
+        -> Result<(String, ServerFinal), Error>
+    {
+        let mut nonce = None;
+        let mut salt = None;
+        let mut iterations = None;
+        for attr in server_first.split(',') {
+            match attr.split_at(attr.find('=').map(|i| i+1).unwrap_or(0)) {
+                ("r=", value) => nonce = Some(value),
+                ("s=", value) => salt = Some(value),
+                ("i=", value) => iterations = Some(value),
+                ("m=", _) => {
+                    return Err(AuthenticationError::with_message(
+                        "unsupported SCRAM extension"));
+                }
+                _ => {}
+            }
+        }
+        let (nonce, salt, iterations) = match (nonce, salt, iterations) {
+            (Some(n), Some(s), Some(i)) => (n, s, i),
+            _ => {
+                return Err(ProtocolError::with_message(
+                    "invalid SCRAM server-first message"));
+            }
+        };
+        if !nonce.starts_with(&self.nonce) {
+            return Err(AuthenticationError::with_message(
+                "SCRAM server nonce doesn't match client nonce"));
+        }
+        let salt = base64::decode(salt)
+            .map_err(|e| ProtocolError::with_source(e)
+                     .context("invalid SCRAM salt"))?;
+        let iterations = iterations.parse::<NonZeroU32>()
+            .map_err(|e| ProtocolError::with_source(e)
+                     .context("invalid SCRAM iteration count"))?;
+
+        let mut salted_password = [0u8; digest::SHA256_OUTPUT_LEN];
+        pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, &salt,
+                       self.password.as_bytes(), &mut salted_password);
+        let salted_key = hmac::Key::new(hmac::HMAC_SHA256, &salted_password);
+        let client_key = hmac::sign(&salted_key, b"Client Key");
+        let stored_key = digest::digest(&digest::SHA256, client_key.as_ref());
+        let server_key = hmac::sign(&salted_key, b"Server Key");
+
+        let mut binding = self.gs2_header.as_bytes().to_vec();
+        binding.extend(&self.binding_data);
+        let client_final_bare = format!("c={},r={}",
+                                        base64::encode(binding), nonce);
+        let auth_message = format!("{},{},{}",
+            self.client_first_bare, server_first, client_final_bare);
+
+        let client_signature = hmac::sign(
+            &hmac::Key::new(hmac::HMAC_SHA256, stored_key.as_ref()),
+            auth_message.as_bytes());
+        let proof = client_key.as_ref().iter()
+            .zip(client_signature.as_ref())
+            .map(|(k, s)| k ^ s)
+            .collect::<Vec<u8>>();
+        let server_signature = hmac::sign(
+            &hmac::Key::new(hmac::HMAC_SHA256, server_key.as_ref()),
+            auth_message.as_bytes());
+        Ok((
+            format!("{},p={}", client_final_bare, base64::encode(proof)),
+            ServerFinal { server_signature },
+        ))
+    }
+}
+
+#[cfg(feature="zeroize")]
+impl Drop for ScramClient {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.password);
+    }
+}
+
+impl ServerFinal {
+    pub fn verify(&self, server_final: &str) -> Result<(), Error> {
+        if let Some(err) = server_final.strip_prefix("e=") {
+            return Err(AuthenticationError::with_message(format!(
+                "Authentication error: {}", err)));
+        }
+        let signature = server_final.split(',')
+            .find_map(|attr| attr.strip_prefix("v="))
+            .ok_or_else(|| ProtocolError::with_message(
+                "invalid SCRAM server-final message"))?;
+        let signature = base64::decode(signature)
+            .map_err(|e| ProtocolError::with_source(e)
+                     .context("invalid SCRAM server signature"))?;
+        let valid = constant_time::verify_slices_are_equal(
+            &signature, self.server_signature.as_ref());
+        if valid.is_err() {
+            return Err(AuthenticationError::with_message(
+                "SCRAM server signature mismatch"));
+        }
+        Ok(())
+    }
+}
+
+/// Hash of the certificate for `tls-server-end-point` channel binding
+///
+/// Uses hash function of the certificate signature algorithm, or SHA-256
+/// if the algorithm is based on MD5 or SHA-1 or can't be determined.
+fn server_end_point(cert: &[u8]) -> Vec<u8> {
+    // OIDs of sha{384,512}WithRSAEncryption and ecdsa-with-SHA{384,512}
+    const SHA384: &[&[u8]] = &[
+        b"\x2a\x86\x48\x86\xf7\x0d\x01\x01\x0c",
+        b"\x2a\x86\x48\xce\x3d\x04\x03\x03",
+    ];
+    const SHA512: &[&[u8]] = &[
+        b"\x2a\x86\x48\x86\xf7\x0d\x01\x01\x0d",
+        b"\x2a\x86\x48\xce\x3d\x04\x03\x04",
+    ];
+    let algorithm = signature_algorithm(cert);
+    let hash = match algorithm {
+        Some(oid) if SHA384.contains(&oid) => &digest::SHA384,
+        Some(oid) if SHA512.contains(&oid) => &digest::SHA512,
+        _ => &digest::SHA256,
+    };
+    digest::digest(hash, cert).as_ref().to_vec()
+}
+
+/// Returns OID of the `signatureAlgorithm` of DER-encoded certificate
+fn signature_algorithm(cert: &[u8]) -> Option<&[u8]> {
+    // Certificate ::= SEQUENCE {
+    //     tbsCertificate       TBSCertificate,
+    //     signatureAlgorithm   AlgorithmIdentifier,
+    //     signatureValue       BIT STRING }
+    let (_, certificate, _) = der_element(cert)?;
+    let (_, _tbs, rest) = der_element(certificate)?;
+    let (_, algorithm, _) = der_element(rest)?;
+    let (tag, oid, _) = der_element(algorithm)?;
+    if tag != 0x06 {
+        return None;
+    }
+    Some(oid)
+}
+
+/// Splits DER element into tag, contents and the remaining data
+fn der_element(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, data) = data.split_first()?;
+    let (&first, data) = data.split_first()?;
+    let (len, data) = if first < 0x80 {
+        (first as usize, data)
+    } else {
+        let num = (first & 0x7f) as usize;
+        if num == 0 || num > 4 || data.len() < num {
+            return None;
+        }
+        let len = data[..num].iter()
+            .fold(0usize, |len, b| (len << 8) | *b as usize);
+        (len, &data[num..])
+    };
+    if data.len() < len {
+        return None;
+    }
+    Some((tag, &data[..len], &data[len..]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ScramClient, ChannelBinding, server_end_point};
+    use crate::entropy::Entropy;
+
+    /// Self-signed certificate with `ecdsa-with-SHA384` signature
+    const SHA384_CERT: &[u8] = include_bytes!("../../tests/certs/sha384.der");
+
+    #[derive(Debug)]
+    struct Fixed;
+
+    impl Entropy for Fixed {
+        fn fill_bytes(&self, buf: &mut [u8]) {
+            buf.fill(0xFF);
+        }
+    }
+
+    #[test]
+    fn nonce() {
+        let client = ScramClient::new("user", "pencil",
+            ChannelBinding::Unsupported, &Fixed);
+        assert_eq!(client.client_first(),
+                   "n,,n=user,r=////////////////////////");
+    }
+
+    #[test]
+    fn rfc7677_example() {
+        let client = ScramClient::with_nonce("user", "pencil",
+            ChannelBinding::Unsupported, "rOprNGfwEbeRWgbNEkqO".into());
+        assert_eq!(client.method(), "SCRAM-SHA-256");
+        assert_eq!(client.client_first(), "n,,n=user,r=rOprNGfwEbeRWgbNEkqO");
+        let (client_final, server_final) = client.handle_server_first(
+            "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+             s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096").unwrap();
+        assert_eq!(client_final,
+            "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+             p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=");
+        server_final.verify(
+            "v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=").unwrap();
+        assert!(server_final.verify(
+            "v=AAAATRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=").is_err());
+    }
+
+    #[test]
+    fn channel_binding() {
+        let client = ScramClient::with_nonce("user", "pencil",
+            ChannelBinding::TlsServerEndPoint(vec![1, 2, 3]),
+            "abc".into());
+        assert_eq!(client.method(), "SCRAM-SHA-256-PLUS");
+        assert_eq!(client.client_first(),
+                   "p=tls-server-end-point,,n=user,r=abc");
+        let (client_final, _) = client.handle_server_first(
+            "r=abcdef,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=16").unwrap();
+        // base64 of "p=tls-server-end-point,,\x01\x02\x03"
+        assert!(client_final.starts_with(
+            "c=cD10bHMtc2VydmVyLWVuZC1wb2ludCwsAQID,r=abcdef,p="));
+
+        let client = ScramClient::with_nonce("user", "pencil",
+            ChannelBinding::NotOffered, "abc".into());
+        assert_eq!(client.method(), "SCRAM-SHA-256");
+        assert_eq!(client.client_first(), "y,,n=user,r=abc");
+    }
+
+    #[test]
+    fn negotiate() {
+        let plus_only = vec![String::from("SCRAM-SHA-256-PLUS")];
+        let binding = ChannelBinding::negotiate(Some(SHA384_CERT), &plus_only);
+        assert_eq!(binding.method(), "SCRAM-SHA-256-PLUS");
+        let binding = ChannelBinding::negotiate(None, &plus_only);
+        assert_eq!(binding.method(), "SCRAM-SHA-256");
+        let plain = vec![String::from("SCRAM-SHA-256")];
+        let binding = ChannelBinding::negotiate(Some(SHA384_CERT), &plain);
+        assert_eq!(binding.method(), "SCRAM-SHA-256");
+    }
+
+    #[test]
+    fn end_point_sha384() {
+        // openssl dgst -sha384 tests/certs/sha384.der
+        assert_eq!(server_end_point(SHA384_CERT),
+            b"\x53\x7a\x04\xad\x2b\xf3\x25\x14\x7c\x98\x71\xb2\
+              \x78\xed\x94\x8d\x37\x83\x44\x33\x4f\xd0\x52\x43\
+              \xfe\xb9\x40\xd3\x16\x4f\xa4\x8a\x7c\xb8\xbb\x96\
+              \xa3\x0b\x9a\x84\xcd\xc5\xc3\xfa\x6e\xff\x6d\x36");
+    }
+
+    #[test]
+    fn bad_nonce() {
+        let client = ScramClient::with_nonce("user", "pencil",
+            ChannelBinding::Unsupported, "abc".into());
+        assert!(client.handle_server_first(
+            "r=xyz,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=16").is_err());
+    }
+}
@@ -0,0 +1,204 @@
+//! Fake server for tests of the pool and the client
+//!
+//! Speaks just enough of the protocol to establish a connection over a Unix
+//! socket and to run queries returning a single `std::str`.
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bytes::{Bytes, BytesMut, BufMut};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::task::JoinHandle;
+
+use edgedb_protocol::client_message::ClientMessage;
+use edgedb_protocol::common::{Capabilities, Cardinality};
+use edgedb_protocol::encoding::{Input, Output};
+use edgedb_protocol::features::ProtocolVersion;
+use edgedb_protocol::model::Uuid;
+use edgedb_protocol::server_message::{ServerMessage, Authentication};
+use edgedb_protocol::server_message::{CommandComplete1, Data};
+use edgedb_protocol::server_message::{CommandDataDescription1};
+use edgedb_protocol::server_message::{ReadyForCommand, TransactionState};
+use edgedb_protocol::server_message::{ServerKeyData};
+
+use crate::builder::{Address, Builder, Config};
+
+const STR_TYPE: u128 = 0x101;
+static SERVER_NO: AtomicUsize = AtomicUsize::new(0);
+
+/// Reply of the server to a client message
+pub enum Reply {
+    Send(Vec<ServerMessage>),
+    /// Close the connection without replying
+    Close,
+}
+
+/// Handles client messages
+///
+/// Receives zero-based number of the connection and the message. Replying
+/// `Close` to the handshake refuses the connection, any other reply to it is
+/// replaced by a successful handshake.
+pub type Handler = Arc<dyn Fn(usize, &ClientMessage) -> Reply + Send + Sync>;
+
+pub struct TestServer {
+    dir: PathBuf,
+    accepted: Arc<AtomicUsize>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl TestServer {
+    /// Reserve a socket path, the server isn't listening yet
+    pub fn new() -> TestServer {
+        let dir = std::env::temp_dir().join(format!(
+            "edgedb-tokio-test-{}-{}",
+            process::id(), SERVER_NO.fetch_add(1, Ordering::SeqCst)));
+        std::fs::create_dir_all(&dir).expect("can create temp dir");
+        TestServer {
+            dir,
+            accepted: Arc::new(AtomicUsize::new(0)),
+            task: None,
+        }
+    }
+    /// Start listening and reply with [`default_reply`]
+    pub fn start(&mut self) {
+        self.start_with(Arc::new(|_, msg| default_reply(msg)))
+    }
+    pub fn start_with(&mut self, handler: Handler) {
+        let listener = UnixListener::bind(self.path())
+            .expect("can bind test socket");
+        let accepted = self.accepted.clone();
+        self.task = Some(tokio::spawn(async move {
+            loop {
+                let (sock, _) = match listener.accept().await {
+                    Ok(pair) => pair,
+                    Err(_) => return,
+                };
+                let index = accepted.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(serve(sock, index, handler.clone()));
+            }
+        }));
+    }
+    pub fn path(&self) -> PathBuf {
+        self.dir.join("edgedb.sock")
+    }
+    /// Configuration connecting to this server
+    pub fn config(&self) -> Config {
+        let mut builder = Builder::uninitialized();
+        builder.host_port(None::<&str>, None);
+        builder.wait_until_available(std::time::Duration::ZERO);
+        let mut config = builder.build().expect("config is valid");
+        Arc::get_mut(&mut config.0).expect("config is not shared")
+            .address = Address::Unix(self.path());
+        config
+    }
+    /// Number of connections accepted so far
+    pub fn accepted(&self) -> usize {
+        self.accepted.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        std::fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+/// Replies to parse and execute of any query as if it returns `"ok"`
+pub fn default_reply(msg: &ClientMessage) -> Reply {
+    let proto = ProtocolVersion::current();
+    match msg {
+        ClientMessage::Parse(_) => {
+            let mut output_typedesc = BytesMut::with_capacity(17);
+            output_typedesc.put_u8(2);  // base scalar
+            output_typedesc.put_u128(STR_TYPE);
+            Reply::Send(vec![
+                ServerMessage::CommandDataDescription1(
+                    CommandDataDescription1 {
+                        proto,
+                        annotations: HashMap::new(),
+                        capabilities: Capabilities::empty(),
+                        result_cardinality: Cardinality::Many,
+                        input_typedesc_id: Uuid::from_u128(0),
+                        input_typedesc: Bytes::new(),
+                        output_typedesc_id: Uuid::from_u128(STR_TYPE),
+                        output_typedesc: output_typedesc.freeze(),
+                    }),
+            ])
+        }
+        ClientMessage::Execute1(_) => Reply::Send(vec![
+            ServerMessage::Data(Data {
+                data: vec![Bytes::from_static(b"ok")],
+            }),
+            ServerMessage::CommandComplete1(CommandComplete1 {
+                annotations: HashMap::new(),
+                capabilities: Capabilities::empty(),
+                status_data: Bytes::from_static(b"SELECT"),
+                state_typedesc_id: Uuid::from_u128(0),
+                state_data: Bytes::new(),
+            }),
+        ]),
+        ClientMessage::Sync => Reply::Send(vec![ready()]),
+        ClientMessage::Terminate => Reply::Close,
+        _ => Reply::Send(Vec::new()),
+    }
+}
+
+fn ready() -> ServerMessage {
+    ServerMessage::ReadyForCommand(ReadyForCommand {
+        headers: HashMap::new(),
+        transaction_state: TransactionState::NotInTransaction,
+    })
+}
+
+async fn serve(mut sock: UnixStream, index: usize, handler: Handler)
+    -> io::Result<()>
+{
+    let proto = ProtocolVersion::current();
+    let mut in_buf = BytesMut::with_capacity(8192);
+    let mut out_buf = BytesMut::with_capacity(8192);
+    loop {
+        while in_buf.len() < 5 ||
+            in_buf.len() < 1 + u32::from_be_bytes(
+                in_buf[1..5].try_into().unwrap()) as usize
+        {
+            if sock.read_buf(&mut in_buf).await? == 0 {
+                return Ok(());
+            }
+        }
+        let len = u32::from_be_bytes(in_buf[1..5].try_into().unwrap());
+        let frame = in_buf.split_to(1 + len as usize).freeze();
+        let msg = ClientMessage::decode(&mut Input::new(proto.clone(), frame))
+            .expect("client message is valid");
+        let reply = match handler(index, &msg) {
+            Reply::Close => Reply::Close,
+            _ if matches!(msg, ClientMessage::ClientHandshake(_)) => {
+                Reply::Send(vec![
+                    ServerMessage::Authentication(Authentication::Ok),
+                    ServerMessage::ServerKeyData(ServerKeyData {
+                        data: [index as u8; 32],
+                    }),
+                    ready(),
+                ])
+            }
+            reply => reply,
+        };
+        match reply {
+            Reply::Send(messages) => {
+                out_buf.truncate(0);
+                for msg in messages {
+                    msg.encode(&mut Output::new(&proto, &mut out_buf))
+                        .expect("server message can be encoded");
+                }
+                sock.write_all(&out_buf).await?;
+            }
+            Reply::Close => return Ok(()),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Detecting schema changes made by migrations
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+
+use crate::client::Client;
+use crate::errors::{Error, ErrorKind, ClientError};
+
+
+/// Watches the database for applied migrations
+///
+/// Created by [`Client::watch_schema`]. While the watcher is alive, a
+/// background task periodically fetches the [schema
+/// version](Client::schema_version) and, when it changes, closes pooled
+/// connections of the client, so that descriptors and session state
+/// codecs cached on connections are fetched anew. Application can also
+/// wait for changes using [`changed`](SchemaWatcher::changed) to reset its
+/// own caches.
+///
+/// Background task is stopped when the watcher is dropped.
+#[derive(Debug)]
+pub struct SchemaWatcher {
+    version: watch::Receiver<Option<String>>,
+    task: JoinHandle<()>,
+}
+
+impl SchemaWatcher {
+    pub(crate) async fn new(client: Client, interval: Duration)
+        -> Result<SchemaWatcher, Error>
+    {
+        let version = client.schema_version().await?;
+        let (tx, rx) = watch::channel(version.clone());
+        let task = tokio::spawn(poll(client, interval, version, tx));
+        Ok(SchemaWatcher {
+            version: rx,
+            task,
+        })
+    }
+    /// Last known schema version
+    ///
+    /// This is the name of the last applied migration, or `None` if the
+    /// database has no migrations.
+    pub fn version(&self) -> Option<String> {
+        self.version.borrow().clone()
+    }
+    /// Wait until schema version changes and return the new version
+    ///
+    /// By the time this method returns, the connection pool is already
+    /// reset.
+    pub async fn changed(&mut self) -> Result<Option<String>, Error> {
+        self.version.changed().await
+            .map_err(|_| ClientError::with_message(
+                "schema watcher task has stopped"))?;
+        Ok(self.version.borrow().clone())
+    }
+}
+
+impl Drop for SchemaWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn poll(client: Client, interval: Duration,
+              mut version: Option<String>,
+              tx: watch::Sender<Option<String>>)
+{
+    loop {
+        sleep(interval).await;
+        match client.schema_version().await {
+            Ok(new) if new != version => {
+                log::info!("Schema version changed from {:?} to {:?}, \
+                            resetting connections", version, new);
+                client.invalidate_connections();
+                version = new;
+                if tx.send(version.clone()).is_err() {
+                    return;
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                log::warn!("Error checking schema version: {:#}", e);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use edgedb_protocol::client_message::ClientMessage;
+    use edgedb_protocol::server_message::{ServerMessage, Data};
+
+    use crate::client::Client;
+    use crate::raw::test_server::{TestServer, Reply, default_reply};
+
+    #[tokio::test]
+    async fn migration() {
+        let version = Arc::new(Mutex::new("m1"));
+        let mut server = TestServer::new();
+        server.start_with(Arc::new({
+            let version = version.clone();
+            move |_, msg| match (msg, default_reply(msg)) {
+                // every query returns current schema version
+                (ClientMessage::Execute1(_), Reply::Send(mut messages)) => {
+                    let name = *version.lock().unwrap();
+                    messages[0] = ServerMessage::Data(Data {
+                        data: vec![Bytes::from_static(name.as_bytes())],
+                    });
+                    Reply::Send(messages)
+                }
+                (_, reply) => reply,
+            }
+        }));
+        let client = Client::new(&server.config());
+        let mut watcher = client.watch_schema(Duration::from_millis(10))
+            .await.unwrap();
+        assert_eq!(watcher.version().as_deref(), Some("m1"));
+        assert_eq!(server.accepted(), 1);
+
+        *version.lock().unwrap() = "m2";
+        let new = watcher.changed().await.unwrap();
+        assert_eq!(new.as_deref(), Some("m2"));
+        assert_eq!(watcher.version().as_deref(), Some("m2"));
+        // stop polling, so that only the query below can connect
+        drop(watcher);
+
+        // connection used by the watcher was closed on release
+        let value = client.query_required_single::<String, _>(
+            "SELECT 1", &()).await.unwrap();
+        assert_eq!(value, "m2");
+        assert_eq!(server.accepted(), 2);
+    }
+}
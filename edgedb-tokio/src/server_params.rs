@@ -45,3 +45,26 @@ impl Key for SystemConfig {
 
 impl SealedParam for SystemConfig { }
 impl ServerParam for SystemConfig { }
+
+
+/// Key data sent by the server after authentication
+///
+/// It identifies the session and is needed to cancel queries running on
+/// this connection out of band (i.e. from another connection). Treat it as
+/// a secret: anyone who has it can interrupt the session.
+#[derive(Clone)]
+pub struct ServerKeyData(pub [u8; 32]);
+
+impl Key for ServerKeyData {
+    type Value = ServerKeyData;
+}
+
+impl SealedParam for ServerKeyData { }
+impl ServerParam for ServerKeyData { }
+
+impl std::fmt::Debug for ServerKeyData {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // do not leak the key into logs
+        f.write_str("ServerKeyData(..)")
+    }
+}
@@ -0,0 +1,128 @@
+//! Session state (config settings and globals) sent along with every query
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use bytes::{Bytes, BytesMut};
+
+use edgedb_protocol::codec::Codec;
+use edgedb_protocol::common::Capabilities;
+use edgedb_protocol::features::ProtocolVersion;
+use edgedb_protocol::model::Uuid;
+use edgedb_protocol::server_message::StateDataDescription;
+use edgedb_protocol::value::{Value, SparseObject};
+
+use crate::errors::{Error, ErrorKind};
+use crate::errors::{ClientEncodingError, ProtocolEncodingError};
+
+
+/// Session state that is attached to a client
+///
+/// Only the parts that differ from the server defaults are stored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionState {
+    config: BTreeMap<String, Value>,
+    globals: BTreeMap<String, Value>,
+    /// Add a snapshot of this state to the errors of failed queries
+    snapshot_errors: bool,
+}
+
+/// Codec for the state as described by the server
+#[derive(Debug)]
+pub struct StateCodec {
+    pub typedesc_id: Uuid,
+    codec: Arc<dyn Codec>,
+}
+
+impl SessionState {
+    pub fn with_config(&self, name: &str, value: Value) -> SessionState {
+        let mut state = self.clone();
+        state.config.insert(name.into(), value);
+        state
+    }
+    /// Adds global values, names without a module refer to `default`
+    pub fn with_globals<K>(&self, globals: impl IntoIterator<Item=(K, Value)>)
+        -> SessionState
+        where K: AsRef<str>,
+    {
+        let mut state = self.clone();
+        for (name, value) in globals {
+            let name = name.as_ref();
+            let name = if name.contains("::") {
+                name.into()
+            } else {
+                format!("default::{}", name)
+            };
+            state.globals.insert(name, value);
+        }
+        state
+    }
+    pub fn with_error_snapshot(&self, enable: bool) -> SessionState {
+        SessionState {
+            snapshot_errors: enable,
+            ..self.clone()
+        }
+    }
+    /// Adds state snapshot to the error context if enabled
+    ///
+    /// Values of the globals are redacted, as they are often used for
+    /// user ids and similar data, only names are listed.
+    pub fn annotate(&self, err: Error, allowed: Capabilities,
+                    used: Option<Capabilities>)
+        -> Error
+    {
+        if !self.snapshot_errors {
+            return err;
+        }
+        let config = self.config.iter()
+            .map(|(name, value)| format!("{}: {:?}", name, value))
+            .collect::<Vec<_>>();
+        let globals = self.globals.keys()
+            .map(|name| format!("{}: <redacted>", name))
+            .collect::<Vec<_>>();
+        let mut buf = format!(
+            "session state: config {{{}}}; globals {{{}}}; \
+             allowed capabilities: {:?}",
+            config.join(", "), globals.join(", "), allowed);
+        if let Some(used) = used {
+            write!(&mut buf, "; query capabilities: {:?}", used).unwrap();
+        }
+        err.context(buf)
+    }
+    pub fn is_default(&self) -> bool {
+        self.config.is_empty() && self.globals.is_empty()
+    }
+    pub fn encode(&self, codec: &StateCodec) -> Result<Bytes, Error> {
+        let mut state = Vec::with_capacity(2);
+        if !self.config.is_empty() {
+            let config = SparseObject::from_pairs(
+                self.config.iter().map(|(k, v)| (k, Some(v.clone())))
+            );
+            state.push(("config", Some(Value::SparseObject(config))));
+        }
+        if !self.globals.is_empty() {
+            let globals = SparseObject::from_pairs(
+                self.globals.iter().map(|(k, v)| (k, Some(v.clone())))
+            );
+            state.push(("globals", Some(Value::SparseObject(globals))));
+        }
+        let state = SparseObject::from_pairs(state);
+        let mut buf = BytesMut::new();
+        codec.codec.encode(&mut buf, &Value::SparseObject(state))
+            .map_err(ClientEncodingError::with_source)?;
+        Ok(buf.freeze())
+    }
+}
+
+impl StateCodec {
+    pub fn new(desc: StateDataDescription, proto: &ProtocolVersion)
+        -> Result<StateCodec, Error>
+    {
+        let typedesc_id = desc.typedesc_id;
+        let codec = desc.parse(proto)
+            .map_err(ProtocolEncodingError::with_source)?
+            .build_codec()
+            .map_err(ProtocolEncodingError::with_source)?;
+        Ok(StateCodec { typedesc_id, codec })
+    }
+}
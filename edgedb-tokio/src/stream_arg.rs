@@ -0,0 +1,109 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use tokio::io::AsyncRead;
+
+use edgedb_protocol::descriptors::TypePos;
+use edgedb_protocol::query_arg::{QueryArg, ScalarArg, Encoder};
+use edgedb_protocol::query_arg::{DescriptorContext};
+
+use crate::errors::{Error, ErrorKind, ClientEncodingError};
+
+/// Query argument which data is streamed from an [`AsyncRead`]
+///
+/// The argument is sent directly from the reader to the connection, so the
+/// whole payload is never kept in memory. This is useful for storing large
+/// blobs. Length of the data must be known in advance: exactly `len` bytes
+/// are read, and the query fails (closing the connection) if the reader ends
+/// earlier.
+///
+/// ```rust,no_run
+/// # async fn main_(client: edgedb_tokio::Client)
+/// #     -> Result<(), Box<dyn std::error::Error>>
+/// # {
+/// use edgedb_tokio::StreamArg;
+///
+/// let file = tokio::fs::File::open("blob.bin").await?;
+/// let len = file.metadata().await?.len();
+/// client.query::<i64, _>(
+///     "SELECT len((INSERT Blob { data := <bytes>$0 }).data)",
+///     &(StreamArg::bytes(file, len)?,),
+/// ).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The reader is consumed when the query is sent, so the argument can only
+/// be used once. In particular, it must be created inside of the
+/// [transaction](crate::Client::transaction) body to make retries work.
+/// Data for [`str`](StreamArg::str) arguments is validated by the server.
+pub struct StreamArg<T> {
+    len: usize,
+    reader: Mutex<Option<ArgReader>>,
+    phantom: PhantomData<fn() -> T>,
+}
+
+/// Source of the external argument data as passed to the connection
+pub(crate) struct ArgReader(pub Box<dyn AsyncRead + Send + Unpin>);
+
+impl StreamArg<Bytes> {
+    /// Stream `len` bytes of `std::bytes` argument from the reader
+    pub fn bytes(reader: impl AsyncRead + Send + Unpin + 'static, len: u64)
+        -> Result<StreamArg<Bytes>, Error>
+    {
+        StreamArg::new(reader, len)
+    }
+}
+
+impl StreamArg<String> {
+    /// Stream `len` bytes of `std::str` argument from the reader
+    ///
+    /// Data must be valid UTF-8.
+    pub fn str(reader: impl AsyncRead + Send + Unpin + 'static, len: u64)
+        -> Result<StreamArg<String>, Error>
+    {
+        StreamArg::new(reader, len)
+    }
+}
+
+impl<T> StreamArg<T> {
+    fn new(reader: impl AsyncRead + Send + Unpin + 'static, len: u64)
+        -> Result<StreamArg<T>, Error>
+    {
+        let len = usize::try_from(len).ok()
+            .filter(|len| i32::try_from(*len).is_ok())
+            .ok_or_else(|| ClientEncodingError::with_message(format!(
+                "argument of {} bytes is too long", len)))?;
+        Ok(StreamArg {
+            len,
+            reader: Mutex::new(Some(ArgReader(Box::new(reader)))),
+            phantom: PhantomData,
+        })
+    }
+}
+
+impl<T: ScalarArg> QueryArg for StreamArg<T> {
+    fn encode_slot(&self, enc: &mut Encoder) -> Result<(), Error> {
+        let reader = self.reader.lock()
+            .expect("stream argument mutex is not poisoned")
+            .take()
+            .ok_or_else(|| ClientEncodingError::with_message(
+                "stream argument has already been sent"))?;
+        enc.external_slot(self.len, Box::new(reader))
+    }
+    fn check_descriptor(ctx: &DescriptorContext, pos: TypePos)
+        -> Result<(), Error>
+    {
+        T::check_descriptor(ctx, pos)
+    }
+}
+
+impl<T> fmt::Debug for StreamArg<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StreamArg")
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
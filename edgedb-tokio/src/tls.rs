@@ -1,5 +1,6 @@
+use std::fmt;
 use std::io;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
 
 use anyhow::Context;
@@ -10,6 +11,8 @@ use tls_api::{TlsConnectorBox};
 use tls_api_rustls::{TlsConnector};
 use webpki::{SignatureAlgorithm};
 
+use crate::errors::Error;
+
 
 static SIG_ALGS: &[&SignatureAlgorithm] = &[
     &webpki::ECDSA_P256_SHA256,
@@ -23,6 +26,27 @@ static SIG_ALGS: &[&SignatureAlgorithm] = &[
     &webpki::RSA_PKCS1_3072_8192_SHA384,
 ];
 
+/// Customizes TLS configuration of the connections
+///
+/// Invoked for every new connection, before the client installs its
+/// certificate verifier and ALPN protocols, so implementation may replace
+/// the whole `rustls` configuration. This can be used to restrict cipher
+/// suites or protocol versions, or to enable key logging. Certificate
+/// verification is always configured by the [`Builder`](crate::Builder)
+/// and is applied on top of it.
+///
+/// The configuration is passed as is, so the version of `rustls` (currently
+/// 0.20) is a part of the public API of this crate: upgrading it is a
+/// breaking change. Use the [`rustls`](crate::rustls) re-export to get the
+/// matching version.
+///
+/// Set it using [`Builder::tls_configure`](crate::Builder::tls_configure).
+pub trait TlsConfigure: fmt::Debug + Send + Sync {
+    /// Adjust client configuration before connecting
+    fn configure(&self, config: &mut rustls::ClientConfig)
+        -> Result<(), Error>;
+}
+
 pub struct NullVerifier;
 
 pub struct NoHostnameVerifier {
@@ -89,17 +113,72 @@ impl ServerCertVerifier for NullVerifier {
     }
 }
 
+/// Verifier that remembers the certificate of the verified server
+///
+/// Certificate is used for SCRAM channel binding.
+pub struct CertCapture {
+    inner: Arc<dyn ServerCertVerifier>,
+    cert: Arc<Mutex<Option<Certificate>>>,
+}
+
+impl ServerCertVerifier for CertCapture {
+    fn verify_server_cert(&self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(end_entity,
+            intermediates, server_name, scts, ocsp_response, now)?;
+        *self.cert.lock().expect("certificate mutex is not poisoned")
+            = Some(end_entity.clone());
+        Ok(verified)
+    }
+    fn request_scts(&self) -> bool {
+        self.inner.request_scts()
+    }
+}
+
+/// Create TLS connector
+///
+/// The certificate of the server is stored into `server_cert` on each
+/// successful handshake (unless the session is resumed).
 pub fn connector(
     cert_verifier: Arc<dyn ServerCertVerifier>,
+    server_cert: Arc<Mutex<Option<Certificate>>>,
+    configure: Option<&dyn TlsConfigure>,
 ) -> anyhow::Result<TlsConnectorBox>
 {
     let mut builder = TlsConnector::builder()?;
-    builder.config.dangerous().set_certificate_verifier(cert_verifier);
+    configure_client(&mut builder.config,
+                     cert_verifier, server_cert, configure)?;
     builder.set_alpn_protocols(&[b"edgedb-binary"])?;
     let connector = builder.build()?.into_dyn();
     Ok(connector)
 }
 
+/// Run the user hook, then install the certificate verifier on top of it
+fn configure_client(
+    config: &mut rustls::ClientConfig,
+    cert_verifier: Arc<dyn ServerCertVerifier>,
+    server_cert: Arc<Mutex<Option<Certificate>>>,
+    configure: Option<&dyn TlsConfigure>,
+) -> anyhow::Result<()>
+{
+    if let Some(configure) = configure {
+        configure.configure(config)?;
+    }
+    config.dangerous().set_certificate_verifier(Arc::new(
+        CertCapture {
+            inner: cert_verifier,
+            cert: server_cert,
+        }
+    ));
+    Ok(())
+}
+
 impl From<webpki::TrustAnchor<'_>> for OwnedTrustAnchor {
     fn from(src: webpki::TrustAnchor) -> OwnedTrustAnchor {
         OwnedTrustAnchor {
@@ -175,3 +254,107 @@ fn pki_error(error: webpki::Error) -> rustls::Error {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::SystemTime;
+
+    use rustls::client::{ServerCertVerifier, ServerCertVerified};
+    use rustls::{Certificate, PrivateKey, ServerName};
+    use rustls::{ClientConnection, ServerConnection};
+
+    use crate::errors::Error;
+    use super::{TlsConfigure, configure_client};
+
+    const CERT: &[u8] = include_bytes!("../tests/certs/sha384.der");
+    const KEY: &[u8] = include_bytes!("../tests/certs/sha384.key.der");
+
+    /// Accepts any certificate and counts calls
+    #[derive(Debug, Default)]
+    struct Counter(AtomicUsize);
+
+    impl ServerCertVerifier for Counter {
+        fn verify_server_cert(&self,
+            _end_entity: &Certificate,
+            _intermediates: &[Certificate],
+            _server_name: &ServerName,
+            _scts: &mut dyn Iterator<Item = &[u8]>,
+            _ocsp_response: &[u8],
+            _now: SystemTime
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(ServerCertVerified::assertion())
+        }
+    }
+
+    /// Hook that tries to install its own verifier
+    #[derive(Debug)]
+    struct Hook {
+        calls: AtomicUsize,
+        verifier: Arc<Counter>,
+    }
+
+    impl TlsConfigure for Hook {
+        fn configure(&self, config: &mut rustls::ClientConfig)
+            -> Result<(), Error>
+        {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            config.enable_sni = false;
+            config.dangerous().set_certificate_verifier(
+                self.verifier.clone());
+            Ok(())
+        }
+    }
+
+    fn handshake(client: &mut ClientConnection, server: &mut ServerConnection)
+    {
+        while client.is_handshaking() || server.is_handshaking() {
+            let mut buf = Vec::new();
+            client.write_tls(&mut buf).unwrap();
+            server.read_tls(&mut &buf[..]).unwrap();
+            server.process_new_packets().unwrap();
+            let mut buf = Vec::new();
+            server.write_tls(&mut buf).unwrap();
+            client.read_tls(&mut &buf[..]).unwrap();
+            client.process_new_packets().unwrap();
+        }
+    }
+
+    #[test]
+    fn configure_hook() {
+        let hook = Hook {
+            calls: AtomicUsize::new(0),
+            verifier: Arc::new(Counter::default()),
+        };
+        let verifier = Arc::new(Counter::default());
+        let server_cert = Arc::new(Mutex::new(None));
+        let mut config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(rustls::RootCertStore::empty())
+            .with_no_client_auth();
+        configure_client(&mut config, verifier.clone(), server_cert.clone(),
+                         Some(&hook)).unwrap();
+        assert_eq!(hook.calls.load(Ordering::SeqCst), 1);
+        assert!(!config.enable_sni);
+
+        let server_config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(vec![Certificate(CERT.to_vec())],
+                              PrivateKey(KEY.to_vec()))
+            .unwrap();
+        let mut client = ClientConnection::new(Arc::new(config),
+            "localhost".try_into().unwrap()).unwrap();
+        let mut server = ServerConnection::new(Arc::new(server_config))
+            .unwrap();
+        handshake(&mut client, &mut server);
+
+        // verifier configured by the client replaces the one of the hook
+        assert_eq!(verifier.0.load(Ordering::SeqCst), 1);
+        assert_eq!(hook.verifier.0.load(Ordering::SeqCst), 0);
+        assert_eq!(server_cert.lock().unwrap().as_ref().map(|c| &c.0[..]),
+                   Some(CERT));
+    }
+}
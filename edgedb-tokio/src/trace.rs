@@ -0,0 +1,199 @@
+//! Tracing of connection establishment
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::errors::Error;
+
+
+/// Stage of establishing a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ConnectStage {
+    /// Resolving host name into addresses
+    Dns,
+    /// Establishing TCP (or Unix socket) connection
+    Tcp,
+    /// TLS handshake, including ALPN check
+    Tls,
+    /// Protocol version negotiation
+    Handshake,
+    /// Authentication (including SCRAM exchange if needed)
+    Authentication,
+    /// Receiving server parameters until the connection is ready for queries
+    Ready,
+}
+
+/// Single step of connection establishment
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct TraceStep {
+    /// Connection attempt, zero-based
+    ///
+    /// Attempts are repeated on temporary errors until the
+    /// [`wait_until_available`](crate::Builder::wait_until_available) time is
+    /// elapsed.
+    pub attempt: u32,
+    /// Stage of the connection
+    pub stage: ConnectStage,
+    /// Time since connection establishment was started
+    pub start: Duration,
+    /// Time spent in this step
+    pub duration: Duration,
+    /// Error message, if the step has failed
+    pub error: Option<String>,
+}
+
+/// Trace of a single connection establishment
+///
+/// Enabled with [`Builder::trace_connection`](crate::Builder::trace_connection)
+/// and can be fetched using
+/// [`Client::last_connection_trace`](crate::Client::last_connection_trace).
+/// Steps are also logged at debug level as they finish (to `tracing` if the
+/// `tracing` feature is enabled, or to `log` otherwise).
+#[derive(Debug, Clone)]
+pub struct ConnectionTrace {
+    steps: Vec<TraceStep>,
+    total: Duration,
+}
+
+/// Progress of waiting for the server to become available
+///
+/// Passed to the callback set by
+/// [`Builder::on_connect_progress`](crate::Builder::on_connect_progress)
+/// after each failed connection attempt that is going to be retried.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ConnectProgress<'a> {
+    /// Number of failed attempts so far
+    pub attempts: u32,
+    /// Error of the latest attempt
+    pub last_error: &'a Error,
+    /// Time since connection establishment was started
+    pub elapsed: Duration,
+    /// Time left until the client gives up, as configured by
+    /// [`wait_until_available`](crate::Builder::wait_until_available)
+    pub remaining: Duration,
+}
+
+#[derive(Clone)]
+pub(crate) struct ProgressCallback(
+    pub Arc<dyn Fn(&ConnectProgress) + Send + Sync>,
+);
+
+#[derive(Debug)]
+pub(crate) struct Tracer {
+    enabled: bool,
+    start: Instant,
+    attempt: u32,
+    current: Option<(ConnectStage, Instant)>,
+    steps: Vec<TraceStep>,
+}
+
+impl ConnectionTrace {
+    /// Steps of all attempts in order of execution
+    pub fn steps(&self) -> &[TraceStep] {
+        &self.steps
+    }
+    /// Total time spent on establishing the connection
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+    /// Returns `true` if the connection has been established
+    pub fn is_success(&self) -> bool {
+        self.steps.last().map(|s| s.error.is_none()).unwrap_or(false)
+    }
+}
+
+impl fmt::Display for ConnectionTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for step in &self.steps {
+            write!(f, "#{} {:>10.3?} {:?}: {:.3?}",
+                   step.attempt, step.start, step.stage, step.duration)?;
+            if let Some(error) = &step.error {
+                write!(f, " failed: {}", error)?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "total: {:.3?}", self.total)
+    }
+}
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ProgressCallback(..)")
+    }
+}
+
+impl Tracer {
+    pub fn new(enabled: bool) -> Tracer {
+        Tracer {
+            enabled,
+            start: Instant::now(),
+            attempt: 0,
+            current: None,
+            steps: Vec::new(),
+        }
+    }
+    /// Finishes current step (if any) and starts the new one
+    pub fn stage(&mut self, stage: ConnectStage) {
+        if !self.enabled {
+            return;
+        }
+        self.finish_step(None);
+        self.current = Some((stage, Instant::now()));
+    }
+    /// Finishes current step successfully
+    pub fn done(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        self.finish_step(None);
+    }
+    /// Finishes current step with an error
+    pub fn fail(&mut self, error: &Error) {
+        if !self.enabled {
+            return;
+        }
+        self.finish_step(Some(format!("{:#}", error)));
+    }
+    pub fn next_attempt(&mut self) {
+        self.attempt += 1;
+    }
+    pub fn finish(self) -> Option<ConnectionTrace> {
+        if !self.enabled {
+            return None;
+        }
+        Some(ConnectionTrace {
+            steps: self.steps,
+            total: self.start.elapsed(),
+        })
+    }
+    fn finish_step(&mut self, error: Option<String>) {
+        let (stage, started) = match self.current.take() {
+            Some(current) => current,
+            None => return,
+        };
+        let step = TraceStep {
+            attempt: self.attempt,
+            stage,
+            start: started.duration_since(self.start),
+            duration: started.elapsed(),
+            error,
+        };
+        #[cfg(feature="tracing")]
+        tracing::debug!(
+            attempt=step.attempt,
+            stage=?step.stage,
+            start=?step.start,
+            duration=?step.duration,
+            error=step.error.as_deref(),
+            "connection step finished");
+        #[cfg(not(feature="tracing"))]
+        log::debug!("Connection attempt #{} step {:?} took {:?}{}",
+            step.attempt, step.stage, step.duration,
+            step.error.as_ref().map(|e| format!(", failed: {}", e))
+                .unwrap_or_default());
+        self.steps.push(step);
+    }
+}
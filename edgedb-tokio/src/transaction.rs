@@ -1,18 +1,21 @@
 use std::future::Future;
+use std::sync::Arc;
 
-use bytes::BytesMut;
-use edgedb_protocol::QueryResult;
+use edgedb_protocol::{QueryResult, WithId};
 use edgedb_protocol::common::CompilationOptions;
 use edgedb_protocol::common::{IoFormat, Capabilities, Cardinality};
-use edgedb_protocol::model::Json;
-use edgedb_protocol::query_arg::{QueryArgs, Encoder};
+use edgedb_protocol::model::{Json, Uuid};
+use edgedb_protocol::query_arg::QueryArgs;
 use tokio::sync::oneshot;
 use tokio::time::sleep;
 
+use crate::client::encode_streamed_arguments;
 use crate::errors::{ClientError};
 use crate::errors::{Error, ErrorKind, SHOULD_RETRY};
 use crate::errors::{ProtocolEncodingError, NoResultExpected, NoDataError};
+use crate::errors::{ResultCardinalityMismatchError};
 use crate::raw::{Pool, Connection, Options};
+use crate::state::SessionState;
 
 
 /// Transaction object passed to the closure via
@@ -22,6 +25,8 @@ use crate::raw::{Pool, Connection, Options};
 ///
 /// All database queries in transaction should be executed using methods on
 /// this object instead of using original [`Client`](crate::Client) instance.
+/// Queries made on the original client within the transaction closure fail
+/// with [`InterfaceError`](crate::errors::InterfaceError).
 #[derive(Debug)]
 pub struct Transaction {
     iteration: u32,
@@ -38,6 +43,8 @@ pub struct TransactionResult {
 pub struct Inner {
     started: bool,
     conn: Connection,
+    state: Arc<SessionState>,
+    unknown_scalars: bool,
     return_conn: oneshot::Sender<TransactionResult>,
 }
 
@@ -46,7 +53,7 @@ impl Assert for Transaction {}
 
 impl Drop for Transaction {
     fn drop(&mut self) {
-        self.inner.take().map(|Inner { started, conn, return_conn }| {
+        self.inner.take().map(|Inner { started, conn, return_conn, .. }| {
             return_conn.send(TransactionResult {
                 started,
                 conn,
@@ -70,10 +77,12 @@ pub(crate) async fn transaction<T, B, F>(pool: &Pool, options: &Options,
             inner: Some(Inner {
                 started: false,
                 conn,
+                state: options.state.clone(),
+                unknown_scalars: options.unknown_scalars,
                 return_conn: tx,
             })
         };
-        let result = body(tran).await;
+        let result = pool.in_transaction(body(tran)).await;
         let TransactionResult { mut conn, started } =
             rx.try_recv().expect("Transaction object must \
             be dropped by the time transaction body finishes.");
@@ -81,14 +90,14 @@ pub(crate) async fn transaction<T, B, F>(pool: &Pool, options: &Options,
             Ok(val) => {
                 log::debug!("Comitting transaction");
                 if started {
-                    conn.statement("COMMIT").await?;
+                    conn.statement("COMMIT", &options.state).await?;
                 }
                 return Ok(val)
             }
             Err(e) => {
                 log::debug!("Rolling back transaction on error");
                 if started {
-                    conn.statement("ROLLBACK").await?;
+                    conn.statement("ROLLBACK", &options.state).await?;
                 }
                 for e in e.chain() {
                     if let Some(e) = e.downcast_ref::<Error>() {
@@ -98,7 +107,11 @@ pub(crate) async fn transaction<T, B, F>(pool: &Pool, options: &Options,
                                 log::info!("Retrying transaction on {:#}",
                                            e);
                                 iteration += 1;
-                                sleep((rule.backoff)(iteration)).await;
+                                let backoff = rule.delay(
+                                    iteration, &*pool.entropy());
+                                options.retry.notify_retry(
+                                    iteration, e, backoff);
+                                sleep(backoff).await;
                                 continue 'transaction;
                             }
                         }
@@ -121,7 +134,8 @@ impl Transaction {
     async fn ensure_started(&mut self) -> anyhow::Result<(), Error> {
         if let Some(inner) = &mut self.inner {
             if !inner.started {
-                inner.conn.statement("START TRANSACTION").await?;
+                inner.conn.statement("START TRANSACTION", &inner.state)
+                    .await?;
                 inner.started = true;
             }
             return Ok(());
@@ -160,24 +174,22 @@ impl Transaction {
             io_format: IoFormat::Binary,
             expected_cardinality: Cardinality::Many,
         };
-        let ref mut conn = self.inner().conn;
-        let desc = conn.parse(&flags, query).await?;
-        let inp_desc = desc.input()
-            .map_err(ProtocolEncodingError::with_source)?;
-
-        let mut arg_buf = BytesMut::with_capacity(8);
-        arguments.encode(&mut Encoder::new(
-            &inp_desc.as_query_arg_context(),
-            &mut arg_buf,
-        ))?;
+        let Inner {
+            ref mut conn, ref state, unknown_scalars, ..
+        } = *self.inner();
+        let desc = conn.parse(&flags, query, state).await?;
+        let (arguments, external) =
+            encode_streamed_arguments(&desc, arguments)?;
 
-        let data = conn.execute(&flags, query, &desc, &arg_buf.freeze()).await?;
+        let data = conn.execute_external(&flags, query, state,
+            &desc, &arguments, external).await?.data;
 
         let out_desc = desc.output()
             .map_err(ProtocolEncodingError::with_source)?;
         match out_desc.root_pos() {
             Some(root_pos) => {
-                let ctx = out_desc.as_queryable_context();
+                let mut ctx = out_desc.as_queryable_context();
+                ctx.unknown_scalars = unknown_scalars;
                 let mut state = R::prepare(&ctx, root_pos)?;
                 let rows = data.into_iter()
                     .flat_map(|chunk| chunk.data)
@@ -185,8 +197,27 @@ impl Transaction {
                     .collect::<Result<_, _>>()?;
                 Ok(rows)
             }
-            None => Err(NoResultExpected::build()),
+            None => Err(NoResultExpected::with_message(
+                "query doesn't return any data")),
+        }
+    }
+
+    /// Execute a query and return a non-empty collection of results
+    ///
+    /// This is similar to [`query()`](Self::query), but if the query returns
+    /// an empty set, a [`NoDataError`][crate::errors::NoDataError] is raised.
+    /// This is useful for queries of `AtLeastOne` cardinality.
+    pub async fn query_required<R, A>(&mut self, query: &str, arguments: &A)
+        -> Result<Vec<R>, Error>
+        where A: QueryArgs,
+              R: QueryResult,
+    {
+        let rows = self.query(query, arguments).await?;
+        if rows.is_empty() {
+            return Err(NoDataError::with_message(
+                "query returned zero results"));
         }
+        Ok(rows)
     }
 
     /// Execute a query and return a single result
@@ -220,34 +251,37 @@ impl Transaction {
             io_format: IoFormat::Binary,
             expected_cardinality: Cardinality::AtMostOne,
         };
-        let ref mut conn = self.inner().conn;
-        let desc = conn.parse(&flags, query).await?;
-        let inp_desc = desc.input()
-            .map_err(ProtocolEncodingError::with_source)?;
-
-        let mut arg_buf = BytesMut::with_capacity(8);
-        arguments.encode(&mut Encoder::new(
-            &inp_desc.as_query_arg_context(),
-            &mut arg_buf,
-        ))?;
+        let Inner {
+            ref mut conn, ref state, unknown_scalars, ..
+        } = *self.inner();
+        let desc = conn.parse(&flags, query, state).await?;
+        let (arguments, external) =
+            encode_streamed_arguments(&desc, arguments)?;
 
-        let data = conn.execute(&flags, query, &desc, &arg_buf.freeze()).await?;
+        let data = conn.execute_external(&flags, query, state,
+            &desc, &arguments, external).await?.data;
 
         let out_desc = desc.output()
             .map_err(ProtocolEncodingError::with_source)?;
         match out_desc.root_pos() {
             Some(root_pos) => {
-                let ctx = out_desc.as_queryable_context();
+                let mut ctx = out_desc.as_queryable_context();
+                ctx.unknown_scalars = unknown_scalars;
                 let mut state = R::prepare(&ctx, root_pos)?;
-                let bytes = data.into_iter().next()
-                    .and_then(|chunk| chunk.data.into_iter().next());
+                let mut rows = data.into_iter().flat_map(|chunk| chunk.data);
+                let bytes = rows.next();
+                if rows.next().is_some() {
+                    return Err(ResultCardinalityMismatchError::with_message(
+                        "expected at most one row, but query returned more"));
+                }
                 if let Some(bytes) = bytes {
                     Ok(Some(R::decode(&mut state, &bytes)?))
                 } else {
                     Ok(None)
                 }
             }
-            None => Err(NoResultExpected::build()),
+            None => Err(NoResultExpected::with_message(
+                "query doesn't return any data")),
         }
     }
 
@@ -286,6 +320,60 @@ impl Transaction {
                         "query row returned zero results"))
     }
 
+    /// Execute a query and collect results into a map
+    ///
+    /// Every row returned by the query must be a two-element tuple: the
+    /// first element is used as a key and the second one as a value. The
+    /// result can be collected into any [`FromIterator`] of pairs, such as
+    /// [`HashMap`](std::collections::HashMap) or
+    /// [`BTreeMap`](std::collections::BTreeMap):
+    ///
+    /// ```rust,ignore
+    /// let names: HashMap<Uuid, String> = tx.query_map(
+    ///     "SELECT (User.id, User.name)",
+    ///     &(),
+    /// ).await?;
+    /// ```
+    ///
+    /// Values can be objects too, as long as the value type derives
+    /// `Queryable`. Use [`query_map_by_id`](Self::query_map_by_id) to key
+    /// objects by their `id` without wrapping them into a tuple.
+    ///
+    /// If the same key is returned multiple times, the last value wins.
+    pub async fn query_map<K, V, M, A>(&mut self, query: &str, arguments: &A)
+        -> Result<M, Error>
+        where A: QueryArgs,
+              (K, V): QueryResult,
+              M: FromIterator<(K, V)>,
+    {
+        let rows = self.query::<(K, V), _>(query, arguments).await?;
+        Ok(rows.into_iter().collect())
+    }
+
+    /// Execute a query returning objects and collect them into a map keyed
+    /// by object `id`
+    ///
+    /// The `id` must be selected in the shape. The object (including the
+    /// `id`) is decoded into `V`, so a `V` deriving `Queryable` must have an
+    /// `id` field as well:
+    ///
+    /// ```rust,ignore
+    /// let users: HashMap<Uuid, User> = tx.query_map_by_id(
+    ///     "SELECT User { id, name, age }",
+    ///     &(),
+    /// ).await?;
+    /// ```
+    pub async fn query_map_by_id<V, M, A>(&mut self,
+                                          query: &str, arguments: &A)
+        -> Result<M, Error>
+        where A: QueryArgs,
+              V: QueryResult,
+              M: FromIterator<(Uuid, V)>,
+    {
+        let rows = self.query::<WithId<V>, _>(query, arguments).await?;
+        Ok(rows.into_iter().map(|row| (row.id, row.value)).collect())
+    }
+
     /// Execute a query and return the result as JSON.
     pub async fn query_json(&mut self, query: &str, arguments: &impl QueryArgs)
         -> Result<Json, Error>
@@ -300,18 +388,13 @@ impl Transaction {
             io_format: IoFormat::Json,
             expected_cardinality: Cardinality::Many,
         };
-        let ref mut conn = self.inner().conn;
-        let desc = conn.parse(&flags, query).await?;
-        let inp_desc = desc.input()
-            .map_err(ProtocolEncodingError::with_source)?;
-
-        let mut arg_buf = BytesMut::with_capacity(8);
-        arguments.encode(&mut Encoder::new(
-            &inp_desc.as_query_arg_context(),
-            &mut arg_buf,
-        ))?;
+        let Inner { ref mut conn, ref state, .. } = *self.inner();
+        let desc = conn.parse(&flags, query, state).await?;
+        let (arguments, external) =
+            encode_streamed_arguments(&desc, arguments)?;
 
-        let data = conn.execute(&flags, query, &desc, &arg_buf.freeze()).await?;
+        let data = conn.execute_external(&flags, query, state,
+            &desc, &arguments, external).await?.data;
 
         let out_desc = desc.output()
             .map_err(ProtocolEncodingError::with_source)?;
@@ -331,7 +414,8 @@ impl Transaction {
                         "query row returned zero results"))
                 }
             }
-            None => Err(NoResultExpected::build()),
+            None => Err(NoResultExpected::with_message(
+                "query doesn't return any data")),
         }
     }
 
@@ -355,18 +439,13 @@ impl Transaction {
             io_format: IoFormat::Json,
             expected_cardinality: Cardinality::AtMostOne,
         };
-        let ref mut conn = self.inner().conn;
-        let desc = conn.parse(&flags, query).await?;
-        let inp_desc = desc.input()
-            .map_err(ProtocolEncodingError::with_source)?;
-
-        let mut arg_buf = BytesMut::with_capacity(8);
-        arguments.encode(&mut Encoder::new(
-            &inp_desc.as_query_arg_context(),
-            &mut arg_buf,
-        ))?;
+        let Inner { ref mut conn, ref state, .. } = *self.inner();
+        let desc = conn.parse(&flags, query, state).await?;
+        let (arguments, external) =
+            encode_streamed_arguments(&desc, arguments)?;
 
-        let data = conn.execute(&flags, query, &desc, &arg_buf.freeze()).await?;
+        let data = conn.execute_external(&flags, query, state,
+            &desc, &arguments, external).await?.data;
 
         let out_desc = desc.output()
             .map_err(ProtocolEncodingError::with_source)?;
@@ -375,8 +454,12 @@ impl Transaction {
                 let ctx = out_desc.as_queryable_context();
                 // JSON objects are returned as strings :(
                 let mut state = String::prepare(&ctx, root_pos)?;
-                let bytes = data.into_iter().next()
-                    .and_then(|chunk| chunk.data.into_iter().next());
+                let mut rows = data.into_iter().flat_map(|chunk| chunk.data);
+                let bytes = rows.next();
+                if rows.next().is_some() {
+                    return Err(ResultCardinalityMismatchError::with_message(
+                        "expected at most one row, but query returned more"));
+                }
                 if let Some(bytes) = bytes {
                     // we trust database to produce valid json
                     let s = String::decode(&mut state, &bytes)?;
@@ -385,7 +468,8 @@ impl Transaction {
                     Ok(None)
                 }
             }
-            None => Err(NoResultExpected::build()),
+            None => Err(NoResultExpected::with_message(
+                "query doesn't return any data")),
         }
     }
 
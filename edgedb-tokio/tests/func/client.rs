@@ -1,5 +1,16 @@
-use edgedb_tokio::Client;
-use edgedb_errors::NoDataError;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Cursor;
+use std::time::Duration;
+
+use bytes::Bytes;
+use edgedb_protocol::common::Cardinality;
+use edgedb_protocol::features::ProtocolVersion;
+use edgedb_protocol::model::Uuid;
+use edgedb_protocol::value::Value;
+use edgedb_tokio::{Builder, Client, StreamArg, OfflineQueue};
+use edgedb_errors::{NoDataError, ResultCardinalityMismatchError};
+use edgedb_errors::{QueryTimeoutError, ClientEncodingError};
+use edgedb_errors::{ClientConnectionError, DescriptorMismatch};
 use futures_util::stream::{self, StreamExt};
 
 use crate::server::SERVER;
@@ -62,3 +73,337 @@ async fn parallel_queries() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn describe_queries() -> anyhow::Result<()> {
+    let client = Client::new(&SERVER.config);
+    client.ensure_connected().await?;
+
+    let descs = client.describe_queries(&[
+        "SELECT <int64>$0",
+        "SELECT 'hello'",
+        "SELECT {1, 2, 3}",
+    ]).await?;
+    assert_eq!(descs.len(), 3);
+    assert!(!descs[0].input()?.is_empty_tuple());
+    assert!(descs[1].input()?.is_empty_tuple());
+    assert!(descs[2].output()?.root_pos().is_some());
+
+    let err = client.describe_queries(&[
+        "SELECT 1",
+        "SELECT nonexistent_function()",
+    ]).await.unwrap_err();
+    assert_eq!(err.contexts().next(), Some("query #1"));
+
+    // connection is still usable after an error in the batch
+    let value = client.query_required_single::<i64, _>(
+        "SELECT 5*11", &()).await?;
+    assert_eq!(value, 55);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn protocol_version() -> anyhow::Result<()> {
+    let client = Client::new(&SERVER.config);
+    assert_eq!(client.protocol_version().await?, ProtocolVersion::current());
+    Ok(())
+}
+
+#[tokio::test]
+async fn bytes() -> anyhow::Result<()> {
+    let client = Client::new(&SERVER.config);
+
+    let value = client.query_required_single::<Vec<u8>, _>(
+        "SELECT b'hello'", &()).await?;
+    assert_eq!(value, b"hello");
+
+    let value = client.query_required_single::<Bytes, _>(
+        "SELECT <bytes>$0 ++ b'!'", &(Bytes::from_static(b"hello"),)).await?;
+    assert_eq!(&value[..], b"hello!");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn query_map() -> anyhow::Result<()> {
+    let client = Client::new(&SERVER.config);
+
+    let map: HashMap<String, i64> = client.query_map(
+        "SELECT {('a', 1), ('b', 2), ('c', 3)}", &()).await?;
+    assert_eq!(map.len(), 3);
+    assert_eq!(map["b"], 2);
+
+    let map: BTreeMap<i64, String> = client.query_map(
+        "SELECT {(2, 'two'), (1, 'one')}", &()).await?;
+    assert_eq!(map.into_iter().collect::<Vec<_>>(), vec![
+        (1, "one".into()),
+        (2, "two".into()),
+    ]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn query_map_by_id() -> anyhow::Result<()> {
+    let client = Client::new(&SERVER.config);
+
+    let map: HashMap<Uuid, Value> = client.query_map_by_id(
+        "SELECT schema::ScalarType { id, name }
+         FILTER .name IN {'std::str', 'std::int64'}", &()).await?;
+    assert_eq!(map.len(), 2);
+    let id: Uuid = client.query_required_single(
+        "SELECT (SELECT schema::ScalarType FILTER .name = 'std::str').id",
+        &()).await?;
+    assert!(map.contains_key(&id));
+
+    let err = client.query_map_by_id::<Value, HashMap<_, _>, _>(
+        "SELECT { name := 'x' }", &()).await.unwrap_err();
+    assert!(err.is::<DescriptorMismatch>());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn query_counted() -> anyhow::Result<()> {
+    let client = Client::new(&SERVER.config);
+
+    let (page, total) = client.query_counted::<i64, _, _>(
+        "WITH items := range_unpack(range(0, 100)) \
+         SELECT items ORDER BY items OFFSET <int64>$0 LIMIT 3",
+        &(10_i64,),
+        "SELECT count(range_unpack(range(0, 100)))",
+        &(),
+    ).await?;
+    assert_eq!(page, vec![10, 11, 12]);
+    assert_eq!(total, 100);
+
+    let err = client.query_counted::<i64, _, _>(
+        "SELECT 1", &(),
+        "SELECT 'not a number'", &(),
+    ).await.unwrap_err();
+    assert!(err.is::<edgedb_errors::DescriptorMismatch>());
+
+    let err = client.query_counted::<i64, _, _>(
+        "SELECT 1", &(),
+        "SELECT nonexistent_function()", &(),
+    ).await.unwrap_err();
+    assert_eq!(err.contexts().next(), Some("query #1"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cardinality() -> anyhow::Result<()> {
+    let client = Client::new(&SERVER.config);
+
+    let value = client.query_required::<i64, _>(
+        "SELECT {1, 2}", &()).await?;
+    assert_eq!(value, vec![1, 2]);
+
+    let err = client.query_required::<i64, _>(
+        "SELECT <int64>{}", &()).await.unwrap_err();
+    assert!(err.is::<NoDataError>());
+
+    let err = client.query_single::<i64, _>(
+        "SELECT {1, 2}", &()).await.unwrap_err();
+    assert!(err.is::<ResultCardinalityMismatchError>());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn query_timeout() -> anyhow::Result<()> {
+    let client = Client::new(&SERVER.config);
+    let limited = client.with_query_timeout(Duration::from_millis(100));
+
+    let err = limited.query::<i64, _>(
+        "SELECT count(sys::_sleep(1))", &()).await.unwrap_err();
+    assert!(err.is::<QueryTimeoutError>(), "{err:#}");
+
+    // original client is unaffected
+    let value = client.query_single::<i64, _>(
+        "SELECT count(sys::_sleep(0.2))", &()).await?;
+    assert_eq!(value, Some(1));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reload_config() -> anyhow::Result<()> {
+    let offline = Builder::uninitialized()
+        .host_port(Some("127.0.0.1"), Some(1))
+        .wait_until_available(Duration::from_secs(0))
+        .build()?;
+    let client = Client::new(&SERVER.config);
+    let tx_client = client.clone();
+    let value = tx_client.transaction(|mut tx| {
+        let client = client.clone();
+        let offline = offline.clone();
+        async move {
+            // connection used by the transaction is closed on release
+            client.reload_config(&offline);
+            tx.query_required_single::<i64, _>("SELECT 7", &()).await
+        }
+    }).await?;
+    assert_eq!(value, 7);
+
+    // connection of the old configuration is not reused
+    let err = client.query_required_single::<i64, _>(
+        "SELECT 8", &()).await.unwrap_err();
+    assert!(err.is::<ClientConnectionError>(), "{err:#}");
+
+    client.reload_config(&SERVER.config);
+    let value = client.query_required_single::<i64, _>(
+        "SELECT 8", &()).await?;
+    assert_eq!(value, 8);
+    Ok(())
+}
+
+#[tokio::test]
+async fn schema_version() -> anyhow::Result<()> {
+    let client = Client::new(&SERVER.config);
+    let version = client.schema_version().await?;
+    assert!(version.is_some());
+    let watcher = client.watch_schema(Duration::from_millis(10)).await?;
+    assert_eq!(watcher.version(), version);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(watcher.version(), version);
+    let value = client.query_required_single::<i64, _>(
+        "SELECT 9", &()).await?;
+    assert_eq!(value, 9);
+    Ok(())
+}
+
+#[tokio::test]
+async fn offline_queue() -> anyhow::Result<()> {
+    let offline = Builder::uninitialized()
+        .host_port(Some("127.0.0.1"), Some(1))
+        .wait_until_available(Duration::from_secs(0))
+        .build()?;
+    let client = Client::new(&offline).with_offline_queue(
+        OfflineQueue::default()
+            .ttl(Duration::from_millis(300))
+            .probe_interval(Duration::from_millis(50)));
+
+    // reads fail immediately
+    let err = client.query::<i64, _>("SELECT 1", &()).await.unwrap_err();
+    assert!(err.is::<ClientConnectionError>(), "{err:#}");
+    assert!(!format!("{err:#}").contains("offline queue"), "{err:#}");
+
+    let err = client.query::<i64, _>(
+        "SELECT count((INSERT test::Counter { name := 'offline' }))", &()
+    ).await.unwrap_err();
+    assert!(err.is::<ClientConnectionError>(), "{err:#}");
+    assert!(format!("{err:#}").contains("expired in the offline queue"));
+
+    // queued query is sent when the server becomes reachable
+    let client = Client::new(&offline).with_offline_queue(
+        OfflineQueue::default()
+            .reads(true)
+            .probe_interval(Duration::from_millis(50)));
+    let reload = tokio::spawn({
+        let client = client.clone();
+        async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            client.reload_config(&SERVER.config);
+        }
+    });
+    let value = client.query_required_single::<i64, _>(
+        "SELECT 7", &()).await?;
+    assert_eq!(value, 7);
+    reload.await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn globals() -> anyhow::Result<()> {
+    let client = Client::new(&SERVER.config);
+
+    let value = client.query_single::<String, _>(
+        "SELECT global current_user", &()).await?;
+    assert_eq!(value, None);
+
+    let alice = client.with_globals([
+        ("current_user", Value::Str("alice".into())),
+    ]);
+    let value = alice.query_single::<String, _>(
+        "SELECT global current_user", &()).await?;
+    assert_eq!(value.as_deref(), Some("alice"));
+
+    let bob = alice.with_globals([
+        ("default::current_user", Value::Str("bob".into())),
+    ]);
+    let value = bob.query_single::<String, _>(
+        "SELECT global current_user", &()).await?;
+    assert_eq!(value.as_deref(), Some("bob"));
+
+    // original client is not affected
+    let value = client.query_single::<String, _>(
+        "SELECT global current_user", &()).await?;
+    assert_eq!(value, None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn error_state_snapshot() -> anyhow::Result<()> {
+    let client = Client::new(&SERVER.config)
+        .with_globals([("current_user", Value::Str("alice".into()))]);
+
+    let err = client.query::<i64, _>("SELECT 1/0", &()).await.unwrap_err();
+    assert_eq!(err.contexts().count(), 0);
+
+    let client = client.with_error_state_snapshot(true);
+    let err = client.query::<i64, _>("SELECT 1/0", &()).await.unwrap_err();
+    let context = err.contexts().collect::<Vec<_>>().join("\n");
+    assert!(context.contains("default::current_user: <redacted>"),
+            "{}", context);
+    assert!(context.contains("allowed capabilities: MODIFICATIONS"),
+            "{}", context);
+    assert!(!context.contains("alice"), "{}", context);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn query_with_metadata() -> anyhow::Result<()> {
+    let client = Client::new(&SERVER.config);
+    let (rows, meta) = client.query_with_metadata::<i64, _>(
+        "SELECT {1, 2}", &()).await?;
+    assert_eq!(rows, vec![1, 2]);
+    assert_eq!(meta.status, "SELECT");
+    assert_eq!(meta.cardinality, Cardinality::AtLeastOne);
+    assert!(meta.capabilities.is_empty());
+    assert_eq!(meta.output_typedesc_id,
+               "00000000-0000-0000-0000-000000000105".parse::<Uuid>()?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn stream_arg() -> anyhow::Result<()> {
+    let client = Client::new(&SERVER.config);
+    let data = vec![b'x'; 3_000_000];
+    let len = client.query_required_single::<i64, _>(
+        "SELECT len(<bytes>$0)",
+        &(StreamArg::bytes(Cursor::new(data.clone()), 3_000_000)?,),
+    ).await?;
+    assert_eq!(len, 3_000_000);
+    let len = client.query_required_single::<i64, _>(
+        "SELECT len(<str>$0) + <int64>$1",
+        &(StreamArg::str(Cursor::new(data.clone()), 3_000_000)?, 1i64),
+    ).await?;
+    assert_eq!(len, 3_000_001);
+
+    let err = client.query_required_single::<i64, _>(
+        "SELECT len(<bytes>$0)",
+        &(StreamArg::bytes(Cursor::new(data), 3_000_001)?,),
+    ).await.unwrap_err();
+    assert!(err.is::<ClientEncodingError>());
+    // failed connection is not returned to the pool
+    let value = client.query_required_single::<i64, _>(
+        "SELECT 7", &()).await?;
+    assert_eq!(value, 7);
+    Ok(())
+}
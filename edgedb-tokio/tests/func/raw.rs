@@ -1,7 +1,7 @@
 use bytes::Bytes;
 
-use edgedb_tokio::raw::Pool;
-use edgedb_protocol::common::{CompliationOptions, IoFormat, Cardinality};
+use edgedb_tokio::raw::{Pool, ServerKeyData, SessionState};
+use edgedb_protocol::common::{CompilationOptions, IoFormat, Cardinality};
 use edgedb_protocol::common::{Capabilities};
 
 use crate::server::SERVER;
@@ -11,7 +11,7 @@ async fn poll_connect() -> anyhow::Result<()> {
     let pool = Pool::new(&SERVER.config);
     let mut conn = pool.acquire().await?;
     assert!(conn.is_consistent());
-    let _prepare = conn.prepare(&CompliationOptions {
+    let options = CompilationOptions {
         implicit_limit: None,
         implicit_typenames: false,
         implicit_typeids: false,
@@ -19,14 +19,25 @@ async fn poll_connect() -> anyhow::Result<()> {
         explicit_objectids: true,
         io_format: IoFormat::Binary,
         expected_cardinality: Cardinality::Many,
-    }, "SELECT 7*8").await;
+    };
+    let state = SessionState::default();
+    let desc = conn.parse(&options, "SELECT 7*8", &state).await?;
     assert!(conn.is_consistent());
-    let _descr = conn.describe_data().await?;
-    assert!(conn.is_consistent());
-    let data = conn.execute(&Bytes::new()).await?;
+    let data = conn.execute(&options, "SELECT 7*8", &state,
+                            &desc, &Bytes::new()).await?;
     assert!(conn.is_consistent());
     assert_eq!(data.len(), 1);
     assert_eq!(data[0].data.len(), 1);
     assert_eq!(&data[0].data[0][..], b"\0\0\0\0\0\0\0\x38");
     Ok(())
 }
+
+#[tokio::test]
+async fn server_key_data() -> anyhow::Result<()> {
+    let pool = Pool::new(&SERVER.config);
+    let conn = pool.acquire().await?;
+    assert!(conn.server_key_data().is_some());
+    assert_eq!(format!("{:?}", conn.get_param::<ServerKeyData>()),
+               "Some(ServerKeyData(..))");
+    Ok(())
+}
@@ -3,6 +3,7 @@ use std::sync::atomic::{AtomicUsize, AtomicBool, Ordering};
 
 use tokio::sync::{Mutex};
 
+use edgedb_errors::InterfaceError;
 use edgedb_tokio::{Client, Transaction};
 
 use crate::server::SERVER;
@@ -141,3 +142,19 @@ async fn transaction_conflict_with_complex_err() -> anyhow::Result<()> {
     assert_eq!(iters.load(Ordering::SeqCst), 3);
     Ok(())
 }
+
+#[tokio::test]
+async fn client_query_inside_transaction() -> anyhow::Result<()> {
+    let client = Client::new(&SERVER.config);
+    let res = client.clone().transaction(|mut tx| {
+        let client = client.clone();
+        async move {
+            tx.query::<i64, _>("SELECT 1", &()).await?;
+            client.query::<i64, _>("SELECT 2", &()).await
+        }
+    }).await;
+    assert!(res.unwrap_err().is::<InterfaceError>());
+    // client is usable outside of the transaction
+    client.query::<i64, _>("SELECT 3", &()).await?;
+    Ok(())
+}